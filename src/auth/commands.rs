@@ -10,6 +10,7 @@ pub type ServerGuid = [u64; 2];
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ServerCommand {
+    AgreeUnixFd,
     Data(Vec<u8>),
     Error,
     Ok { server_guid: ServerGuid },
@@ -30,6 +31,7 @@ pub enum ClientCommand {
     Cancel,
     Data(Cow<'static, [u8]>),
     Error(Option<Cow<'static, [u8]>>),
+    NegotiateUnixFd,
     Raw {
         cmd: Cow<'static, [u8]>,
         payload: Option<Cow<'static, [u8]>>,
@@ -80,6 +82,7 @@ pub fn encode_client_cmd(cmd: &ClientCommand, output: &mut Vec<u8>) {
         }
         ClientCommand::Begin => output.extend_from_slice(b"BEGIN\r\n"),
         ClientCommand::Cancel => output.extend_from_slice(b"CANCEL\r\n"),
+        ClientCommand::NegotiateUnixFd => output.extend_from_slice(b"NEGOTIATE_UNIX_FD\r\n"),
         ClientCommand::Data(ref bytes) => {
             output.reserve_exact(5 + hex_encoded_len(&bytes) + 2);
 
@@ -149,6 +152,7 @@ macro_rules! hex_uint(
 named!(parse_server_cmd(&[u8]) -> ServerCommand,
     do_parse!(
         cmd: alt!(
+            parse_server_cmd_agree_unix_fd |
             parse_server_cmd_data |
             parse_server_cmd_error |
             parse_server_cmd_ok |
@@ -160,6 +164,10 @@ named!(parse_server_cmd(&[u8]) -> ServerCommand,
     )
 );
 
+named!(parse_server_cmd_agree_unix_fd(&[u8]) -> ServerCommand,
+    value!(ServerCommand::AgreeUnixFd, tag!(b"AGREE_UNIX_FD"))
+);
+
 named!(parse_server_cmd_data(&[u8]) -> ServerCommand,
     do_parse!(
         tag!(b"DATA ") >>
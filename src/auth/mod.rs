@@ -9,4 +9,4 @@ pub mod strategies;
 pub use auth::client::Authenticator;
 pub use auth::commands::{ClientCommand, ServerCommand, ServerGuid, decode_server_cmd,
                          encode_client_cmd};
-pub use auth::strategies::{AuthError, auth_external};
+pub use auth::strategies::{AuthError, auth_external, negotiate_unix_fd};
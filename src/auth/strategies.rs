@@ -95,3 +95,26 @@ pub fn auth_external
             })
     })
 }
+
+/// Asks the server, after a successful authentication, whether Unix file
+/// descriptors may be passed over the connection. Resolves to `true` if the
+/// server agrees.
+pub fn negotiate_unix_fd
+    (auth: Authenticator)
+     -> impl Future<Item = (bool, Authenticator), Error = (AuthError, Option<Authenticator>)> {
+    auth.send(ClientCommand::NegotiateUnixFd)
+        .map_err(|err| (err.into(), None))
+        .and_then(|auth| auth.into_future().map_err(|(err, auth)| (err.into(), Some(auth))))
+        .and_then(|(response, auth)| {
+            match response {
+                Some(ServerCommand::AgreeUnixFd) => Ok((true, auth)),
+                Some(_) => Ok((false, auth)),
+                None => {
+                    Err((Error::new(ErrorKind::UnexpectedEof,
+                                    "unexpected EOF during authentication")
+                             .into(),
+                         Some(auth)))
+                }
+            }
+        })
+}
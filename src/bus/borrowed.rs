@@ -0,0 +1,111 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use bus::types::{Type, validate_signature};
+use bus::wire::{BasicValue, ContainerValue, Value};
+
+/// A value that borrows its strings, object paths, signatures and byte arrays
+/// from the buffer it was decoded from, so decoding it doesn't allocate for
+/// them. Use `into_owned` to detach it from the buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValueRef<'a> {
+    BasicValue(BasicValueRef<'a>),
+    ContainerValue(ContainerValueRef<'a>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BasicValueRef<'a> {
+    Byte(u8),
+    Bool(bool),
+    Int16(i16),
+    UInt16(u16),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Double(f64),
+    String(&'a str),
+    ObjectPath(&'a str),
+    /// A signature that has already been validated.
+    Signature(&'a str),
+    /// An index into the descriptors attached to the message.
+    UnixFd(u32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ContainerValueRef<'a> {
+    Array(Vec<ValueRef<'a>>),
+    /// An array of bytes (`ay`), kept as a single slice.
+    ByteArray(&'a [u8]),
+    Struct(Vec<ValueRef<'a>>),
+    /// The contents, with the type the signature ahead of them gave.
+    Variant(Type, Box<ValueRef<'a>>),
+    Dict(Vec<(BasicValueRef<'a>, ValueRef<'a>)>),
+}
+
+impl<'a> ValueRef<'a> {
+    pub fn into_owned(self) -> Value {
+        match self {
+            ValueRef::BasicValue(basic) => Value::BasicValue(basic.into_owned()),
+            ValueRef::ContainerValue(container) => Value::ContainerValue(container.into_owned()),
+        }
+    }
+}
+
+impl<'a> BasicValueRef<'a> {
+    pub fn into_owned(self) -> BasicValue {
+        match self {
+            BasicValueRef::Byte(n) => BasicValue::Byte(n),
+            BasicValueRef::Bool(b) => BasicValue::Bool(b),
+            BasicValueRef::Int16(n) => BasicValue::Int16(n),
+            BasicValueRef::UInt16(n) => BasicValue::UInt16(n),
+            BasicValueRef::Int32(n) => BasicValue::Int32(n),
+            BasicValueRef::UInt32(n) => BasicValue::UInt32(n),
+            BasicValueRef::Int64(n) => BasicValue::Int64(n),
+            BasicValueRef::UInt64(n) => BasicValue::UInt64(n),
+            BasicValueRef::Double(n) => BasicValue::Double(n),
+            BasicValueRef::String(s) => BasicValue::String(s.to_owned().into()),
+            BasicValueRef::ObjectPath(path) => {
+                BasicValue::ObjectPath(path.as_bytes().to_vec().into())
+            }
+            BasicValueRef::Signature(signature) => {
+                // Signatures are validated when they're decoded.
+                BasicValue::Signature(validate_signature(signature.as_bytes()).unwrap())
+            }
+            BasicValueRef::UnixFd(index) => BasicValue::UnixFd(index),
+        }
+    }
+}
+
+impl<'a> ContainerValueRef<'a> {
+    pub fn into_owned(self) -> ContainerValue {
+        match self {
+            ContainerValueRef::Array(elems) => {
+                ContainerValue::Array(elems.into_iter().map(ValueRef::into_owned).collect())
+            }
+            ContainerValueRef::ByteArray(bytes) => {
+                ContainerValue::Array(bytes.iter()
+                    .map(|&b| Value::BasicValue(BasicValue::Byte(b)))
+                    .collect())
+            }
+            ContainerValueRef::Struct(fields) => {
+                ContainerValue::Struct(fields.into_iter().map(ValueRef::into_owned).collect())
+            }
+            ContainerValueRef::Variant(inner_ty, inner) => {
+                ContainerValue::Variant(inner_ty, Box::new(inner.into_owned()))
+            }
+            ContainerValueRef::Dict(entries) => {
+                ContainerValue::Dict(entries.into_iter()
+                    .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                    .collect())
+            }
+        }
+    }
+}
+
+impl<'a> From<ValueRef<'a>> for Value {
+    fn from(value: ValueRef<'a>) -> Self {
+        value.into_owned()
+    }
+}
@@ -2,20 +2,116 @@
 // v. 2.0. If a copy of the MPL was not distributed with this file, You can
 // obtain one at http://mozilla.org/MPL/2.0/.
 
-use futures::Future;
-use std::io::Result;
+use futures::{future, Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+use futures::sync::{mpsc, oneshot};
+use futures::task::AtomicTask;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::{Error, ErrorKind, Result};
+use std::mem;
 use std::net::Shutdown;
 use std::path::Path;
-use tokio_core::reactor::Handle;
+use std::rc::Rc;
+use std::result;
+use std::time::Duration;
+use tokio_core::reactor::{Handle, Timeout};
 use tokio_uds::UnixStream;
 
 use auth::{Authenticator, AuthError, ServerGuid};
+use bus::convert::FromDBus;
+use bus::error::{DBusError, StandardError};
+use bus::message::{Limits, Message, MessageType, NO_REPLY_EXPECTED};
+use bus::names::{BusName, ObjectPath};
+use bus::rule::MatchRule;
+use bus::server::{self, MethodResult, ObjectServer};
+use bus::transport::Transport;
+use bus::types::Signature;
+use bus::variant::Variant;
+use bus::wire::Value;
+
+const BUS_NAME: &'static str = "org.freedesktop.DBus";
+const BUS_PATH: &'static str = "/org/freedesktop/DBus";
+const BUS_INTERFACE: &'static str = "org.freedesktop.DBus";
+// Where libdbus reports a lost connection from.
+const LOCAL_PATH: &'static str = "/org/freedesktop/DBus/Local";
+const LOCAL_INTERFACE: &'static str = "org.freedesktop.DBus.Local";
+
+/// How long a call waits for its reply unless told otherwise, as in libdbus.
+pub const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(25);
+
+/// For `Bus::request_name`: let another connection take the name over.
+pub const NAME_ALLOW_REPLACEMENT: u32 = 0x1;
+/// For `Bus::request_name`: take the name over from its owner, if it allows.
+pub const NAME_REPLACE_EXISTING: u32 = 0x2;
+/// For `Bus::request_name`: fail rather than wait in the name's queue.
+pub const NAME_DO_NOT_QUEUE: u32 = 0x4;
+
+// How many calls given up on to remember, so their replies are thrown away
+// if they still come. Past this, replies to the oldest come out of the stream
+// like any other message, rather than a peer that never answers using up
+// more and more memory.
+const MAX_ABANDONED_CALLS: usize = 1024;
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
 
 pub struct Bus {
-    inner: UnixStream,
+    inner: Transport,
+    next_serial: u32,
+    unique_name: Option<BusName>,
+    // Messages that arrived before the reply to `Hello`, to be yielded first.
+    queued: VecDeque<Message>,
+    // Method calls waiting for their turn to be written.
+    outgoing: VecDeque<Message>,
+    // Where to deliver the replies to calls in flight, by serial.
+    pending: HashMap<u32, oneshot::Sender<Message>>,
+    // Calls given up on, whose replies are thrown away if they still come,
+    // and the order they were given up in, to forget the oldest first.
+    abandoned: HashSet<u32>,
+    abandoned_order: VecDeque<u32>,
+    handle: Option<Handle>,
+    call_timeout: Option<Duration>,
+    // Signal subscribers, by the text of their rule.
+    subscriptions: HashMap<String, Subscription>,
+    next_subscriber: u64,
+    objects: ObjectServer,
+    // Whether a driver answers calls on exported objects, as it can't run
+    // their handlers until it stops borrowing the connection.
+    shared: bool,
+    // Calls on exported objects waiting for the driver.
+    calls: VecDeque<Message>,
+    // Well-known names asked for, with their flags, to ask for again after
+    // reconnecting.
+    names: BTreeMap<BusName, u32>,
+    disconnected: bool,
+    // Whether a `Supervisor` reconnects when the connection is lost.
+    supervised: bool,
+    // Set by `BusHandle::close` for the driver.
+    closing: bool,
+    // Waiting for the connection to be gone.
+    closed: Vec<oneshot::Sender<()>>,
+    // Waiting to hear of the connection being replaced by a supervisor.
+    reconnects: Vec<mpsc::UnboundedSender<Reconnected>>,
+    // Where dropped `PendingCall`s and `SignalStream`s say so.
+    dropped_tx: mpsc::UnboundedSender<Dropped>,
+    dropped_rx: mpsc::UnboundedReceiver<Dropped>,
+}
+
+enum Dropped {
+    Call(u32),
+    Subscriber(String, u64),
+}
+
+// The subscribers sharing one rule on the bus.
+struct Subscription {
+    rule: MatchRule,
+    subscribers: Vec<(u64, mpsc::UnboundedSender<Result<Message>>)>,
 }
 
 impl Bus {
+    /// Connects to a message bus, authenticates with `auth_strategy`, and
+    /// says `Hello` to the bus to get a unique name.
     pub fn connect<P, F, T>
         (path: P,
          handle: &Handle,
@@ -26,25 +122,1266 @@ impl Bus {
               T: Future<Item = (ServerGuid, Authenticator),
                         Error = (AuthError, Option<Authenticator>)>
     {
-        Authenticator::connect(path, handle)
+        Bus::connect_peer(path, handle, auth_strategy).and_then(|(server_guid, bus)| {
+            bus.hello()
+                .map(move |bus| (server_guid, bus))
+                .map_err(|err| (err.into(), None))
+        })
+    }
+
+    /// Like `connect`, but without the `Hello`, for a peer-to-peer connection
+    /// rather than one to a message bus.
+    pub fn connect_peer<P, F, T>
+        (path: P,
+         handle: &Handle,
+         auth_strategy: F)
+         -> impl Future<Item = (ServerGuid, Self), Error = (AuthError, Option<Authenticator>)>
+        where P: AsRef<Path>,
+              F: FnOnce(Authenticator) -> T,
+              T: Future<Item = (ServerGuid, Authenticator),
+                        Error = (AuthError, Option<Authenticator>)>
+    {
+        let handle = handle.clone();
+        Authenticator::connect(path, &handle)
             .map_err(|err| (err.into(), None))
             .and_then(|auth| auth_strategy(auth))
-            .and_then(|(server_guid, auth)| {
+            .and_then(move |(server_guid, auth)| {
                 auth.begin()
-                    .map(move |bus| (server_guid, bus))
+                    .map(move |mut bus| {
+                        bus.set_handle(&handle);
+                        (server_guid, bus)
+                    })
                     .map_err(|err| (err.into(), None))
             })
     }
 
     pub fn new(inner: UnixStream) -> Self {
-        Bus { inner: inner }
+        Bus::with_limits(inner, Limits::default())
+    }
+
+    /// Wraps a connection whose incoming messages are held to `limits`.
+    pub fn with_limits(inner: UnixStream, limits: Limits) -> Self {
+        let (dropped_tx, dropped_rx) = mpsc::unbounded();
+        Bus {
+            inner: Transport::new(inner, limits),
+            next_serial: 1,
+            unique_name: None,
+            queued: VecDeque::new(),
+            outgoing: VecDeque::new(),
+            pending: HashMap::new(),
+            abandoned: HashSet::new(),
+            abandoned_order: VecDeque::new(),
+            handle: None,
+            call_timeout: Some(DEFAULT_CALL_TIMEOUT),
+            subscriptions: HashMap::new(),
+            next_subscriber: 0,
+            objects: ObjectServer::new(),
+            shared: false,
+            calls: VecDeque::new(),
+            names: BTreeMap::new(),
+            disconnected: false,
+            supervised: false,
+            closing: false,
+            closed: Vec::new(),
+            reconnects: Vec::new(),
+            dropped_tx: dropped_tx,
+            dropped_rx: dropped_rx,
+        }
+    }
+
+    /// Calls the bus's `Hello` method, which it requires before any other,
+    /// and records the unique name it assigns to this connection. `connect`
+    /// does this already.
+    pub fn hello(mut self) -> impl Future<Item = Self, Error = Error> {
+        let mut msg = Message::method_call(BUS_PATH, "Hello");
+        msg.fields.interface = Some(BUS_INTERFACE.to_owned());
+        msg.fields.destination = Some(BUS_NAME.to_owned());
+        msg.serial = self.next_serial();
+        let serial = msg.serial;
+        self.send(msg).and_then(move |bus| {
+            HelloReply {
+                bus: Some(bus),
+                serial: serial,
+            }
+        })
+    }
+
+    /// The name the bus assigned this connection, such as `:1.42`, once it
+    /// has said `Hello`.
+    pub fn unique_name(&self) -> Option<&BusName> {
+        self.unique_name.as_ref()
+    }
+
+    /// Calls a method, returning a future of the reply's body.
+    ///
+    /// The call is written, and its reply picked out of the incoming
+    /// messages, as the connection is polled as a stream; other messages
+    /// come out of the stream as usual. Any number of calls can be in flight
+    /// at once.
+    ///
+    /// The arguments are `Variant`s only so that their types are known even
+    /// where their values don't show them, as with an empty array; each is
+    /// marshalled as its contents.
+    ///
+    /// Calls only time out on a connection with a reactor `Handle`, as from
+    /// `connect`, `spawn` or `set_handle`. One made with `Bus::new` has
+    /// none, so its calls wait for their replies however long they take.
+    pub fn call<D, P, I, M>(&mut self,
+                            destination: D,
+                            path: P,
+                            interface: I,
+                            member: M,
+                            args: &[Variant])
+                            -> PendingCall
+        where D: Into<String>,
+              P: Into<String>,
+              I: Into<String>,
+              M: Into<String>
+    {
+        let mut msg = Message::method_call(path, member);
+        msg.fields.interface = Some(interface.into());
+        msg.fields.destination = Some(destination.into());
+        match set_args(&mut msg, args) {
+            Ok(()) => self.call_message(msg),
+            Err(err) => PendingCall::failed(err.into()),
+        }
+    }
+
+    /// Calls a method without asking for a reply, setting
+    /// `NO_REPLY_EXPECTED`, and returns the call's serial. The call is
+    /// written as the connection is polled.
+    pub fn call_no_reply<D, P, I, M>(&mut self,
+                                     destination: D,
+                                     path: P,
+                                     interface: I,
+                                     member: M,
+                                     args: &[Variant])
+                                     -> Result<u32>
+        where D: Into<String>,
+              P: Into<String>,
+              I: Into<String>,
+              M: Into<String>
+    {
+        let mut msg = Message::method_call(path, member);
+        msg.fields.interface = Some(interface.into());
+        msg.fields.destination = Some(destination.into());
+        msg.set_flag(NO_REPLY_EXPECTED, true);
+        set_args(&mut msg, args)?;
+        Ok(self.queue(msg))
+    }
+
+    /// Like `call`, for a method call message built by hand. If it has
+    /// `NO_REPLY_EXPECTED` set, it's queued as `call_no_reply` would, and the
+    /// returned call resolves at once with an empty body.
+    pub fn call_message(&mut self, mut msg: Message) -> PendingCall {
+        if self.disconnected {
+            return PendingCall::failed(disconnected_error());
+        }
+        if msg.has_flag(NO_REPLY_EXPECTED) {
+            self.queue(msg);
+            return PendingCall::done(Ok(Vec::new()));
+        }
+        if msg.serial == 0 {
+            msg.serial = self.next_serial();
+        }
+        let (sender, receiver) = oneshot::channel();
+        self.pending.insert(msg.serial, sender);
+        let call = PendingCall {
+            state: CallState::Waiting(receiver),
+            serial: Some(msg.serial),
+            timer: None,
+            handle: self.handle.clone(),
+            dropped: Some(self.dropped_tx.clone()),
+        };
+        self.outgoing.push_back(msg);
+        call.timeout(self.call_timeout)
+    }
+
+    /// Gives the connection the reactor it runs on, for timing out calls.
+    /// `connect` and `connect_peer` do this already; without it, calls wait
+    /// for their replies however long they take.
+    pub fn set_handle(&mut self, handle: &Handle) {
+        self.handle = Some(handle.clone());
+    }
+
+    /// Changes how long calls made from now on wait for their replies, by
+    /// default `DEFAULT_CALL_TIMEOUT`. `None` waits forever.
+    pub fn set_call_timeout(&mut self, timeout: Option<Duration>) {
+        self.call_timeout = timeout;
+    }
+
+    /// Subscribes to the signals that match `rule`, which is added to the
+    /// bus with `AddMatch` until the returned stream is dropped.
+    ///
+    /// Subscriptions with the same rule share it on the bus. Signals are
+    /// delivered, as the connection is polled as a stream, to every
+    /// subscription they match, and only come out of the connection's own
+    /// stream if they match none. On a bus, a well-known `sender` in a rule
+    /// is left to the bus to check, as `MatchRule::matches_routed` says.
+    pub fn subscribe(&mut self, rule: MatchRule) -> SignalStream {
+        let rule = rule.message_type(MessageType::Signal);
+        let key = rule.to_string();
+        let id = self.next_subscriber;
+        self.next_subscriber += 1;
+        let (sender, receiver) = mpsc::unbounded();
+        // A supervised connection adds the rule again once it's back.
+        if self.disconnected && !self.supervised {
+            // Dropping the sender ends the stream.
+            return SignalStream {
+                key: key,
+                id: id,
+                add_match: None,
+                receiver: receiver,
+                dropped: self.dropped_tx.clone(),
+            };
+        }
+        let add_match = if self.subscriptions.contains_key(&key) {
+            None
+        } else {
+            let add_match = self.bus_call("AddMatch", &key);
+            self.subscriptions.insert(key.clone(),
+                                      Subscription {
+                                          rule: rule,
+                                          subscribers: Vec::new(),
+                                      });
+            Some(add_match)
+        };
+        self.subscriptions.get_mut(&key).unwrap().subscribers.push((id, sender));
+        SignalStream {
+            key: key,
+            id: id,
+            add_match: add_match,
+            receiver: receiver,
+            dropped: self.dropped_tx.clone(),
+        }
+    }
+
+    /// Exports an object at `path`, whose handler answers the method calls
+    /// made on it as the connection is polled. Its replies honour
+    /// `NO_REPLY_EXPECTED`. Calls on other paths come out of the
+    /// connection's stream.
+    pub fn export<F>(&mut self, path: ObjectPath, handler: F)
+        where F: FnMut(&Message) -> MethodResult + 'static
+    {
+        self.objects.export(path, handler);
+    }
+
+    /// Stops exporting the object at `path`, returning whether there was one.
+    pub fn unexport(&mut self, path: &str) -> bool {
+        self.objects.unexport(path)
+    }
+
+    /// Asks the bus for the well-known name `name`, with flags such as
+    /// `NAME_DO_NOT_QUEUE`. The call resolves to the bus's reply code, 1 if
+    /// this connection is now the name's primary owner.
+    ///
+    /// A supervised connection asks again after reconnecting, until the name
+    /// is released.
+    pub fn request_name(&mut self, name: BusName, flags: u32) -> PendingCall {
+        let call = self.name_call(&name, flags);
+        self.names.insert(name, flags);
+        call
+    }
+
+    /// Gives up the well-known name `name`. The call resolves to the bus's
+    /// reply code, 1 if the name was released.
+    pub fn release_name(&mut self, name: &BusName) -> PendingCall {
+        self.names.remove(name);
+        self.bus_call("ReleaseName", name)
+    }
+
+    /// Changes the limits that incoming messages are held to.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.inner.set_limits(limits);
     }
 
     pub fn into_inner(self) -> UnixStream {
-        self.inner
+        self.inner.into_inner()
     }
 
+    /// Shuts the connection down at once, losing anything not yet written.
     pub fn disconnect(self) -> Result<()> {
         self.into_inner().shutdown(Shutdown::Both)
     }
+
+    /// Shuts the connection down once everything queued, calls included,
+    /// has been written.
+    pub fn close(mut self) -> impl Future<Item = (), Error = Error> {
+        future::poll_fn(move || {
+            try_ready!(self.write_queued());
+            self.lost_connection();
+            self.inner.shutdown(Shutdown::Both).map(Async::Ready)
+        })
+    }
+
+    /// Whether the connection is still up, as far as is known. It goes down
+    /// when the peer closes it, on an I/O error, or on libdbus's
+    /// `org.freedesktop.DBus.Local.Disconnected` signal; calls in flight
+    /// then fail with `Disconnected`, and signal streams end.
+    pub fn is_connected(&self) -> bool {
+        !self.disconnected
+    }
+
+    /// A future that resolves once the connection is gone, or the `Bus`
+    /// has been dropped.
+    pub fn closed(&mut self) -> Closed {
+        let (sender, receiver) = oneshot::channel();
+        if !self.disconnected {
+            self.closed.push(sender);
+        }
+        Closed { receiver: receiver }
+    }
+
+    /// A stream of the times a `Supervisor` has replaced the connection,
+    /// with the unique names before and after. It ends once the connection
+    /// is gone for good.
+    pub fn reconnects(&mut self) -> Reconnects {
+        let (sender, receiver) = mpsc::unbounded();
+        // Dropping the sender ends the stream.
+        if !self.disconnected || self.supervised {
+            self.reconnects.push(sender);
+        }
+        Reconnects { receiver: receiver }
+    }
+
+    /// Splits the connection into a handle that can be cloned and used from
+    /// many places at once, and the driver that does its I/O, which must be
+    /// run for anything to happen.
+    pub fn into_shared(mut self) -> (BusHandle, BusDriver) {
+        self.shared = true;
+        let shared = Rc::new(Shared {
+            bus: RefCell::new(self),
+            driver: AtomicTask::new(),
+        });
+        (BusHandle { shared: shared.clone() }, BusDriver { shared: shared })
+    }
+
+    /// Like `into_shared`, spawning the driver on `handle`, which is also
+    /// used to time out calls.
+    pub fn spawn(mut self, handle: &Handle) -> BusHandle {
+        if self.handle.is_none() {
+            self.set_handle(handle);
+        }
+        let (bus, driver) = self.into_shared();
+        handle.spawn(driver.map_err(|_| ()));
+        bus
+    }
+
+    /// Like `into_shared`, with a `Supervisor` in place of the driver, which
+    /// reconnects to the bus at `path`, authenticating with
+    /// `auth_strategy`, whenever the connection is lost.
+    pub fn supervise<P, F, T>(self,
+                              path: P,
+                              handle: &Handle,
+                              auth_strategy: F)
+                              -> (BusHandle, Supervisor)
+        where P: AsRef<Path>,
+              F: FnMut(Authenticator) -> T + 'static,
+              T: Future<Item = (ServerGuid, Authenticator),
+                        Error = (AuthError, Option<Authenticator>)> + 'static
+    {
+        let path = path.as_ref().to_owned();
+        let reconnect_handle = handle.clone();
+        let auth_strategy = Rc::new(RefCell::new(auth_strategy));
+        Supervisor::new(self, handle, move || {
+            let auth_strategy = auth_strategy.clone();
+            Bus::connect_peer(path.clone(),
+                              &reconnect_handle,
+                              move |auth| (*auth_strategy.borrow_mut())(auth))
+                .map(|(_, bus)| bus)
+                .map_err(|(err, _)| err.into())
+        })
+    }
+
+    fn next_serial(&mut self) -> u32 {
+        let serial = self.next_serial;
+        // Serials must never be zero.
+        self.next_serial = self.next_serial.wrapping_add(1);
+        if self.next_serial == 0 {
+            self.next_serial = 1;
+        }
+        serial
+    }
+
+    // Queues `msg` to be written with the calls, returning its serial.
+    fn queue(&mut self, mut msg: Message) -> u32 {
+        if msg.serial == 0 {
+            msg.serial = self.next_serial();
+        }
+        let serial = msg.serial;
+        self.outgoing.push_back(msg);
+        serial
+    }
+
+    fn lost_connection(&mut self) {
+        self.disconnected = true;
+        // Dropping these fails the calls and ends the streams.
+        self.pending.clear();
+        self.abandoned.clear();
+        self.abandoned_order.clear();
+        self.outgoing.clear();
+        // There's no one left to answer these calls to.
+        self.calls.clear();
+        if self.supervised && !self.closing {
+            // The streams carry on once the supervisor has reconnected.
+            return;
+        }
+        self.subscriptions.clear();
+        self.reconnects.clear();
+        for closed in self.closed.drain(..) {
+            let _ = closed.send(());
+        }
+    }
+
+    // Whether nothing is left to write, wait for or answer: no calls or
+    // subscriptions, and no exported objects or well-known names for others
+    // to call on.
+    fn is_idle(&self) -> bool {
+        self.outgoing.is_empty() && self.pending.is_empty() && self.subscriptions.is_empty() &&
+        self.objects.is_empty() && self.names.is_empty() && self.calls.is_empty()
+    }
+
+    fn bus_call(&mut self, member: &str, arg: &str) -> PendingCall {
+        self.call(BUS_NAME, BUS_PATH, BUS_INTERFACE, member, &[Variant::new(arg)])
+    }
+
+    fn name_call(&mut self, name: &BusName, flags: u32) -> PendingCall {
+        self.call(BUS_NAME,
+                  BUS_PATH,
+                  BUS_INTERFACE,
+                  "RequestName",
+                  &[Variant::new(name.as_str()), Variant::new(flags)])
+    }
+
+    // Takes over the connection a supervisor made in place of the lost one,
+    // and restores the match rules and names the bus knew of, telling
+    // whoever asked with `reconnects` the new unique name.
+    fn reconnected(&mut self, mut fresh: Bus) {
+        fresh.inner.set_limits(self.inner.limits());
+        self.inner = fresh.inner;
+        self.queued = fresh.queued;
+        let old_name = mem::replace(&mut self.unique_name, fresh.unique_name);
+        self.disconnected = false;
+
+        // Nothing is left to tell if these fail.
+        let rules = self.subscriptions.keys().cloned().collect::<Vec<_>>();
+        for rule in rules {
+            self.bus_call("AddMatch", &rule);
+        }
+        let names = self.names.clone();
+        for (name, flags) in names {
+            self.name_call(&name, flags);
+        }
+
+        let event = Reconnected {
+            old_name: old_name,
+            new_name: self.unique_name.clone(),
+        };
+        // Streams that were dropped are forgotten.
+        self.reconnects.retain(|sender| sender.unbounded_send(event.clone()).is_ok());
+    }
+
+    // Forgets the calls and subscribers that were dropped, removing rules
+    // that none are left for from the bus.
+    fn forget_dropped(&mut self) {
+        while let Ok(Async::Ready(Some(dropped))) = self.dropped_rx.poll() {
+            let (key, id) = match dropped {
+                Dropped::Call(serial) => {
+                    if self.pending.remove(&serial).is_some() {
+                        self.abandon(serial);
+                    }
+                    continue;
+                }
+                Dropped::Subscriber(key, id) => (key, id),
+            };
+            let unused = match self.subscriptions.get_mut(&key) {
+                Some(subscription) => {
+                    subscription.subscribers.retain(|&(other, _)| other != id);
+                    subscription.subscribers.is_empty()
+                }
+                None => false,
+            };
+            if unused {
+                self.subscriptions.remove(&key);
+                // Nothing is left to tell if this fails.
+                self.bus_call("RemoveMatch", &key);
+            }
+        }
+    }
+
+    fn abandon(&mut self, serial: u32) {
+        self.abandoned.insert(serial);
+        self.abandoned_order.push_back(serial);
+        while self.abandoned_order.len() > MAX_ABANDONED_CALLS {
+            let oldest = self.abandoned_order.pop_front().unwrap();
+            self.abandoned.remove(&oldest);
+        }
+    }
+
+    // Hands queued calls to the transport and writes out what it holds.
+    fn write_queued(&mut self) -> Poll<(), Error> {
+        while let Some(msg) = self.outgoing.pop_front() {
+            if let AsyncSink::NotReady(msg) = self.inner.start_send(msg)? {
+                self.outgoing.push_front(msg);
+                break;
+            }
+        }
+        let flushed = self.inner.poll_complete()?;
+        if self.outgoing.is_empty() {
+            Ok(flushed)
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+
+    // Delivers `msg` to the call it replies to, the subscriptions it
+    // matches or the object it calls, or returns it if there are none.
+    fn dispatch(&mut self, msg: Message) -> Option<Message> {
+        match msg.message_type {
+            MessageType::Signal => return self.fan_out(msg),
+            MessageType::MethodCall => {
+                let exported = msg.fields
+                    .path
+                    .as_ref()
+                    .map_or(false, |path| self.objects.is_exported(path));
+                if exported {
+                    if self.shared {
+                        self.calls.push_back(msg);
+                    } else {
+                        self.answer(&msg);
+                    }
+                    return None;
+                }
+                return Some(msg);
+            }
+            _ => {}
+        }
+        let is_reply = msg.message_type == MessageType::MethodReturn ||
+                       msg.message_type == MessageType::Error;
+        let sender = match msg.fields.reply_serial {
+            Some(serial) if is_reply => {
+                if self.abandoned.remove(&serial) {
+                    return None;
+                }
+                self.pending.remove(&serial)
+            }
+            _ => None,
+        };
+        match sender {
+            // The caller may have lost interest in the reply.
+            Some(sender) => {
+                let _ = sender.send(msg);
+                None
+            }
+            None => Some(msg),
+        }
+    }
+
+    fn answer(&mut self, call: &Message) {
+        if let Some(reply) = self.objects.handle(call) {
+            self.queue(reply);
+        }
+    }
+
+    fn fan_out(&mut self, msg: Message) -> Option<Message> {
+        // On a bus, senders are always unique names, so rules naming a
+        // well-known sender are left to the bus to check.
+        let routed = self.unique_name.is_some();
+        let senders = self.subscriptions
+            .values()
+            .filter(|subscription| if routed {
+                subscription.rule.matches_routed(&msg)
+            } else {
+                subscription.rule.matches(&msg)
+            })
+            .flat_map(|subscription| subscription.subscribers.iter().map(|&(_, ref tx)| tx))
+            .collect::<Vec<_>>();
+        match senders.split_last() {
+            // Subscribers that went away are cleaned up by `unsubscribe`.
+            Some((last, others)) => {
+                for sender in others {
+                    let _ = sender.unbounded_send(msg.try_clone());
+                }
+                let _ = last.unbounded_send(Ok(msg));
+                None
+            }
+            None => Some(msg),
+        }
+    }
+}
+
+struct Shared {
+    bus: RefCell<Bus>,
+    // The task running the `BusDriver`, to wake when there's work.
+    driver: AtomicTask,
+}
+
+/// A handle to a connection shared with a `BusDriver`, made by
+/// `Bus::into_shared` or `Bus::spawn`. Clones of it can all make calls,
+/// subscribe to signals and send messages at the same time.
+#[derive(Clone)]
+pub struct BusHandle {
+    shared: Rc<Shared>,
+}
+
+impl BusHandle {
+    /// Like `Bus::call`.
+    pub fn call<D, P, I, M>(&self,
+                            destination: D,
+                            path: P,
+                            interface: I,
+                            member: M,
+                            args: &[Variant])
+                            -> PendingCall
+        where D: Into<String>,
+              P: Into<String>,
+              I: Into<String>,
+              M: Into<String>
+    {
+        self.with_bus(|bus| bus.call(destination, path, interface, member, args))
+    }
+
+    /// Like `Bus::call_message`.
+    pub fn call_message(&self, msg: Message) -> PendingCall {
+        self.with_bus(|bus| bus.call_message(msg))
+    }
+
+    /// Like `Bus::subscribe`. As the driver owns the connection's stream,
+    /// signals that match no subscription are dropped.
+    pub fn subscribe(&self, rule: MatchRule) -> SignalStream {
+        self.with_bus(|bus| bus.subscribe(rule))
+    }
+
+    /// Like `Bus::call_no_reply`.
+    pub fn call_no_reply<D, P, I, M>(&self,
+                                     destination: D,
+                                     path: P,
+                                     interface: I,
+                                     member: M,
+                                     args: &[Variant])
+                                     -> Result<u32>
+        where D: Into<String>,
+              P: Into<String>,
+              I: Into<String>,
+              M: Into<String>
+    {
+        self.with_bus(|bus| bus.call_no_reply(destination, path, interface, member, args))
+    }
+
+    /// Queues `msg` to be sent, in order with any calls, and returns its
+    /// serial.
+    pub fn send(&self, msg: Message) -> u32 {
+        self.with_bus(|bus| bus.queue(msg))
+    }
+
+    /// Like `Bus::export`. Calls on paths with no object get an
+    /// `UnknownObject` error.
+    pub fn export<F>(&self, path: ObjectPath, handler: F)
+        where F: FnMut(&Message) -> MethodResult + 'static
+    {
+        self.shared.bus.borrow_mut().export(path, handler);
+    }
+
+    pub fn unexport(&self, path: &str) -> bool {
+        self.shared.bus.borrow_mut().unexport(path)
+    }
+
+    /// Like `Bus::request_name`.
+    pub fn request_name(&self, name: BusName, flags: u32) -> PendingCall {
+        self.with_bus(|bus| bus.request_name(name, flags))
+    }
+
+    /// Like `Bus::release_name`.
+    pub fn release_name(&self, name: &BusName) -> PendingCall {
+        self.with_bus(|bus| bus.release_name(name))
+    }
+
+    pub fn unique_name(&self) -> Option<BusName> {
+        self.shared.bus.borrow().unique_name.clone()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.bus.borrow().is_connected()
+    }
+
+    /// Like `Bus::closed`.
+    pub fn closed(&self) -> Closed {
+        self.shared.bus.borrow_mut().closed()
+    }
+
+    /// Has the driver shut the connection down once everything queued has
+    /// been written, and finish. Returns a future of when that's done.
+    pub fn close(&self) -> Closed {
+        self.with_bus(|bus| {
+            bus.closing = true;
+            bus.closed()
+        })
+    }
+
+    /// Like `Bus::reconnects`.
+    pub fn reconnects(&self) -> Reconnects {
+        self.shared.bus.borrow_mut().reconnects()
+    }
+
+    /// Like `Bus::set_call_timeout`, for every handle.
+    pub fn set_call_timeout(&self, timeout: Option<Duration>) {
+        self.shared.bus.borrow_mut().set_call_timeout(timeout);
+    }
+
+    fn with_bus<F, T>(&self, f: F) -> T
+        where F: FnOnce(&mut Bus) -> T
+    {
+        let result = f(&mut self.shared.bus.borrow_mut());
+        self.shared.driver.notify();
+        result
+    }
+}
+
+impl Drop for BusHandle {
+    fn drop(&mut self) {
+        // The driver may be done once no handles are left.
+        self.shared.driver.notify();
+    }
+}
+
+/// Does the I/O for a connection shared by `BusHandle`s, routing replies to
+/// calls, signals to subscriptions and method calls to exported objects;
+/// other incoming messages are dropped.
+/// It finishes when the connection closes, or once no handles, calls,
+/// subscriptions, exported objects or requested names are left and
+/// everything queued has been written. So a service that exports objects
+/// keeps answering after its last handle is dropped.
+///
+/// Handlers run while the driver isn't using the connection, so they can
+/// use handles to it to make calls or send signals.
+pub struct BusDriver {
+    shared: Rc<Shared>,
+}
+
+impl Future for BusDriver {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        self.shared.driver.register();
+        drive(&self.shared)
+    }
+}
+
+// Does the I/O for a shared connection, until it closes or is no longer
+// needed.
+fn drive(shared: &Rc<Shared>) -> Poll<(), Error> {
+    let mut bus = shared.bus.borrow_mut();
+    if bus.closing && !bus.disconnected {
+        bus.forget_dropped();
+        try_ready!(bus.write_queued());
+        bus.lost_connection();
+        bus.inner.shutdown(Shutdown::Both)?;
+        return Ok(Async::Ready(()));
+    }
+    loop {
+        match bus.poll()? {
+            Async::Ready(Some(msg)) => {
+                // There is no one else to answer calls.
+                bus.answer(&msg);
+                continue;
+            }
+            Async::Ready(None) => return Ok(Async::Ready(())),
+            Async::NotReady => {}
+        }
+        let call = match bus.calls.pop_front() {
+            Some(call) => call,
+            None => break,
+        };
+        // Handlers may use handles to the connection, so it isn't borrowed
+        // while they run. Their replies are written as it's polled again.
+        let handler = server::handler_for(&bus.objects, &call);
+        drop(bus);
+        let reply = server::run_handler(handler, &call);
+        bus = shared.bus.borrow_mut();
+        if let Some(reply) = reply {
+            bus.queue(reply);
+        }
+    }
+    if Rc::strong_count(shared) == 1 && bus.is_idle() {
+        return bus.inner.poll_complete();
+    }
+    Ok(Async::NotReady)
+}
+
+/// Like `BusDriver`, for a connection to a message bus that should survive
+/// the bus restarting; made by `Bus::supervise` or `Supervisor::new`.
+///
+/// When the connection is lost, it makes a new one, waiting longer after
+/// each failed attempt. It then says `Hello`, adds the rules of the signal
+/// subscriptions again and asks for the names from `Bus::request_name`;
+/// exported objects stay exported. Signal streams carry on, and
+/// `BusHandle::reconnects` tells of the new unique name. Calls in flight
+/// when the connection is lost, or made before it's back, fail with
+/// `Disconnected`.
+///
+/// It finishes when closed, or once nothing is left for it to do, as with
+/// `BusDriver`.
+pub struct Supervisor {
+    shared: Rc<Shared>,
+    handle: Handle,
+    reconnect: Box<FnMut() -> Box<Future<Item = Bus, Error = Error>>>,
+    min_delay: Duration,
+    max_delay: Duration,
+    // How long to wait before the next attempt.
+    delay: Duration,
+    state: Option<Reconnect>,
+}
+
+enum Reconnect {
+    Waiting(Timeout),
+    Connecting(Box<Future<Item = Bus, Error = Error>>),
+}
+
+impl Supervisor {
+    /// Shares `bus` like `Bus::into_shared`, with a supervisor that calls
+    /// `reconnect` for each new connection, which should be authenticated
+    /// but not yet have said `Hello`. Its timers run on `handle`.
+    pub fn new<F, T>(mut bus: Bus, handle: &Handle, mut reconnect: F) -> (BusHandle, Supervisor)
+        where F: FnMut() -> T + 'static,
+              T: Future<Item = Bus, Error = Error> + 'static
+    {
+        if bus.handle.is_none() {
+            bus.set_handle(handle);
+        }
+        bus.supervised = true;
+        let (bus, driver) = bus.into_shared();
+        let supervisor = Supervisor {
+            shared: driver.shared,
+            handle: handle.clone(),
+            reconnect: Box::new(move || Box::new(reconnect().and_then(|bus| bus.hello()))),
+            min_delay: MIN_RECONNECT_DELAY,
+            max_delay: MAX_RECONNECT_DELAY,
+            delay: MIN_RECONNECT_DELAY,
+            state: None,
+        };
+        (bus, supervisor)
+    }
+
+    /// Changes how long to wait before reconnecting: `min` after losing the
+    /// connection, doubling after each failed attempt up to `max`. The
+    /// defaults are a tenth of a second and half a minute.
+    pub fn set_backoff(&mut self, min: Duration, max: Duration) {
+        self.min_delay = min;
+        self.max_delay = max;
+        self.delay = min;
+    }
+
+    // Whether to stop reconnecting: when closed, or when there's no one
+    // left to reconnect for.
+    fn is_abandoned(&self) -> bool {
+        let bus = self.shared.bus.borrow();
+        bus.closing || (Rc::strong_count(&self.shared) == 1 && bus.is_idle())
+    }
+
+    fn give_up(&mut self) -> Poll<(), Error> {
+        let mut bus = self.shared.bus.borrow_mut();
+        bus.supervised = false;
+        bus.lost_connection();
+        Ok(Async::Ready(()))
+    }
+}
+
+impl Future for Supervisor {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        self.shared.driver.register();
+        loop {
+            let next = match self.state.take() {
+                None => {
+                    let result = drive(&self.shared);
+                    let lost = {
+                        let bus = self.shared.bus.borrow();
+                        bus.disconnected && !bus.closing
+                    };
+                    match result {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        // The error went with the connection.
+                        Ok(Async::Ready(())) | Err(_) if lost => {}
+                        result => return result,
+                    }
+                    Reconnect::Waiting(Timeout::new(self.delay, &self.handle)?)
+                }
+                Some(_) if self.is_abandoned() => return self.give_up(),
+                Some(Reconnect::Waiting(mut timer)) => {
+                    if timer.poll()?.is_not_ready() {
+                        self.state = Some(Reconnect::Waiting(timer));
+                        return Ok(Async::NotReady);
+                    }
+                    Reconnect::Connecting((self.reconnect)())
+                }
+                Some(Reconnect::Connecting(mut connecting)) => {
+                    match connecting.poll() {
+                        Ok(Async::Ready(fresh)) => {
+                            self.shared.bus.borrow_mut().reconnected(fresh);
+                            self.delay = self.min_delay;
+                            continue;
+                        }
+                        Ok(Async::NotReady) => {
+                            self.state = Some(Reconnect::Connecting(connecting));
+                            return Ok(Async::NotReady);
+                        }
+                        Err(_) => {
+                            self.delay = cmp::min(self.delay * 2, self.max_delay);
+                            Reconnect::Waiting(Timeout::new(self.delay, &self.handle)?)
+                        }
+                    }
+                }
+            };
+            self.state = Some(next);
+        }
+    }
+}
+
+impl Stream for Bus {
+    type Item = Message;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.disconnected {
+            return Ok(Async::Ready(None));
+        }
+        self.forget_dropped();
+        while let Some(msg) = self.queued.pop_front() {
+            if let Some(msg) = self.dispatch(msg) {
+                return Ok(Async::Ready(Some(msg)));
+            }
+        }
+        // Waiting for messages is what drives calls, so they're written here.
+        if let Err(err) = self.write_queued() {
+            self.lost_connection();
+            return Err(err);
+        }
+        loop {
+            let msg = match self.inner.poll() {
+                Ok(Async::Ready(Some(msg))) => msg,
+                Ok(Async::Ready(None)) => {
+                    self.lost_connection();
+                    return Ok(Async::Ready(None));
+                }
+                Ok(Async::NotReady) => {
+                    // Answers to calls that came in since are written too.
+                    if let Err(err) = self.write_queued() {
+                        self.lost_connection();
+                        return Err(err);
+                    }
+                    return Ok(Async::NotReady);
+                }
+                Err(err) => {
+                    self.lost_connection();
+                    return Err(err);
+                }
+            };
+            if is_local_disconnected(&msg) {
+                self.lost_connection();
+                return Ok(Async::Ready(None));
+            }
+            if let Some(msg) = self.dispatch(msg) {
+                return Ok(Async::Ready(Some(msg)));
+            }
+        }
+    }
+}
+
+impl Sink for Bus {
+    type SinkItem = Message;
+    type SinkError = Error;
+
+    fn start_send(&mut self, mut item: Self::SinkItem) -> StartSend<Self::SinkItem, Error> {
+        if item.serial == 0 {
+            item.serial = self.next_serial();
+        }
+        // Queued calls go first, to keep messages in order.
+        if !self.outgoing.is_empty() {
+            self.write_queued()?;
+            if !self.outgoing.is_empty() {
+                return Ok(AsyncSink::NotReady(item));
+            }
+        }
+        self.inner.start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Error> {
+        self.write_queued()
+    }
+}
+
+/// The signals matching a rule given to `Bus::subscribe`. The stream ends
+/// when the connection does, unless a `Supervisor` reconnects it.
+pub struct SignalStream {
+    key: String,
+    id: u64,
+    // The reply to `AddMatch`, for the first subscriber to the rule.
+    add_match: Option<PendingCall>,
+    receiver: mpsc::UnboundedReceiver<Result<Message>>,
+    dropped: mpsc::UnboundedSender<Dropped>,
+}
+
+impl Stream for SignalStream {
+    type Item = Message;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Message>, Error> {
+        let added = match self.add_match {
+            Some(ref mut add_match) => {
+                match add_match.poll() {
+                    Ok(ready) => ready.is_ready(),
+                    // The stream just ends.
+                    Err(ref err) if err.standard_kind() == Some(StandardError::Disconnected) => {
+                        true
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            None => false,
+        };
+        if added {
+            self.add_match = None;
+        }
+        match self.receiver.poll() {
+            Ok(Async::Ready(Some(Ok(msg)))) => Ok(Async::Ready(Some(msg))),
+            Ok(Async::Ready(Some(Err(err)))) => Err(err),
+            Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(()) => unreachable!(),
+        }
+    }
+}
+
+impl Drop for SignalStream {
+    fn drop(&mut self) {
+        // The connection may be gone already.
+        let _ = self.dropped.unbounded_send(Dropped::Subscriber(self.key.clone(), self.id));
+    }
+}
+
+/// A connection being replaced by a `Supervisor`; see `Bus::reconnects`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Reconnected {
+    /// The unique name of the connection that was lost.
+    pub old_name: Option<BusName>,
+    /// The unique name the bus gave the new connection.
+    pub new_name: Option<BusName>,
+}
+
+/// The times a connection is replaced; see `Bus::reconnects`. It never
+/// fails.
+pub struct Reconnects {
+    receiver: mpsc::UnboundedReceiver<Reconnected>,
+}
+
+impl Stream for Reconnects {
+    type Item = Reconnected;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Reconnected>, ()> {
+        self.receiver.poll()
+    }
+}
+
+/// Resolves once a connection is gone; see `Bus::closed`. It never fails.
+pub struct Closed {
+    receiver: oneshot::Receiver<()>,
+}
+
+impl Future for Closed {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        match self.receiver.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            // The sender is dropped along with the connection.
+            Ok(Async::Ready(())) | Err(oneshot::Canceled) => Ok(Async::Ready(())),
+        }
+    }
+}
+
+fn is_local_disconnected(msg: &Message) -> bool {
+    msg.message_type == MessageType::Signal &&
+    msg.fields.path.as_ref().map_or(false, |path| path == LOCAL_PATH) &&
+    msg.fields.interface.as_ref().map_or(false, |interface| interface == LOCAL_INTERFACE) &&
+    msg.fields.member.as_ref().map_or(false, |member| member == "Disconnected")
+}
+
+fn disconnected_error() -> DBusError {
+    DBusError::standard(StandardError::Disconnected, "The connection is closed.")
+}
+
+// Sets a call's body to the contents of `args`, typed as the variants say.
+fn set_args(msg: &mut Message, args: &[Variant]) -> Result<()> {
+    let values = args.iter().map(|arg| arg.value().clone()).collect::<Vec<_>>();
+    let signature = Signature::new(args.iter().map(|arg| arg.ty().clone()).collect());
+    msg.set_body_with_signature(&values, signature)
+}
+
+/// The reply to a method call made with `Bus::call`. It resolves to the
+/// reply's body, or fails with the error the reply carries, with `NoReply`
+/// if it times out, or with `Disconnected` if the connection closes first.
+///
+/// Dropping it gives up on the call, and its reply is thrown away if it
+/// still comes.
+pub struct PendingCall {
+    state: CallState,
+    // The serial, while the connection is waiting for the reply.
+    serial: Option<u32>,
+    timer: Option<(Timeout, Duration)>,
+    handle: Option<Handle>,
+    dropped: Option<mpsc::UnboundedSender<Dropped>>,
+}
+
+enum CallState {
+    Waiting(oneshot::Receiver<Message>),
+    // Settled without waiting for a reply.
+    Done(Option<result::Result<Vec<Value>, DBusError>>),
+}
+
+impl PendingCall {
+    fn done(result: result::Result<Vec<Value>, DBusError>) -> Self {
+        PendingCall {
+            state: CallState::Done(Some(result)),
+            serial: None,
+            timer: None,
+            handle: None,
+            dropped: None,
+        }
+    }
+
+    fn failed(err: DBusError) -> Self {
+        PendingCall::done(Err(err))
+    }
+
+    /// Changes how long to wait for the reply, counting from now. `None`
+    /// waits forever. This only has an effect if the connection has a
+    /// reactor `Handle`.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timer = None;
+        if let (Some(timeout), Some(handle)) = (timeout, self.handle.as_ref()) {
+            match Timeout::new(timeout, handle) {
+                Ok(timer) => self.timer = Some((timer, timeout)),
+                Err(err) => {
+                    self.abandon();
+                    self.state = CallState::Done(Some(Err(err.into())));
+                }
+            }
+        }
+        self
+    }
+
+    // Tells the connection to stop waiting for the reply.
+    fn abandon(&mut self) {
+        if let (Some(serial), Some(dropped)) = (self.serial.take(), self.dropped.as_ref()) {
+            // The connection may be gone already.
+            let _ = dropped.unbounded_send(Dropped::Call(serial));
+        }
+    }
+}
+
+impl Future for PendingCall {
+    type Item = Vec<Value>;
+    type Error = DBusError;
+
+    fn poll(&mut self) -> Poll<Vec<Value>, DBusError> {
+        let result = match self.state {
+            CallState::Waiting(ref mut receiver) => receiver.poll(),
+            CallState::Done(ref mut result) => {
+                let result = result.take().expect("polled PendingCall after completion");
+                return result.map(Async::Ready);
+            }
+        };
+        match result {
+            Ok(Async::Ready(reply)) => {
+                self.serial = None;
+                return reply_body(reply).map(Async::Ready);
+            }
+            Ok(Async::NotReady) => {}
+            Err(oneshot::Canceled) => {
+                self.serial = None;
+                return Err(DBusError::standard(StandardError::Disconnected,
+                                               "The connection closed before the reply \
+                                                arrived."));
+            }
+        }
+        let timed_out = match self.timer {
+            Some((ref mut timer, timeout)) => {
+                match timer.poll()? {
+                    Async::Ready(()) => Some(timeout),
+                    Async::NotReady => None,
+                }
+            }
+            None => None,
+        };
+        match timed_out {
+            Some(timeout) => {
+                self.abandon();
+                let secs = timeout.as_secs() as f64 + timeout.subsec_nanos() as f64 / 1e9;
+                Err(DBusError::standard(StandardError::NoReply,
+                                        format!("No reply within {} seconds.", secs)))
+            }
+            None => Ok(Async::NotReady),
+        }
+    }
+}
+
+impl Drop for PendingCall {
+    fn drop(&mut self) {
+        self.abandon();
+    }
+}
+
+fn reply_body(reply: Message) -> result::Result<Vec<Value>, DBusError> {
+    if reply.message_type == MessageType::Error {
+        return Err(DBusError::from_reply(&reply));
+    }
+    Ok(reply.body()?)
+}
+
+// Waits for the reply to `Hello`, queueing any other messages.
+struct HelloReply {
+    bus: Option<Bus>,
+    serial: u32,
+}
+
+impl Future for HelloReply {
+    type Item = Bus;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Bus, Error> {
+        loop {
+            let msg = {
+                let bus = self.bus.as_mut().expect("polled HelloReply after completion");
+                match try_ready!(bus.inner.poll()) {
+                    Some(msg) => msg,
+                    None => {
+                        return Err(Error::new(ErrorKind::UnexpectedEof,
+                                              "connection closed before the reply to Hello"))
+                    }
+                }
+            };
+            if msg.fields.reply_serial != Some(self.serial) {
+                self.bus.as_mut().unwrap().queued.push_back(msg);
+                continue;
+            }
+
+            let mut bus = self.bus.take().unwrap();
+            if msg.message_type == MessageType::Error {
+                return Err(DBusError::from_reply(&msg).into());
+            }
+            let name = match msg.body()?.into_iter().next() {
+                Some(value) => String::from_dbus(value)?,
+                None => return Err(Error::new(ErrorKind::InvalidData, "empty reply to Hello")),
+            };
+            let name = BusName::new(name)
+                .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+            bus.unique_name = Some(name);
+            return Ok(Async::Ready(bus));
+        }
+    }
 }
@@ -0,0 +1,437 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+use std::result;
+use std::str;
+
+use bus::literal::ConstSignature;
+use bus::names::{BusName, ErrorName, InterfaceName, MemberName, ObjectPath};
+use bus::types::{BasicType, Signature, Type};
+use bus::wire::{BasicValue, ContainerValue, PathSegment, TypeError, TypeErrorKind, Value};
+
+/// A Rust type with a fixed D-Bus type.
+pub trait DBusType {
+    /// `dbus_type` as a signature string, available in constant expressions
+    /// so that it can be checked at build time.
+    const SIGNATURE: ConstSignature;
+
+    /// The D-Bus type values of this type are marshalled as.
+    fn dbus_type() -> Type {
+        Self::SIGNATURE.to_type()
+    }
+
+    /// `dbus_type` as a single-type signature.
+    fn signature() -> Signature {
+        Signature::new(vec![Self::dbus_type()])
+    }
+}
+
+/// A type whose D-Bus type is basic, and so can be used as a dict key.
+pub trait BasicDBusType: DBusType {
+    fn basic_type() -> BasicType;
+}
+
+pub trait ToDBus: DBusType {
+    fn to_dbus(&self) -> Value;
+}
+
+pub trait FromDBus: DBusType + Sized {
+    fn from_dbus(value: Value) -> result::Result<Self, TypeError>;
+}
+
+fn mismatch<T: DBusType>(value: &Value) -> TypeError {
+    TypeError::new(TypeErrorKind::Mismatch {
+        expected: T::dbus_type(),
+        found: value.kind_name(),
+    })
+}
+
+fn invalid_name(name: &str, kind: &str) -> TypeError {
+    TypeError::new(TypeErrorKind::Invalid(format!("{:?} is not a valid D-Bus {}", name, kind)))
+}
+
+macro_rules! basic_impls {
+    ($($ty:ty => $variant:ident $code:expr,)*) => {
+        $(
+            impl DBusType for $ty {
+                const SIGNATURE: ConstSignature = ConstSignature::new($code);
+            }
+
+            impl BasicDBusType for $ty {
+                fn basic_type() -> BasicType {
+                    BasicType::$variant
+                }
+            }
+
+            impl ToDBus for $ty {
+                fn to_dbus(&self) -> Value {
+                    Value::BasicValue(BasicValue::$variant(*self))
+                }
+            }
+
+            impl FromDBus for $ty {
+                fn from_dbus(value: Value) -> result::Result<Self, TypeError> {
+                    match value {
+                        Value::BasicValue(BasicValue::$variant(n)) => Ok(n),
+                        value => Err(mismatch::<Self>(&value)),
+                    }
+                }
+            }
+        )*
+    }
+}
+
+basic_impls! {
+    u8 => Byte "y",
+    bool => Bool "b",
+    i16 => Int16 "n",
+    u16 => UInt16 "q",
+    i32 => Int32 "i",
+    u32 => UInt32 "u",
+    i64 => Int64 "x",
+    u64 => UInt64 "t",
+    f64 => Double "d",
+}
+
+impl DBusType for str {
+    const SIGNATURE: ConstSignature = ConstSignature::new("s");
+}
+
+impl BasicDBusType for str {
+    fn basic_type() -> BasicType {
+        BasicType::String
+    }
+}
+
+impl ToDBus for str {
+    fn to_dbus(&self) -> Value {
+        Value::BasicValue(BasicValue::String(self.to_owned().into()))
+    }
+}
+
+impl DBusType for String {
+    const SIGNATURE: ConstSignature = ConstSignature::new("s");
+}
+
+impl BasicDBusType for String {
+    fn basic_type() -> BasicType {
+        BasicType::String
+    }
+}
+
+impl ToDBus for String {
+    fn to_dbus(&self) -> Value {
+        Value::BasicValue(BasicValue::String(self.clone().into()))
+    }
+}
+
+impl FromDBus for String {
+    fn from_dbus(value: Value) -> result::Result<Self, TypeError> {
+        match value {
+            Value::BasicValue(BasicValue::String(s)) => Ok(s.into_owned()),
+            value => Err(mismatch::<Self>(&value)),
+        }
+    }
+}
+
+impl DBusType for Signature {
+    const SIGNATURE: ConstSignature = ConstSignature::new("g");
+}
+
+impl BasicDBusType for Signature {
+    fn basic_type() -> BasicType {
+        BasicType::Signature
+    }
+}
+
+impl ToDBus for Signature {
+    fn to_dbus(&self) -> Value {
+        Value::BasicValue(BasicValue::Signature(self.clone()))
+    }
+}
+
+impl FromDBus for Signature {
+    fn from_dbus(value: Value) -> result::Result<Self, TypeError> {
+        match value {
+            Value::BasicValue(BasicValue::Signature(signature)) => Ok(signature),
+            value => Err(mismatch::<Self>(&value)),
+        }
+    }
+}
+
+impl DBusType for ObjectPath {
+    const SIGNATURE: ConstSignature = ConstSignature::new("o");
+}
+
+impl BasicDBusType for ObjectPath {
+    fn basic_type() -> BasicType {
+        BasicType::ObjectPath
+    }
+}
+
+impl ToDBus for ObjectPath {
+    fn to_dbus(&self) -> Value {
+        Value::BasicValue(BasicValue::ObjectPath(self.as_bytes().to_vec().into()))
+    }
+}
+
+impl FromDBus for ObjectPath {
+    fn from_dbus(value: Value) -> result::Result<Self, TypeError> {
+        match value {
+            Value::BasicValue(BasicValue::ObjectPath(path)) => {
+                str::from_utf8(&path)
+                    .ok()
+                    .and_then(|path| ObjectPath::new(path).ok())
+                    .ok_or_else(|| invalid_name(&String::from_utf8_lossy(&path), "object path"))
+            }
+            value => Err(mismatch::<Self>(&value)),
+        }
+    }
+}
+
+// Names other than object paths are marshalled as plain strings and
+// validated when they're decoded.
+macro_rules! name_impls {
+    ($($name:ident,)*) => {
+        $(
+            impl DBusType for $name {
+                const SIGNATURE: ConstSignature = ConstSignature::new("s");
+            }
+
+            impl BasicDBusType for $name {
+                fn basic_type() -> BasicType {
+                    BasicType::String
+                }
+            }
+
+            impl ToDBus for $name {
+                fn to_dbus(&self) -> Value {
+                    self.as_str().to_dbus()
+                }
+            }
+
+            impl FromDBus for $name {
+                fn from_dbus(value: Value) -> result::Result<Self, TypeError> {
+                    $name::new(String::from_dbus(value)?)
+                        .map_err(|err| invalid_name(&err.name, err.kind))
+                }
+            }
+        )*
+    }
+}
+
+name_impls! {
+    BusName,
+    InterfaceName,
+    MemberName,
+    ErrorName,
+}
+
+impl<'a, T: DBusType + ?Sized> DBusType for &'a T {
+    const SIGNATURE: ConstSignature = T::SIGNATURE;
+}
+
+impl<'a, T: BasicDBusType + ?Sized> BasicDBusType for &'a T {
+    fn basic_type() -> BasicType {
+        T::basic_type()
+    }
+}
+
+impl<'a, T: ToDBus + ?Sized> ToDBus for &'a T {
+    fn to_dbus(&self) -> Value {
+        (**self).to_dbus()
+    }
+}
+
+impl<T: DBusType> DBusType for Vec<T> {
+    const SIGNATURE: ConstSignature = ConstSignature::array(T::SIGNATURE);
+}
+
+impl<T: ToDBus> ToDBus for Vec<T> {
+    fn to_dbus(&self) -> Value {
+        Value::ContainerValue(ContainerValue::Array(self.iter().map(ToDBus::to_dbus).collect()))
+    }
+}
+
+impl<T: FromDBus> FromDBus for Vec<T> {
+    fn from_dbus(value: Value) -> result::Result<Self, TypeError> {
+        match value {
+            Value::ContainerValue(ContainerValue::Array(elems)) => {
+                elems.into_iter()
+                    .enumerate()
+                    .map(|(i, elem)| {
+                        T::from_dbus(elem).map_err(|err| err.at(PathSegment::Element(i)))
+                    })
+                    .collect()
+            }
+            value => Err(mismatch::<Self>(&value)),
+        }
+    }
+}
+
+fn dict_to_dbus<'a, K, V, I>(entries: I) -> Value
+    where K: ToDBus + BasicDBusType + 'a,
+          V: ToDBus + 'a,
+          I: Iterator<Item = (&'a K, &'a V)>
+{
+    Value::ContainerValue(ContainerValue::Dict(entries.map(|(key, value)| {
+            let key = match key.to_dbus() {
+                Value::BasicValue(key) => key,
+                // `BasicDBusType` promises a basic value.
+                Value::ContainerValue(_) => unreachable!(),
+            };
+            (key, value.to_dbus())
+        })
+        .collect()))
+}
+
+fn dict_from_dbus<K, V, C>(value: Value) -> result::Result<C, TypeError>
+    where K: FromDBus + BasicDBusType,
+          V: FromDBus,
+          C: DBusType + Default + Extend<(K, V)>
+{
+    match value {
+        Value::ContainerValue(ContainerValue::Dict(entries)) => {
+            let mut dict = C::default();
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                let key = K::from_dbus(Value::BasicValue(key))
+                    .map_err(|err| err.at(PathSegment::Key(i)))?;
+                let value = V::from_dbus(value).map_err(|err| err.at(PathSegment::Value(i)))?;
+                dict.extend(Some((key, value)));
+            }
+            Ok(dict)
+        }
+        value => Err(mismatch::<C>(&value)),
+    }
+}
+
+impl<K: BasicDBusType, V: DBusType, S> DBusType for HashMap<K, V, S> {
+    const SIGNATURE: ConstSignature = ConstSignature::dict(K::SIGNATURE, V::SIGNATURE);
+}
+
+impl<K, V, S> ToDBus for HashMap<K, V, S>
+    where K: ToDBus + BasicDBusType + Eq + Hash,
+          V: ToDBus,
+          S: BuildHasher
+{
+    fn to_dbus(&self) -> Value {
+        dict_to_dbus(self.iter())
+    }
+}
+
+impl<K, V, S> FromDBus for HashMap<K, V, S>
+    where K: FromDBus + BasicDBusType + Eq + Hash,
+          V: FromDBus,
+          S: BuildHasher + Default
+{
+    fn from_dbus(value: Value) -> result::Result<Self, TypeError> {
+        dict_from_dbus(value)
+    }
+}
+
+impl<K: BasicDBusType, V: DBusType> DBusType for BTreeMap<K, V> {
+    const SIGNATURE: ConstSignature = ConstSignature::dict(K::SIGNATURE, V::SIGNATURE);
+}
+
+impl<K, V> ToDBus for BTreeMap<K, V>
+    where K: ToDBus + BasicDBusType + Ord,
+          V: ToDBus
+{
+    fn to_dbus(&self) -> Value {
+        dict_to_dbus(self.iter())
+    }
+}
+
+impl<K, V> FromDBus for BTreeMap<K, V>
+    where K: FromDBus + BasicDBusType + Ord,
+          V: FromDBus
+{
+    fn from_dbus(value: Value) -> result::Result<Self, TypeError> {
+        dict_from_dbus(value)
+    }
+}
+
+#[cfg(feature = "maybe")]
+impl<T: DBusType> DBusType for Option<T> {
+    const SIGNATURE: ConstSignature = ConstSignature::maybe(T::SIGNATURE);
+}
+
+#[cfg(feature = "maybe")]
+impl<T: ToDBus> ToDBus for Option<T> {
+    fn to_dbus(&self) -> Value {
+        Value::ContainerValue(ContainerValue::Maybe(self.as_ref()
+            .map(|inner| Box::new(inner.to_dbus()))))
+    }
+}
+
+#[cfg(feature = "maybe")]
+impl<T: FromDBus> FromDBus for Option<T> {
+    fn from_dbus(value: Value) -> result::Result<Self, TypeError> {
+        match value {
+            Value::ContainerValue(ContainerValue::Maybe(Some(inner))) => {
+                T::from_dbus(*inner).map(Some).map_err(|err| err.at(PathSegment::Maybe))
+            }
+            Value::ContainerValue(ContainerValue::Maybe(None)) => Ok(None),
+            value => Err(mismatch::<Self>(&value)),
+        }
+    }
+}
+
+// Tuples are marshalled as structs.
+macro_rules! tuple_impls {
+    ($($len:expr => ($($n:tt $name:ident)+),)+) => {
+        $(
+            impl<$($name: DBusType),+> DBusType for ($($name,)+) {
+                const SIGNATURE: ConstSignature = ConstSignature::structure(&[
+                    $($name::SIGNATURE),+
+                ]);
+            }
+
+            impl<$($name: ToDBus),+> ToDBus for ($($name,)+) {
+                fn to_dbus(&self) -> Value {
+                    Value::ContainerValue(ContainerValue::Struct(vec![
+                        $(self.$n.to_dbus()),+
+                    ]))
+                }
+            }
+
+            impl<$($name: FromDBus),+> FromDBus for ($($name,)+) {
+                fn from_dbus(value: Value) -> result::Result<Self, TypeError> {
+                    let fields = match value {
+                        Value::ContainerValue(ContainerValue::Struct(fields)) => fields,
+                        value => return Err(mismatch::<Self>(&value)),
+                    };
+                    if fields.len() != $len {
+                        return Err(TypeError::new(TypeErrorKind::Arity {
+                            expected: $len,
+                            found: fields.len(),
+                        }));
+                    }
+                    let mut fields = fields.into_iter();
+                    Ok(($(
+                        $name::from_dbus(fields.next().unwrap())
+                            .map_err(|err| err.at(PathSegment::Field($n)))?,
+                    )+))
+                }
+            }
+        )+
+    }
+}
+
+tuple_impls! {
+    1 => (0 T0),
+    2 => (0 T0 1 T1),
+    3 => (0 T0 1 T1 2 T2),
+    4 => (0 T0 1 T1 2 T2 3 T3),
+    5 => (0 T0 1 T1 2 T2 3 T3 4 T4),
+    6 => (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5),
+    7 => (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6),
+    8 => (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7),
+    9 => (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8),
+    10 => (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9),
+    11 => (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10),
+    12 => (0 T0 1 T1 2 T2 3 T3 4 T4 5 T5 6 T6 7 T7 8 T8 9 T9 10 T10 11 T11),
+}
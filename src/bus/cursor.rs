@@ -0,0 +1,295 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io::{Error, ErrorKind, Result};
+
+use bus::borrowed::ValueRef;
+use bus::convert::FromDBus;
+use bus::marshal::{self, Decoder, Endianness};
+use bus::types::{ContainerType, Depth, Signature, Type};
+
+/// Walks marshalled values one at a time, without decoding the ones the
+/// caller doesn't ask for.
+///
+/// A cursor steps through a sequence of values: the arguments of a body,
+/// the fields of a struct, the elements of an array, or the contents of a
+/// variant. `enter` opens the next value, if it is a container, as a cursor
+/// of its own, so that an array can be walked an element at a time however
+/// long it is. The entries of a dict are walked as two-field structs.
+///
+/// Values that are skipped, or left behind when a nested cursor is dropped,
+/// are still validated. After malformed data the cursor's position is lost,
+/// and every later call fails; a value that `read` can't convert is still
+/// stepped over.
+pub struct ValueCursor<'c, 'a: 'c> {
+    decoder: DecoderSlot<'c, 'a>,
+    items: Items,
+    depth: Depth,
+    // An error hit while a dropped nested cursor skipped its remaining
+    // values, reported by the next call on this one.
+    error: Option<Error>,
+    parent_error: Option<&'c mut Option<Error>>,
+    failed: bool,
+}
+
+enum DecoderSlot<'c, 'a: 'c> {
+    Owned(Decoder<'a>),
+    Borrowed(&'c mut Decoder<'a>),
+}
+
+impl<'c, 'a> DecoderSlot<'c, 'a> {
+    fn get(&mut self) -> &mut Decoder<'a> {
+        match *self {
+            DecoderSlot::Owned(ref mut decoder) => decoder,
+            DecoderSlot::Borrowed(ref mut decoder) => decoder,
+        }
+    }
+
+    fn get_ref(&self) -> &Decoder<'a> {
+        match *self {
+            DecoderSlot::Owned(ref decoder) => decoder,
+            DecoderSlot::Borrowed(ref decoder) => decoder,
+        }
+    }
+}
+
+enum Items {
+    // Values of the given types, of which `next` is the next to read.
+    Fields { types: Vec<Type>, next: usize },
+    // Values of one type, up to the offset at which the array ends.
+    Elements { ty: Type, end: usize },
+}
+
+impl Items {
+    fn peek(&self, decoder: &Decoder) -> Option<&Type> {
+        match *self {
+            Items::Fields { ref types, next } => types.get(next),
+            Items::Elements { ref ty, end } if decoder.position() < end => Some(ty),
+            Items::Elements { .. } => None,
+        }
+    }
+
+    // Moves past the next value's type, returning it, or `None` at the end.
+    fn advance(&mut self, decoder: &Decoder) -> Result<Option<&Type>> {
+        match *self {
+            Items::Fields { ref types, ref mut next } => {
+                let ty = types.get(*next);
+                if ty.is_some() {
+                    *next += 1;
+                }
+                Ok(ty)
+            }
+            Items::Elements { ref ty, end } => {
+                if decoder.position() < end {
+                    Ok(Some(ty))
+                } else {
+                    decoder.end_array(end)?;
+                    Ok(None)
+                }
+            }
+        }
+    }
+}
+
+impl<'a> ValueCursor<'a, 'a> {
+    /// A cursor over the values of `signature` marshalled in `input`, such as
+    /// a message body.
+    pub fn new(input: &'a [u8],
+               signature: &Signature,
+               endianness: Endianness,
+               max_array_len: u32)
+               -> Self {
+        ValueCursor {
+            decoder: DecoderSlot::Owned(Decoder::new(input, 0, endianness, max_array_len)),
+            items: Items::Fields {
+                types: signature.to_vec(),
+                next: 0,
+            },
+            depth: Depth::new(),
+            error: None,
+            parent_error: None,
+            failed: false,
+        }
+    }
+}
+
+impl<'c, 'a> ValueCursor<'c, 'a> {
+    /// The type of the next value, or `None` if there are no more.
+    pub fn peek_type(&self) -> Option<&Type> {
+        self.items.peek(self.decoder.get_ref())
+    }
+
+    /// Decodes the next value, or returns `None` if there are no more.
+    pub fn next_value(&mut self) -> Result<Option<ValueRef<'a>>> {
+        self.check()?;
+        let depth = self.depth;
+        let result = {
+            let decoder = self.decoder.get();
+            match self.items.advance(decoder) {
+                Ok(Some(ty)) => decoder.value(ty, depth).map(Some),
+                Ok(None) => Ok(None),
+                Err(err) => Err(err),
+            }
+        };
+        self.track(result)
+    }
+
+    /// Decodes the next value and converts it to `T`.
+    pub fn read<T: FromDBus>(&mut self) -> Result<Option<T>> {
+        match self.next_value()? {
+            Some(value) => Ok(Some(T::from_dbus(value.into_owned())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Steps over the next value, returning `false` if there are no more.
+    pub fn skip(&mut self) -> Result<bool> {
+        self.check()?;
+        let depth = self.depth;
+        let result = {
+            let decoder = self.decoder.get();
+            match self.items.advance(decoder) {
+                Ok(Some(ty)) => decoder.skip(ty, depth).map(|_| true),
+                Ok(None) => Ok(false),
+                Err(err) => Err(err),
+            }
+        };
+        self.track(result)
+    }
+
+    /// Opens the next value, which must be a container, as a cursor over its
+    /// contents. This cursor moves past the container once the returned one
+    /// is dropped.
+    pub fn enter<'b>(&'b mut self) -> Result<Option<ValueCursor<'b, 'a>>> {
+        self.check()?;
+        if let Some(&Type::BasicType(_)) = self.peek_type() {
+            return Err(Error::new(ErrorKind::InvalidInput, "can't enter a basic value"));
+        }
+        let depth = self.depth;
+        let result = {
+            let decoder = self.decoder.get();
+            match self.items.advance(decoder) {
+                Ok(Some(ty)) => open(decoder, ty, depth).map(Some),
+                Ok(None) => Ok(None),
+                Err(err) => Err(err),
+            }
+        };
+        match self.track(result)? {
+            Some((items, depth)) => {
+                Ok(Some(ValueCursor {
+                    decoder: DecoderSlot::Borrowed(self.decoder.get()),
+                    items: items,
+                    depth: depth,
+                    error: None,
+                    parent_error: Some(&mut self.error),
+                    failed: false,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Steps over any remaining values. For a cursor over a whole body, this
+    /// also checks that nothing follows the last value.
+    pub fn finish(mut self) -> Result<()> {
+        while self.skip()? {}
+        match self.decoder {
+            DecoderSlot::Owned(ref decoder) => decoder.finish(),
+            DecoderSlot::Borrowed(_) => Ok(()),
+        }
+    }
+
+    fn check(&mut self) -> Result<()> {
+        if let Some(err) = self.error.take() {
+            self.failed = true;
+            return Err(err);
+        }
+        if self.failed {
+            return Err(failed());
+        }
+        Ok(())
+    }
+
+    fn track<T>(&mut self, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.failed = true;
+        }
+        result
+    }
+}
+
+impl<'c, 'a> Drop for ValueCursor<'c, 'a> {
+    // Moves the parent cursor past the rest of this container.
+    fn drop(&mut self) {
+        if self.parent_error.is_none() {
+            return;
+        }
+        let err = loop {
+            match self.skip() {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(err) => break err,
+            }
+        };
+        if let Some(parent_error) = self.parent_error.take() {
+            *parent_error = Some(err);
+        }
+    }
+}
+
+// Reads the start of a container of type `ty`, returning what it holds and
+// the depth inside it.
+fn open(decoder: &mut Decoder, ty: &Type, depth: Depth) -> Result<(Items, Depth)> {
+    let offset = decoder.position();
+    let container_ty = match *ty {
+        Type::ContainerType(ref container_ty) => container_ty,
+        Type::BasicType(_) => unreachable!(),
+    };
+    Ok(match **container_ty {
+        ContainerType::Array(ref elem_ty) => {
+            let depth = depth.enter_array(offset)?;
+            let end = decoder.begin_array(marshal::alignment(elem_ty))?;
+            (Items::Elements {
+                ty: elem_ty.clone(),
+                end: end,
+            },
+             depth)
+        }
+        ContainerType::Dict(ref key_ty, ref value_ty) => {
+            // Entering each entry adds the struct level.
+            let depth = depth.enter_array(offset)?;
+            let end = decoder.begin_array(8)?;
+            let entry_ty = vec![Type::BasicType(key_ty.clone()), value_ty.clone()];
+            (Items::Elements {
+                ty: Type::ContainerType(Box::new(ContainerType::Struct(entry_ty))),
+                end: end,
+            },
+             depth)
+        }
+        ContainerType::Struct(ref field_tys) => {
+            let depth = depth.enter_struct(offset)?;
+            decoder.align(8)?;
+            (Items::Fields {
+                types: field_tys.clone(),
+                next: 0,
+            },
+             depth)
+        }
+        ContainerType::Variant => {
+            let depth = depth.enter_variant(offset)?;
+            let inner_ty = decoder.single_type_signature()?;
+            (Items::Fields {
+                types: vec![inner_ty],
+                next: 0,
+            },
+             depth)
+        }
+        #[cfg(feature = "maybe")]
+        ContainerType::Maybe(_) => return Err(marshal::malformed("maybe types are GVariant-only")),
+    })
+}
+
+fn failed() -> Error {
+    Error::new(ErrorKind::Other, "the cursor stopped at an earlier error")
+}
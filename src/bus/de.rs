@@ -0,0 +1,533 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use serde::de::{self, Deserialize, DeserializeSeed, IntoDeserializer, Visitor};
+use std::fmt::{self, Display, Formatter};
+use std::marker::PhantomData;
+use std::io::Result;
+use std::result;
+use std::vec;
+
+use bus::marshal::{self, Endianness};
+use bus::message::MAX_ARRAY_LEN;
+use bus::ser::{AsVariant, VARIANT_NEWTYPE};
+use bus::types::{BasicType, ContainerType, Signature, Type};
+use bus::wire::{BasicValue, ContainerValue, PathSegment, TypeError, TypeErrorKind, Value};
+
+impl de::Error for TypeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        TypeError::new(TypeErrorKind::Invalid(msg.to_string()))
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for AsVariant<T> {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> result::Result<Self, D::Error> {
+        struct AsVariantVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for AsVariantVisitor<T> {
+            type Value = AsVariant<T>;
+
+            fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+                f.write_str("a D-Bus variant")
+            }
+
+            fn visit_newtype_struct<D>(self,
+                                       deserializer: D)
+                                       -> result::Result<AsVariant<T>, D::Error>
+                where D: de::Deserializer<'de>
+            {
+                T::deserialize(deserializer).map(AsVariant)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(VARIANT_NEWTYPE,
+                                                AsVariantVisitor(PhantomData))
+    }
+}
+
+/// Converts a `Value` to a serde value, mapping types back the way
+/// `to_value` maps them. Variants are looked through wherever anything but
+/// `AsVariant` is expected, and dicts with string keys can also be read as
+/// structs, matching keys to field names.
+pub fn from_value<'de, T: Deserialize<'de>>(value: Value) -> result::Result<T, TypeError> {
+    T::deserialize(Deserializer::new(value))
+}
+
+/// Unmarshals a serde value of the types in `signature`, which must consume
+/// all of `input`. If `signature` has more than one type, the values are
+/// read as the fields of a struct, such as a tuple.
+pub fn from_bytes<'de, T: Deserialize<'de>>(input: &[u8],
+                                            signature: &Signature,
+                                            endianness: Endianness)
+                                            -> Result<T> {
+    let mut values = marshal::decode_values(input, signature, endianness, MAX_ARRAY_LEN)?;
+    let value = if values.len() == 1 {
+        values.pop().unwrap()
+    } else {
+        Value::ContainerValue(ContainerValue::Struct(values))
+    };
+    Ok(from_value(value)?)
+}
+
+pub struct Deserializer {
+    value: Value,
+}
+
+impl Deserializer {
+    pub fn new(value: Value) -> Self {
+        Deserializer { value: value }
+    }
+
+    fn mismatch(&self, expected: Type) -> TypeError {
+        TypeError::new(TypeErrorKind::Mismatch {
+            expected: expected,
+            found: self.value.kind_name(),
+        })
+    }
+
+    // For containers, whose element types aren't known here.
+    fn expected(&self, what: &str) -> TypeError {
+        TypeError::new(TypeErrorKind::Invalid(format!("Expected {}, found {}",
+                                                      what,
+                                                      self.value.kind_name())))
+    }
+
+    // Variants are transparent to everything but `AsVariant`.
+    fn look_through_variants(self) -> Self {
+        let mut value = self.value;
+        while let Value::ContainerValue(ContainerValue::Variant(_, inner)) = value {
+            value = *inner;
+        }
+        Deserializer { value: value }
+    }
+}
+
+fn basic(ty: BasicType) -> Type {
+    Type::BasicType(ty)
+}
+
+fn container(ty: ContainerType) -> Type {
+    Type::ContainerType(Box::new(ty))
+}
+
+macro_rules! deserialize_basic {
+    ($($method:ident => $variant:ident, $visit:ident;)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, TypeError> {
+                let this = self.look_through_variants();
+                match this.value {
+                    Value::BasicValue(BasicValue::$variant(v)) => visitor.$visit(v),
+                    _ => Err(this.mismatch(basic(BasicType::$variant))),
+                }
+            }
+        )*
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = TypeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, TypeError> {
+        match self.value {
+            Value::BasicValue(basic) => {
+                match basic {
+                    BasicValue::Byte(v) => visitor.visit_u8(v),
+                    BasicValue::Bool(v) => visitor.visit_bool(v),
+                    BasicValue::Int16(v) => visitor.visit_i16(v),
+                    BasicValue::UInt16(v) => visitor.visit_u16(v),
+                    BasicValue::Int32(v) => visitor.visit_i32(v),
+                    BasicValue::UInt32(v) => visitor.visit_u32(v),
+                    BasicValue::Int64(v) => visitor.visit_i64(v),
+                    BasicValue::UInt64(v) => visitor.visit_u64(v),
+                    BasicValue::Double(v) => visitor.visit_f64(v),
+                    BasicValue::String(v) => visitor.visit_string(v.into_owned()),
+                    BasicValue::ObjectPath(v) => {
+                        visitor.visit_string(String::from_utf8_lossy(&v).into_owned())
+                    }
+                    BasicValue::Signature(v) => visitor.visit_string(v.to_string()),
+                    BasicValue::UnixFd(v) => visitor.visit_u32(v),
+                }
+            }
+            Value::ContainerValue(ContainerValue::Array(elems)) => {
+                visitor.visit_seq(SeqAccess::new(elems, PathSegment::Element))
+            }
+            Value::ContainerValue(ContainerValue::Struct(fields)) => {
+                visitor.visit_seq(SeqAccess::new(fields, PathSegment::Field))
+            }
+            Value::ContainerValue(ContainerValue::Variant(_, inner)) => {
+                Deserializer::new(*inner)
+                    .deserialize_any(visitor)
+                    .map_err(|err| err.at(PathSegment::Variant))
+            }
+            Value::ContainerValue(ContainerValue::Dict(entries)) => {
+                visitor.visit_map(MapAccess::new(entries))
+            }
+            #[cfg(feature = "maybe")]
+            Value::ContainerValue(ContainerValue::Maybe(inner)) => {
+                Deserializer::new(Value::ContainerValue(ContainerValue::Maybe(inner)))
+                    .deserialize_option(visitor)
+            }
+        }
+    }
+
+    deserialize_basic! {
+        deserialize_bool => Bool, visit_bool;
+        deserialize_i16 => Int16, visit_i16;
+        deserialize_i32 => Int32, visit_i32;
+        deserialize_i64 => Int64, visit_i64;
+        deserialize_u8 => Byte, visit_u8;
+        deserialize_u16 => UInt16, visit_u16;
+        deserialize_u32 => UInt32, visit_u32;
+        deserialize_u64 => UInt64, visit_u64;
+        deserialize_f64 => Double, visit_f64;
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, TypeError> {
+        self.deserialize_i16(visitor)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, TypeError> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, TypeError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, TypeError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self,
+                                           visitor: V)
+                                           -> result::Result<V::Value, TypeError> {
+        let this = self.look_through_variants();
+        match this.value {
+            Value::BasicValue(BasicValue::String(_)) |
+            Value::BasicValue(BasicValue::ObjectPath(_)) |
+            Value::BasicValue(BasicValue::Signature(_)) => this.deserialize_any(visitor),
+            _ => Err(this.mismatch(basic(BasicType::String))),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, TypeError> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self,
+                                             visitor: V)
+                                             -> result::Result<V::Value, TypeError> {
+        let this = self.look_through_variants();
+        let mismatch = this.mismatch(container(ContainerType::Array(basic(BasicType::Byte))));
+        match this.value {
+            Value::ContainerValue(ContainerValue::Array(elems)) => {
+                let mut bytes = Vec::with_capacity(elems.len());
+                for elem in elems {
+                    match elem {
+                        Value::BasicValue(BasicValue::Byte(b)) => bytes.push(b),
+                        _ => return Err(mismatch),
+                    }
+                }
+                visitor.visit_byte_buf(bytes)
+            }
+            _ => Err(mismatch),
+        }
+    }
+
+    // D-Bus has no null, so whatever is present is `Some`.
+    fn deserialize_option<V: Visitor<'de>>(self,
+                                           visitor: V)
+                                           -> result::Result<V::Value, TypeError> {
+        // Without maybe values, `Some` is transparent and `None` can't occur.
+        match self.value {
+            #[cfg(feature = "maybe")]
+            Value::ContainerValue(ContainerValue::Maybe(None)) => visitor.visit_none(),
+            #[cfg(feature = "maybe")]
+            Value::ContainerValue(ContainerValue::Maybe(Some(inner))) => {
+                visitor.visit_some(Deserializer::new(*inner))
+                    .map_err(|err| err.at(PathSegment::Maybe))
+            }
+            value => visitor.visit_some(Deserializer::new(value)),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, _visitor: V) -> result::Result<V::Value, TypeError> {
+        Err(TypeError::new(TypeErrorKind::Invalid("D-Bus has no representation for the unit type"
+            .to_owned())))
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self,
+                                                _name: &'static str,
+                                                visitor: V)
+                                                -> result::Result<V::Value, TypeError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self,
+                                                   name: &'static str,
+                                                   visitor: V)
+                                                   -> result::Result<V::Value, TypeError> {
+        if name != VARIANT_NEWTYPE {
+            return visitor.visit_newtype_struct(self);
+        }
+        match self.value {
+            Value::ContainerValue(ContainerValue::Variant(_, inner)) => {
+                visitor.visit_newtype_struct(Deserializer::new(*inner))
+                    .map_err(|err| err.at(PathSegment::Variant))
+            }
+            _ => Err(self.mismatch(container(ContainerType::Variant))),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, TypeError> {
+        let this = self.look_through_variants();
+        match this.value {
+            Value::ContainerValue(ContainerValue::Array(elems)) => {
+                visitor.visit_seq(SeqAccess::new(elems, PathSegment::Element))
+            }
+            _ => Err(this.expected("an array")),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self,
+                                          _len: usize,
+                                          visitor: V)
+                                          -> result::Result<V::Value, TypeError> {
+        let this = self.look_through_variants();
+        match this.value {
+            Value::ContainerValue(ContainerValue::Struct(fields)) => {
+                visitor.visit_seq(SeqAccess::new(fields, PathSegment::Field))
+            }
+            _ => Err(this.expected("a struct")),
+        }
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self,
+                                                 _name: &'static str,
+                                                 len: usize,
+                                                 visitor: V)
+                                                 -> result::Result<V::Value, TypeError> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, TypeError> {
+        let this = self.look_through_variants();
+        match this.value {
+            Value::ContainerValue(ContainerValue::Dict(entries)) => {
+                visitor.visit_map(MapAccess::new(entries))
+            }
+            _ => Err(this.expected("a dict")),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self,
+                                           _name: &'static str,
+                                           fields: &'static [&'static str],
+                                           visitor: V)
+                                           -> result::Result<V::Value, TypeError> {
+        let this = self.look_through_variants();
+        match this.value {
+            Value::ContainerValue(ContainerValue::Dict(entries)) => {
+                visitor.visit_map(MapAccess::new(entries))
+            }
+            value => Deserializer::new(value).deserialize_tuple(fields.len(), visitor),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self,
+                                         _name: &'static str,
+                                         _variants: &'static [&'static str],
+                                         visitor: V)
+                                         -> result::Result<V::Value, TypeError> {
+        let this = self.look_through_variants();
+        let mismatch = this.expected("an enum variant name or a `(sv)` struct");
+        match this.value {
+            Value::BasicValue(BasicValue::String(variant)) => {
+                visitor.visit_enum(EnumAccess {
+                    variant: variant.into_owned(),
+                    value: None,
+                })
+            }
+            Value::ContainerValue(ContainerValue::Struct(fields)) => {
+                let mut fields = fields.into_iter();
+                match (fields.next(), fields.next(), fields.next()) {
+                    (Some(Value::BasicValue(BasicValue::String(variant))),
+                     Some(Value::ContainerValue(ContainerValue::Variant(_, value))),
+                     None) => {
+                        visitor.visit_enum(EnumAccess {
+                            variant: variant.into_owned(),
+                            value: Some(*value),
+                        })
+                    }
+                    _ => Err(mismatch),
+                }
+            }
+            _ => Err(mismatch),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self,
+                                               visitor: V)
+                                               -> result::Result<V::Value, TypeError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self,
+                                                visitor: V)
+                                                -> result::Result<V::Value, TypeError> {
+        visitor.visit_unit()
+    }
+}
+
+struct SeqAccess {
+    elems: vec::IntoIter<Value>,
+    index: usize,
+    segment: fn(usize) -> PathSegment,
+}
+
+impl SeqAccess {
+    fn new(elems: Vec<Value>, segment: fn(usize) -> PathSegment) -> Self {
+        SeqAccess {
+            elems: elems.into_iter(),
+            index: 0,
+            segment: segment,
+        }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = TypeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> result::Result<Option<T::Value>, TypeError>
+        where T: DeserializeSeed<'de>
+    {
+        match self.elems.next() {
+            Some(elem) => {
+                let segment = (self.segment)(self.index);
+                self.index += 1;
+                seed.deserialize(Deserializer::new(elem)).map(Some).map_err(|err| err.at(segment))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elems.len())
+    }
+}
+
+struct MapAccess {
+    entries: vec::IntoIter<(BasicValue, Value)>,
+    index: usize,
+    value: Option<Value>,
+}
+
+impl MapAccess {
+    fn new(entries: Vec<(BasicValue, Value)>) -> Self {
+        MapAccess {
+            entries: entries.into_iter(),
+            index: 0,
+            value: None,
+        }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = TypeError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> result::Result<Option<K::Value>, TypeError>
+        where K: DeserializeSeed<'de>
+    {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.index += 1;
+                self.value = Some(value);
+                seed.deserialize(Deserializer::new(Value::BasicValue(key)))
+                    .map(Some)
+                    .map_err(|err| err.at(PathSegment::Key(self.index - 1)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> result::Result<V::Value, TypeError>
+        where V: DeserializeSeed<'de>
+    {
+        let value = self.value.take().expect("next_value_seed called before next_key_seed");
+        seed.deserialize(Deserializer::new(value))
+            .map_err(|err| err.at(PathSegment::Value(self.index - 1)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumAccess {
+    variant: String,
+    value: Option<Value>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = TypeError;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V>(self, seed: V) -> result::Result<(V::Value, VariantAccess), TypeError>
+        where V: DeserializeSeed<'de>
+    {
+        let variant: de::value::StringDeserializer<TypeError> = self.variant.into_deserializer();
+        let variant = seed.deserialize(variant)?;
+        Ok((variant, VariantAccess { value: self.value }))
+    }
+}
+
+struct VariantAccess {
+    value: Option<Value>,
+}
+
+impl VariantAccess {
+    fn contents(self) -> result::Result<Deserializer, TypeError> {
+        match self.value {
+            Some(value) => Ok(Deserializer::new(value)),
+            None => {
+                Err(TypeError::new(TypeErrorKind::Invalid("Expected an enum variant with \
+                                                           contents, found a bare variant name"
+                    .to_owned())))
+            }
+        }
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = TypeError;
+
+    // Unit variants are written with an empty string as their contents, but
+    // whatever they hold is ignored, as is a bare variant name.
+    fn unit_variant(self) -> result::Result<(), TypeError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> result::Result<T::Value, TypeError>
+        where T: DeserializeSeed<'de>
+    {
+        seed.deserialize(self.contents()?).map_err(|err| err.at(PathSegment::Variant))
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> result::Result<V::Value, TypeError>
+        where V: Visitor<'de>
+    {
+        de::Deserializer::deserialize_tuple(self.contents()?, len, visitor)
+            .map_err(|err| err.at(PathSegment::Variant))
+    }
+
+    fn struct_variant<V>(self,
+                         fields: &'static [&'static str],
+                         visitor: V)
+                         -> result::Result<V::Value, TypeError>
+        where V: Visitor<'de>
+    {
+        de::Deserializer::deserialize_struct(self.contents()?, "", fields, visitor)
+            .map_err(|err| err.at(PathSegment::Variant))
+    }
+}
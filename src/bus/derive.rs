@@ -0,0 +1,161 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fmt::Debug;
+use std::result;
+use std::vec;
+
+use bus::convert::{DBusType, FromDBus, ToDBus};
+use bus::types::Type;
+use bus::variant::Variant;
+use bus::wire::{BasicValue, ContainerValue, PathSegment, TypeError, TypeErrorKind, Value};
+
+fn mismatch<T: DBusType>(value: &Value) -> TypeError {
+    TypeError::new(TypeErrorKind::Mismatch {
+        expected: T::dbus_type(),
+        found: value.kind_name(),
+    })
+}
+
+/// Unpacks the fields of a value that should be a struct of type `T` with
+/// `len` fields.
+pub fn struct_fields<T: DBusType>(value: Value,
+                                  len: usize)
+                                  -> result::Result<vec::IntoIter<Value>, TypeError> {
+    struct_fields_of(value, T::dbus_type(), len)
+}
+
+/// Like `struct_fields`, for a struct type without a Rust type of its own,
+/// such as the contents of an enum variant.
+pub fn struct_fields_of(value: Value,
+                        expected: Type,
+                        len: usize)
+                        -> result::Result<vec::IntoIter<Value>, TypeError> {
+    match value {
+        Value::ContainerValue(ContainerValue::Struct(fields)) => {
+            if fields.len() != len {
+                return Err(TypeError::new(TypeErrorKind::Arity {
+                    expected: len,
+                    found: fields.len(),
+                }));
+            }
+            Ok(fields.into_iter())
+        }
+        value => {
+            Err(TypeError::new(TypeErrorKind::Mismatch {
+                expected: expected,
+                found: value.kind_name(),
+            }))
+        }
+    }
+}
+
+/// Converts the next of the fields unpacked by `struct_fields`.
+pub fn field<T: FromDBus>(fields: &mut vec::IntoIter<Value>,
+                          index: usize)
+                          -> result::Result<T, TypeError> {
+    // `struct_fields` has checked the arity.
+    T::from_dbus(fields.next().unwrap()).map_err(|err| err.at(PathSegment::Field(index)))
+}
+
+/// An entry of an `a{sv}` dict.
+pub fn property<T: ToDBus + ?Sized>(name: &str, value: &T) -> (BasicValue, Value) {
+    (BasicValue::String(name.to_owned().into()),
+     Value::ContainerValue(ContainerValue::Variant(T::dbus_type(), Box::new(value.to_dbus()))))
+}
+
+/// The entries of an `a{sv}` dict being converted to a struct of type `T`.
+pub struct Properties {
+    entries: Vec<Option<(String, Value)>>,
+}
+
+pub fn properties<T: DBusType>(value: Value) -> result::Result<Properties, TypeError> {
+    let entries = match value {
+        Value::ContainerValue(ContainerValue::Dict(entries)) => entries,
+        value => return Err(mismatch::<T>(&value)),
+    };
+    let mut props = Vec::with_capacity(entries.len());
+    for (i, (key, value)) in entries.into_iter().enumerate() {
+        match key {
+            BasicValue::String(key) => props.push(Some((key.into_owned(), value))),
+            key => {
+                return Err(mismatch::<String>(&Value::BasicValue(key)).at(PathSegment::Key(i)))
+            }
+        }
+    }
+    Ok(Properties { entries: props })
+}
+
+impl Properties {
+    /// Converts the property named `name`, if present. Properties that
+    /// aren't taken are ignored.
+    pub fn take<T: FromDBus>(&mut self, name: &str) -> result::Result<Option<T>, TypeError> {
+        let i = match self.entries
+            .iter()
+            .position(|entry| entry.as_ref().map_or(false, |&(ref key, _)| key == name)) {
+            Some(i) => i,
+            None => return Ok(None),
+        };
+        let value = self.entries[i].take().unwrap().1;
+        Variant::from_dbus(value)
+            .and_then(Variant::take)
+            .map(Some)
+            .map_err(|err| err.at(PathSegment::Value(i)))
+    }
+
+    /// Like `take`, but a missing property is an error.
+    pub fn require<T: FromDBus>(&mut self, name: &str) -> result::Result<T, TypeError> {
+        match self.take(name)? {
+            Some(value) => Ok(value),
+            None => {
+                Err(TypeError::new(TypeErrorKind::Invalid(format!("Missing property `{}`",
+                                                                  name))))
+            }
+        }
+    }
+}
+
+/// A tagged enum value: a struct of the tag and a variant of the contents,
+/// which are of type `contents_ty`.
+pub fn tagged_value(tag: Value, contents_ty: Type, contents: Value) -> Value {
+    Value::ContainerValue(ContainerValue::Struct(vec![
+        tag,
+        Value::ContainerValue(ContainerValue::Variant(contents_ty, Box::new(contents))),
+    ]))
+}
+
+/// The contents of a tagged enum's unit variants, an empty string, since a
+/// variant must hold something.
+pub fn unit_contents() -> (Type, Value) {
+    (String::dbus_type(), Value::BasicValue(BasicValue::String("".into())))
+}
+
+/// Splits a tagged enum value of type `T` into its tag and contents.
+pub fn tagged<T: DBusType>(value: Value) -> result::Result<(Value, Value), TypeError> {
+    let mismatch = mismatch::<T>(&value);
+    let mut fields = match value {
+        Value::ContainerValue(ContainerValue::Struct(fields)) => fields.into_iter(),
+        _ => return Err(mismatch),
+    };
+    match (fields.next(), fields.next(), fields.next()) {
+        (Some(tag), Some(Value::ContainerValue(ContainerValue::Variant(_, contents))), None) => {
+            Ok((tag, *contents))
+        }
+        _ => Err(mismatch),
+    }
+}
+
+pub fn in_tag(err: TypeError) -> TypeError {
+    err.at(PathSegment::Field(0))
+}
+
+pub fn in_contents(err: TypeError) -> TypeError {
+    err.at(PathSegment::Variant).at(PathSegment::Field(1))
+}
+
+pub fn unknown_variant<D: Debug>(enum_name: &str, tag: D) -> TypeError {
+    TypeError::new(TypeErrorKind::Invalid(format!("Unknown discriminant {:?} for enum `{}`",
+                                                  tag,
+                                                  enum_name)))
+}
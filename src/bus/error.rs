@@ -0,0 +1,249 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, ErrorKind};
+
+use auth::AuthError;
+use bus::convert::{FromDBus, ToDBus};
+use bus::message::{Message, MessageType};
+use bus::names::ErrorName;
+use bus::wire::TypeError;
+
+macro_rules! standard_errors {
+    ($($(#[$attr:meta])* $kind:ident => $name:expr,)*) => {
+        /// The errors named in `org.freedesktop.DBus.Error`, which the bus
+        /// and most services use.
+        #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+        pub enum StandardError {
+            $($(#[$attr])* $kind,)*
+        }
+
+        impl StandardError {
+            /// The full error name, such as
+            /// `org.freedesktop.DBus.Error.ServiceUnknown`.
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    $(StandardError::$kind => concat!("org.freedesktop.DBus.Error.", $name),)*
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $(concat!("org.freedesktop.DBus.Error.", $name) => {
+                        Some(StandardError::$kind)
+                    })*
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+standard_errors! {
+    Failed => "Failed",
+    NoMemory => "NoMemory",
+    /// No connection owns the name a message was sent to, and none could be
+    /// started for it.
+    ServiceUnknown => "ServiceUnknown",
+    NameHasNoOwner => "NameHasNoOwner",
+    /// A method call got no reply in time.
+    NoReply => "NoReply",
+    IoError => "IOError",
+    BadAddress => "BadAddress",
+    NotSupported => "NotSupported",
+    LimitsExceeded => "LimitsExceeded",
+    AccessDenied => "AccessDenied",
+    AuthFailed => "AuthFailed",
+    NoServer => "NoServer",
+    Timeout => "Timeout",
+    NoNetwork => "NoNetwork",
+    AddressInUse => "AddressInUse",
+    /// The connection closed.
+    Disconnected => "Disconnected",
+    InvalidArgs => "InvalidArgs",
+    FileNotFound => "FileNotFound",
+    FileExists => "FileExists",
+    UnknownMethod => "UnknownMethod",
+    UnknownObject => "UnknownObject",
+    UnknownInterface => "UnknownInterface",
+    UnknownProperty => "UnknownProperty",
+    PropertyReadOnly => "PropertyReadOnly",
+    TimedOut => "TimedOut",
+    MatchRuleNotFound => "MatchRuleNotFound",
+    MatchRuleInvalid => "MatchRuleInvalid",
+    UnixProcessIdUnknown => "UnixProcessIdUnknown",
+    InvalidSignature => "InvalidSignature",
+    InvalidFileContent => "InvalidFileContent",
+    SelinuxSecurityContextUnknown => "SELinuxSecurityContextUnknown",
+    AdtAuditDataUnknown => "AdtAuditDataUnknown",
+    ObjectPathInUse => "ObjectPathInUse",
+    InconsistentMessage => "InconsistentMessage",
+    InteractiveAuthorizationRequired => "InteractiveAuthorizationRequired",
+    NotContainer => "NotContainer",
+}
+
+impl Display for StandardError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// An error as D-Bus carries it: a name, such as
+/// `org.freedesktop.DBus.Error.UnknownMethod`, and an optional message for
+/// humans.
+///
+/// This is what failed method calls resolve to. It converts to and from
+/// `io::Error` and `AuthError`, so one `?` covers connecting and calling; an
+/// `io::Error` made from a `DBusError` converts back to the same error.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DBusError {
+    name: ErrorName,
+    message: Option<String>,
+}
+
+impl DBusError {
+    pub fn new(name: ErrorName, message: Option<String>) -> Self {
+        DBusError {
+            name: name,
+            message: message,
+        }
+    }
+
+    /// One of the standard errors, with a message.
+    pub fn standard<S>(kind: StandardError, message: S) -> Self
+        where S: Into<String>
+    {
+        DBusError::new(ErrorName::new(kind.name()).unwrap(), Some(message.into()))
+    }
+
+    /// The error an ERROR message carries. Its name is taken from the header
+    /// and its message from a string first argument, as is the convention.
+    ///
+    /// # Panics
+    ///
+    /// If `reply` is not an ERROR message.
+    pub fn from_reply(reply: &Message) -> Self {
+        assert_eq!(reply.message_type, MessageType::Error);
+        // The header is only required to hold some string.
+        let name = reply.fields
+            .error_name
+            .as_ref()
+            .and_then(|name| ErrorName::new(name.clone()).ok())
+            .unwrap_or_else(|| ErrorName::new(StandardError::Failed.name()).unwrap());
+        let message = match reply.body().ok().and_then(|body| body.into_iter().next()) {
+            Some(value) => String::from_dbus(value).ok(),
+            None => None,
+        };
+        DBusError::new(name, message)
+    }
+
+    /// An ERROR message replying to `call` with this error.
+    pub fn to_reply(&self, call: &Message) -> Message {
+        let mut reply = Message::error(call, self.name.as_str());
+        if let Some(ref message) = self.message {
+            reply.set_body(&[message.to_dbus()]).expect("a string always marshals");
+        }
+        reply
+    }
+
+    pub fn name(&self) -> &ErrorName {
+        &self.name
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_ref().map(|message| &message[..])
+    }
+
+    /// Which standard error this is, if any.
+    pub fn standard_kind(&self) -> Option<StandardError> {
+        StandardError::from_name(&self.name)
+    }
+}
+
+impl Display for DBusError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.message {
+            Some(ref message) => write!(f, "{}: {}", self.name, message),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl error::Error for DBusError {
+    fn description(&self) -> &str {
+        match self.message {
+            Some(ref message) => message,
+            None => &self.name,
+        }
+    }
+}
+
+impl From<DBusError> for io::Error {
+    fn from(err: DBusError) -> Self {
+        let kind = match err.standard_kind() {
+            Some(StandardError::AccessDenied) |
+            Some(StandardError::AuthFailed) => ErrorKind::PermissionDenied,
+            Some(StandardError::FileNotFound) => ErrorKind::NotFound,
+            Some(StandardError::FileExists) => ErrorKind::AlreadyExists,
+            Some(StandardError::NoReply) |
+            Some(StandardError::Timeout) |
+            Some(StandardError::TimedOut) => ErrorKind::TimedOut,
+            Some(StandardError::AddressInUse) => ErrorKind::AddrInUse,
+            Some(StandardError::Disconnected) => ErrorKind::BrokenPipe,
+            Some(StandardError::InvalidArgs) |
+            Some(StandardError::InvalidSignature) => ErrorKind::InvalidInput,
+            _ => ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+impl From<io::Error> for DBusError {
+    fn from(err: io::Error) -> Self {
+        let kind = match err.kind() {
+            ErrorKind::PermissionDenied => StandardError::AccessDenied,
+            ErrorKind::NotFound => StandardError::FileNotFound,
+            ErrorKind::AlreadyExists => StandardError::FileExists,
+            ErrorKind::TimedOut => StandardError::Timeout,
+            ErrorKind::AddrInUse => StandardError::AddressInUse,
+            ErrorKind::BrokenPipe |
+            ErrorKind::ConnectionReset |
+            ErrorKind::ConnectionAborted |
+            ErrorKind::NotConnected |
+            ErrorKind::UnexpectedEof => StandardError::Disconnected,
+            ErrorKind::InvalidInput => StandardError::InvalidArgs,
+            _ => StandardError::IoError,
+        };
+        let is_dbus_error = err.get_ref().map_or(false, |inner| inner.is::<DBusError>());
+        if is_dbus_error {
+            let inner = err.into_inner().unwrap();
+            return *inner.downcast::<DBusError>().unwrap();
+        }
+        DBusError::standard(kind, err.to_string())
+    }
+}
+
+/// For method handlers: arguments of the wrong type are `InvalidArgs`.
+impl From<TypeError> for DBusError {
+    fn from(err: TypeError) -> Self {
+        DBusError::standard(StandardError::InvalidArgs, err.to_string())
+    }
+}
+
+impl From<AuthError> for DBusError {
+    fn from(err: AuthError) -> Self {
+        match err {
+            AuthError::Io(err) => err.into(),
+            err => DBusError::standard(StandardError::AuthFailed, err.to_string()),
+        }
+    }
+}
+
+impl From<DBusError> for AuthError {
+    fn from(err: DBusError) -> Self {
+        AuthError::Io(err.into())
+    }
+}
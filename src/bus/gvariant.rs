@@ -0,0 +1,532 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The GVariant serialization format, used by GLib for GSettings, GVDB
+//! files and GDBus peer connections.
+//!
+//! Unlike the D-Bus format, GVariant data carries no lengths. Fixed-size
+//! values are packed back to back, and containers of variable-size values
+//! record where each one ends in a table of framing offsets at the end of
+//! the container. Offsets are always little-endian, and take 1, 2, 4 or 8
+//! bytes each, the fewest that can address the whole container.
+//!
+//! Alignment is relative to the start of the data, which the decoder
+//! assumes was itself 8-byte aligned. Data that isn't in normal form, such
+//! as fixed-size values of the wrong length or nonzero padding, is rejected
+//! rather than read as default values as GLib does.
+
+use std::borrow::Cow;
+use std::cmp;
+use std::io::{Error, ErrorKind, Result};
+use std::str;
+
+use bus::marshal::Endianness;
+use bus::types::{self, BasicType, ContainerType, Depth, Type};
+use bus::wire::{BasicValue, ContainerValue, Value};
+
+/// Serializes `value` as GVariant data of type `ty`, appending it to
+/// `output`. Alignment is computed from where the value starts, not from the
+/// start of `output`.
+pub fn encode_gvariant(value: &Value,
+                       ty: &Type,
+                       endianness: Endianness,
+                       output: &mut Vec<u8>)
+                       -> Result<()> {
+    value.check_type(ty)?;
+
+    let start = output.len();
+    let mut encoder = Encoder {
+        output: output,
+        start: start,
+        endianness: endianness,
+    };
+    encoder.value(value, ty);
+    Ok(())
+}
+
+/// Deserializes GVariant data of type `ty`, which must span all of `input`.
+pub fn decode_gvariant(input: &[u8], ty: &Type, endianness: Endianness) -> Result<Value> {
+    let decoder = Decoder {
+        input: input,
+        endianness: endianness,
+    };
+    decoder.value(ty, 0, input.len(), Depth::new())
+}
+
+fn malformed(what: &str) -> Error {
+    Error::new(ErrorKind::InvalidData,
+               format!("malformed GVariant data: {}", what))
+}
+
+fn align_up(pos: usize, alignment: usize) -> usize {
+    (pos + alignment - 1) / alignment * alignment
+}
+
+fn basic_alignment(ty: &BasicType) -> usize {
+    match *ty {
+        BasicType::Byte | BasicType::Bool | BasicType::String | BasicType::ObjectPath |
+        BasicType::Signature => 1,
+        BasicType::Int16 | BasicType::UInt16 => 2,
+        BasicType::Int32 | BasicType::UInt32 | BasicType::UnixFd => 4,
+        BasicType::Int64 | BasicType::UInt64 | BasicType::Double => 8,
+    }
+}
+
+fn alignment(ty: &Type) -> usize {
+    match *ty {
+        Type::BasicType(ref basic_ty) => basic_alignment(basic_ty),
+        Type::ContainerType(ref container_ty) => {
+            match **container_ty {
+                ContainerType::Array(ref elem_ty) => alignment(elem_ty),
+                ContainerType::Dict(ref key_ty, ref value_ty) => {
+                    cmp::max(basic_alignment(key_ty), alignment(value_ty))
+                }
+                ContainerType::Struct(ref field_tys) => tuple_alignment(field_tys),
+                ContainerType::Variant => 8,
+                #[cfg(feature = "maybe")]
+                ContainerType::Maybe(ref inner_ty) => alignment(inner_ty),
+            }
+        }
+    }
+}
+
+fn tuple_alignment(tys: &[Type]) -> usize {
+    tys.iter().map(alignment).max().unwrap_or(1)
+}
+
+// The size of every value of type `ty`, if they all have the same size.
+fn fixed_size(ty: &Type) -> Option<usize> {
+    match *ty {
+        Type::BasicType(BasicType::String) |
+        Type::BasicType(BasicType::ObjectPath) |
+        Type::BasicType(BasicType::Signature) => None,
+        Type::BasicType(ref basic_ty) => Some(basic_alignment(basic_ty)),
+        Type::ContainerType(ref container_ty) => {
+            match **container_ty {
+                ContainerType::Struct(ref field_tys) => tuple_fixed_size(field_tys),
+                _ => None,
+            }
+        }
+    }
+}
+
+// Structs and dict entries are fixed-size if all their fields are, padded at
+// the end to their alignment so that arrays of them stay aligned.
+fn tuple_fixed_size(tys: &[Type]) -> Option<usize> {
+    let mut size = 0;
+    for ty in tys {
+        size = align_up(size, alignment(ty)) + fixed_size(ty)?;
+    }
+    Some(align_up(size, tuple_alignment(tys)))
+}
+
+fn dict_entry_types(key_ty: &BasicType, value_ty: &Type) -> [Type; 2] {
+    [Type::BasicType(key_ty.clone()), value_ty.clone()]
+}
+
+// The size of each framing offset in a container of `len` bytes, offsets
+// included.
+fn offset_size(len: usize) -> usize {
+    if len <= 0xff {
+        1
+    } else if len <= 0xffff {
+        2
+    } else if len as u64 <= 0xffff_ffff {
+        4
+    } else {
+        8
+    }
+}
+
+struct Encoder<'a> {
+    output: &'a mut Vec<u8>,
+    // Where the value being encoded starts, which alignment is relative to.
+    start: usize,
+    endianness: Endianness,
+}
+
+impl<'a> Encoder<'a> {
+    fn pos(&self) -> usize {
+        self.output.len() - self.start
+    }
+
+    fn align(&mut self, alignment: usize) {
+        while self.pos() % alignment != 0 {
+            self.output.push(0);
+        }
+    }
+
+    // The value is expected to have been checked against `ty` already.
+    fn value(&mut self, value: &Value, ty: &Type) {
+        match (value, ty) {
+            (&Value::BasicValue(ref basic), _) => self.basic_value(basic),
+            (&Value::ContainerValue(ref container), &Type::ContainerType(ref container_ty)) => {
+                self.align(alignment(ty));
+                self.container_value(container, container_ty)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn basic_value(&mut self, value: &BasicValue) {
+        self.align(basic_alignment(&value.basic_type()));
+        let endianness = self.endianness;
+        match *value {
+            BasicValue::Byte(n) => self.output.push(n),
+            BasicValue::Bool(b) => self.output.push(b as u8),
+            BasicValue::Int16(n) => endianness.write_u16(n as u16, self.output),
+            BasicValue::UInt16(n) => endianness.write_u16(n, self.output),
+            BasicValue::Int32(n) => endianness.write_u32(n as u32, self.output),
+            BasicValue::UInt32(n) |
+            BasicValue::UnixFd(n) => endianness.write_u32(n, self.output),
+            BasicValue::Int64(n) => endianness.write_u64(n as u64, self.output),
+            BasicValue::UInt64(n) => endianness.write_u64(n, self.output),
+            BasicValue::Double(n) => endianness.write_u64(n.to_bits(), self.output),
+            BasicValue::String(ref s) => self.string(s.as_bytes()),
+            BasicValue::ObjectPath(ref path) => self.string(path),
+            BasicValue::Signature(ref signature) => {
+                self.string(signature.to_string().as_bytes())
+            }
+        }
+    }
+
+    fn string(&mut self, bytes: &[u8]) {
+        self.output.extend_from_slice(bytes);
+        self.output.push(0);
+    }
+
+    fn container_value(&mut self, value: &ContainerValue, ty: &ContainerType) {
+        let start = self.pos();
+        match (value, ty) {
+            (&ContainerValue::Array(ref elems), &ContainerType::Array(ref elem_ty)) => {
+                let fixed = fixed_size(elem_ty).is_some();
+                let mut ends = Vec::new();
+                for elem in elems {
+                    self.value(elem, elem_ty);
+                    if !fixed {
+                        ends.push(self.pos() - start);
+                    }
+                }
+                self.offsets(start, &ends);
+            }
+            (&ContainerValue::Dict(ref entries),
+             &ContainerType::Dict(ref key_ty, ref value_ty)) => {
+                let entry_tys = dict_entry_types(key_ty, value_ty);
+                let fixed = tuple_fixed_size(&entry_tys).is_some();
+                let mut ends = Vec::new();
+                for &(ref key, ref value) in entries {
+                    self.align(tuple_alignment(&entry_tys));
+                    self.tuple(&entry_tys, |this, i| if i == 0 {
+                        this.basic_value(key)
+                    } else {
+                        this.value(value, value_ty)
+                    });
+                    if !fixed {
+                        ends.push(self.pos() - start);
+                    }
+                }
+                self.offsets(start, &ends);
+            }
+            (&ContainerValue::Struct(ref fields), &ContainerType::Struct(ref field_tys)) => {
+                self.tuple(field_tys, |this, i| this.value(&fields[i], &field_tys[i]));
+            }
+            (&ContainerValue::Variant(ref inner_ty, ref inner), &ContainerType::Variant) => {
+                self.value(inner, inner_ty);
+                self.output.push(0);
+                self.output.extend_from_slice(inner_ty.to_string().as_bytes());
+            }
+            #[cfg(feature = "maybe")]
+            (&ContainerValue::Maybe(ref inner), &ContainerType::Maybe(ref inner_ty)) => {
+                if let Some(ref inner) = *inner {
+                    self.value(inner, inner_ty);
+                    // A trailing byte tells an empty variable-size value
+                    // apart from nothing.
+                    if fixed_size(inner_ty).is_none() {
+                        self.output.push(0);
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // Writes the fields of a struct or dict entry, starting at an aligned
+    // position. Only the ends of variable-size fields other than the last
+    // need framing offsets, and they're stored last field first.
+    fn tuple<F: FnMut(&mut Self, usize)>(&mut self, tys: &[Type], mut field: F) {
+        let start = self.pos();
+        let mut ends = Vec::new();
+        for (i, ty) in tys.iter().enumerate() {
+            field(self, i);
+            if fixed_size(ty).is_none() && i + 1 < tys.len() {
+                ends.push(self.pos() - start);
+            }
+        }
+        if tuple_fixed_size(tys).is_some() {
+            self.align(tuple_alignment(tys));
+        } else {
+            ends.reverse();
+            self.offsets(start, &ends);
+        }
+    }
+
+    fn offsets(&mut self, start: usize, ends: &[usize]) {
+        if ends.is_empty() {
+            return;
+        }
+        let body_len = self.pos() - start;
+        let size = [1, 2, 4]
+            .iter()
+            .cloned()
+            .find(|&size| offset_size(body_len + ends.len() * size) == size)
+            .unwrap_or(8);
+        for &end in ends {
+            for i in 0..size {
+                self.output.push((end as u64 >> (8 * i)) as u8);
+            }
+        }
+    }
+}
+
+struct Decoder<'a> {
+    input: &'a [u8],
+    endianness: Endianness,
+}
+
+// Values are decoded from a range of `input`, whose bounds come from the
+// framing offsets and fixed sizes of the containers around it.
+impl<'a> Decoder<'a> {
+    fn value(&self, ty: &Type, start: usize, end: usize, depth: Depth) -> Result<Value> {
+        match *ty {
+            Type::BasicType(ref basic_ty) => {
+                self.basic_value(basic_ty, start, end).map(Value::BasicValue)
+            }
+            Type::ContainerType(ref container_ty) => {
+                self.container_value(container_ty, start, end, depth).map(Value::ContainerValue)
+            }
+        }
+    }
+
+    fn basic_value(&self, ty: &BasicType, start: usize, end: usize) -> Result<BasicValue> {
+        let bytes = &self.input[start..end];
+        if let Some(size) = fixed_size(&Type::BasicType(ty.clone())) {
+            if bytes.len() != size {
+                return Err(malformed("fixed-size value has the wrong length"));
+            }
+        }
+        let endianness = self.endianness;
+        Ok(match *ty {
+            BasicType::Byte => BasicValue::Byte(bytes[0]),
+            BasicType::Bool => {
+                match bytes[0] {
+                    0 => BasicValue::Bool(false),
+                    1 => BasicValue::Bool(true),
+                    _ => return Err(malformed("boolean is neither 0 nor 1")),
+                }
+            }
+            BasicType::Int16 => BasicValue::Int16(endianness.read_u16(bytes) as i16),
+            BasicType::UInt16 => BasicValue::UInt16(endianness.read_u16(bytes)),
+            BasicType::Int32 => BasicValue::Int32(endianness.read_u32(bytes) as i32),
+            BasicType::UInt32 => BasicValue::UInt32(endianness.read_u32(bytes)),
+            BasicType::Int64 => BasicValue::Int64(endianness.read_u64(bytes) as i64),
+            BasicType::UInt64 => BasicValue::UInt64(endianness.read_u64(bytes)),
+            BasicType::Double => BasicValue::Double(f64::from_bits(endianness.read_u64(bytes))),
+            BasicType::String => BasicValue::String(Cow::Owned(nul_terminated(bytes)?.to_owned())),
+            BasicType::ObjectPath => {
+                BasicValue::ObjectPath(Cow::Owned(nul_terminated(bytes)?.as_bytes().to_vec()))
+            }
+            BasicType::Signature => {
+                BasicValue::Signature(types::validate_signature(nul_terminated(bytes)?.as_bytes())?)
+            }
+            BasicType::UnixFd => BasicValue::UnixFd(endianness.read_u32(bytes)),
+        })
+    }
+
+    fn container_value(&self,
+                       ty: &ContainerType,
+                       start: usize,
+                       end: usize,
+                       depth: Depth)
+                       -> Result<ContainerValue> {
+        Ok(match *ty {
+            ContainerType::Array(ref elem_ty) => {
+                let depth = depth.enter_array(start)?;
+                let ranges = self.elements(start, end, fixed_size(elem_ty), alignment(elem_ty))?;
+                let mut elems = Vec::with_capacity(ranges.len());
+                for (elem_start, elem_end) in ranges {
+                    elems.push(self.value(elem_ty, elem_start, elem_end, depth)?);
+                }
+                ContainerValue::Array(elems)
+            }
+            ContainerType::Dict(ref key_ty, ref value_ty) => {
+                let depth = depth.enter_array(start)?.enter_struct(start)?;
+                let entry_tys = dict_entry_types(key_ty, value_ty);
+                let ranges = self.elements(start,
+                                   end,
+                                   tuple_fixed_size(&entry_tys),
+                                   tuple_alignment(&entry_tys))?;
+                let mut entries = Vec::with_capacity(ranges.len());
+                for (entry_start, entry_end) in ranges {
+                    let mut fields = self.tuple(&entry_tys, entry_start, entry_end, depth)?
+                        .into_iter();
+                    match (fields.next(), fields.next()) {
+                        (Some(Value::BasicValue(key)), Some(value)) => entries.push((key, value)),
+                        _ => unreachable!(),
+                    }
+                }
+                ContainerValue::Dict(entries)
+            }
+            ContainerType::Struct(ref field_tys) => {
+                let depth = depth.enter_struct(start)?;
+                ContainerValue::Struct(self.tuple(field_tys, start, end, depth)?)
+            }
+            ContainerType::Variant => {
+                let depth = depth.enter_variant(start)?;
+                // The type follows the last nul byte, which type strings
+                // can't contain.
+                let sep = match self.input[start..end].iter().rposition(|&b| b == 0) {
+                    Some(sep) => start + sep,
+                    None => return Err(malformed("variant has no type")),
+                };
+                let inner_ty = types::validate_gvariant_type(&self.input[sep + 1..end])?;
+                let inner = self.value(&inner_ty, start, sep, depth)?;
+                ContainerValue::Variant(inner_ty, Box::new(inner))
+            }
+            #[cfg(feature = "maybe")]
+            ContainerType::Maybe(ref inner_ty) => {
+                let depth = depth.enter_array(start)?;
+                if start == end {
+                    return Ok(ContainerValue::Maybe(None));
+                }
+                let inner_end = match fixed_size(inner_ty) {
+                    Some(_) => end,
+                    None if self.input[end - 1] == 0 => end - 1,
+                    None => return Err(malformed("maybe value lacks its trailing nul byte")),
+                };
+                ContainerValue::Maybe(Some(Box::new(self.value(inner_ty,
+                                                               start,
+                                                               inner_end,
+                                                               depth)?)))
+            }
+        })
+    }
+
+    // Skips the zero padding before a value with the given alignment,
+    // returning where the value starts.
+    fn skip_padding(&self, pos: usize, alignment: usize, limit: usize) -> Result<usize> {
+        let aligned = align_up(pos, alignment);
+        if aligned > limit {
+            return Err(malformed("value extends past the end of its container"));
+        }
+        if self.input[pos..aligned].iter().any(|&b| b != 0) {
+            return Err(malformed("nonzero alignment padding"));
+        }
+        Ok(aligned)
+    }
+
+    fn offset(&self, pos: usize, size: usize) -> usize {
+        self.input[pos..pos + size]
+            .iter()
+            .rev()
+            .fold(0u64, |n, &b| n << 8 | b as u64) as usize
+    }
+
+    // Splits an array into the ranges of its elements.
+    fn elements(&self,
+                start: usize,
+                end: usize,
+                fixed_size: Option<usize>,
+                alignment: usize)
+                -> Result<Vec<(usize, usize)>> {
+        let len = end - start;
+        if let Some(size) = fixed_size {
+            if len % size != 0 {
+                return Err(malformed("array length is not a multiple of its element size"));
+            }
+            return Ok((0..len / size)
+                .map(|i| (start + i * size, start + (i + 1) * size))
+                .collect());
+        }
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+
+        // The last offset marks the end of the last element, which is where
+        // the offsets begin.
+        let size = offset_size(len);
+        if len < size {
+            return Err(malformed("array is too short for its framing offsets"));
+        }
+        let offsets_start = start + self.offset(end - size, size);
+        if offsets_start >= end || (end - offsets_start) % size != 0 {
+            return Err(malformed("framing offset out of range"));
+        }
+        let mut ranges = Vec::with_capacity((end - offsets_start) / size);
+        let mut pos = start;
+        for offset_pos in (offsets_start..end).step_by(size) {
+            let elem_start = self.skip_padding(pos, alignment, offsets_start)?;
+            let elem_end = start + self.offset(offset_pos, size);
+            if elem_end < elem_start || elem_end > offsets_start {
+                return Err(malformed("framing offset out of range"));
+            }
+            ranges.push((elem_start, elem_end));
+            pos = elem_end;
+        }
+        Ok(ranges)
+    }
+
+    // Decodes the fields of a struct or dict entry.
+    fn tuple(&self, tys: &[Type], start: usize, end: usize, depth: Depth) -> Result<Vec<Value>> {
+        let len = end - start;
+        let fixed = tuple_fixed_size(tys);
+        if fixed.map_or(false, |size| size != len) {
+            return Err(malformed("fixed-size struct has the wrong length"));
+        }
+
+        // Framing offsets are read from the end, and the last one read marks
+        // the start of the offset table.
+        let size = offset_size(len);
+        let mut offsets_start = end;
+        let mut fields = Vec::with_capacity(tys.len());
+        let mut pos = start;
+        for (i, ty) in tys.iter().enumerate() {
+            let field_start = self.skip_padding(pos, alignment(ty), offsets_start)?;
+            let field_end = match fixed_size(ty) {
+                Some(field_size) => field_start + field_size,
+                None if i + 1 == tys.len() => offsets_start,
+                None => {
+                    if offsets_start - start < size {
+                        return Err(malformed("struct is too short for its framing offsets"));
+                    }
+                    offsets_start -= size;
+                    start + self.offset(offsets_start, size)
+                }
+            };
+            if field_end < field_start || field_end > offsets_start {
+                return Err(malformed("framing offset out of range"));
+            }
+            fields.push(self.value(ty, field_start, field_end, depth)?);
+            pos = field_end;
+        }
+
+        if fixed.is_some() {
+            self.skip_padding(pos, tuple_alignment(tys), end)?;
+        } else if pos != offsets_start {
+            return Err(malformed("struct has bytes after its last field"));
+        }
+        Ok(fields)
+    }
+}
+
+fn nul_terminated(bytes: &[u8]) -> Result<&str> {
+    match bytes.split_last() {
+        Some((&0, contents)) => {
+            if contents.contains(&0) {
+                return Err(malformed("string contains a nul byte"));
+            }
+            str::from_utf8(contents).map_err(|_| malformed("string is not valid UTF-8"))
+        }
+        _ => Err(malformed("string is not nul-terminated")),
+    }
+}
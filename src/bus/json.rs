@@ -0,0 +1,559 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Conversion between values and JSON, guided by their D-Bus types.
+//!
+//! * Booleans, numbers and strings map to their JSON counterparts. Object
+//!   paths and signatures are strings, and Unix fds are their indices.
+//! * 64-bit integers are numbers, or decimal strings with
+//!   `JsonOptions::with_lossless_int64`, since many JSON readers hold numbers
+//!   as doubles. Either form is accepted when reading.
+//! * Byte arrays, `ay`, are base64 strings, in the standard padded alphabet.
+//! * Other arrays and structs are arrays. Dicts are objects, with keys in
+//!   their text form, such as `"1"` or `"true"`.
+//! * A variant is an object holding its type and contents, as in
+//!   `{"signature": "u", "value": 3}`.
+//! * A maybe is `null` or its contents. The contents of a maybe nested in
+//!   another are wrapped in a one-element array, to keep `just nothing`
+//!   apart from `nothing`.
+//!
+//! JSON has no infinities or NaN, so doubles must be finite.
+
+use std::convert::TryFrom;
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{Error, ErrorKind};
+use std::result;
+
+use serde_json::{Map, Number, Value as Json};
+
+use bus::names::is_valid_object_path;
+use bus::types::{self, BasicType, ContainerType, Signature, Type};
+use bus::wire::{BasicValue, ContainerValue, Value};
+
+/// Options for converting values to JSON.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct JsonOptions {
+    lossless_int64: bool,
+}
+
+impl JsonOptions {
+    pub fn new() -> Self {
+        JsonOptions::default()
+    }
+
+    /// Writes `x` and `t` values as decimal strings, so that readers which
+    /// hold numbers as doubles don't round them.
+    pub fn with_lossless_int64(self, lossless: bool) -> Self {
+        JsonOptions { lossless_int64: lossless }
+    }
+
+    pub fn lossless_int64(&self) -> bool {
+        self.lossless_int64
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct JsonError {
+    /// Where in the JSON the conversion failed, such as `$.Devices[2]`.
+    pub path: String,
+    /// The D-Bus type of the value there.
+    pub expected: Type,
+    pub reason: String,
+}
+
+impl JsonError {
+    fn new<S: Into<String>>(expected: &Type, reason: S) -> Self {
+        JsonError {
+            path: "$".to_owned(),
+            expected: expected.clone(),
+            reason: reason.into(),
+        }
+    }
+
+    fn mismatch(expected: &Type, found: &Json) -> Self {
+        JsonError::new(expected,
+                       format!("expected {}, found {}", json_kind(expected), json_name(found)))
+    }
+
+    fn at_index(mut self, index: usize) -> Self {
+        self.path = format!("$[{}]{}", index, &self.path[1..]);
+        self
+    }
+
+    fn at_key(mut self, key: &str) -> Self {
+        let is_identifier = key.chars().next().map_or(false, |c| !c.is_ascii_digit()) &&
+                            key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        self.path = if is_identifier {
+            format!("$.{}{}", key, &self.path[1..])
+        } else {
+            let key = key.replace('\\', "\\\\").replace('\'', "\\'");
+            format!("$['{}']{}", key, &self.path[1..])
+        };
+        self
+    }
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f,
+               "Can't convert JSON at `{}` for D-Bus type `{}`: {}.",
+               self.path,
+               self.expected,
+               self.reason)
+    }
+}
+
+impl error::Error for JsonError {
+    fn description(&self) -> &str {
+        "JSON conversion failed"
+    }
+}
+
+impl From<JsonError> for Error {
+    fn from(err: JsonError) -> Self {
+        Error::new(ErrorKind::InvalidData, err)
+    }
+}
+
+type Result<T> = result::Result<T, JsonError>;
+
+/// Converts `value`, of type `ty`, to JSON.
+pub fn value_to_json(value: &Value, ty: &Type, options: JsonOptions) -> Result<Json> {
+    Writer { options: options }.value(value, ty)
+}
+
+/// Converts message arguments of the given signature to a JSON array.
+pub fn values_to_json(values: &[Value],
+                      signature: &Signature,
+                      options: JsonOptions)
+                      -> Result<Json> {
+    Writer { options: options }.fields(values, signature, &body_type(signature))
+}
+
+/// Reads a value of type `ty` from JSON.
+pub fn value_from_json(json: &Json, ty: &Type) -> Result<Value> {
+    Reader.value(json, ty)
+}
+
+/// Reads message arguments of the given signature from a JSON array.
+pub fn values_from_json(json: &Json, signature: &Signature) -> Result<Vec<Value>> {
+    Reader.fields(json, signature, &body_type(signature))
+}
+
+// Arguments convert like the fields of a struct, so errors about them
+// report that struct's type.
+fn body_type(signature: &Signature) -> Type {
+    Type::ContainerType(Box::new(ContainerType::Struct(signature.to_vec())))
+}
+
+struct Writer {
+    options: JsonOptions,
+}
+
+impl Writer {
+    fn value(&self, value: &Value, ty: &Type) -> Result<Json> {
+        let mismatch = || JsonError::new(ty, format!("found {}", value.kind_name()));
+        let container_ty = match (value, ty) {
+            (&Value::BasicValue(ref basic), &Type::BasicType(ref basic_ty)) => {
+                if basic.basic_type() != *basic_ty {
+                    return Err(mismatch());
+                }
+                return self.basic_value(basic, ty);
+            }
+            (&Value::ContainerValue(_), &Type::ContainerType(ref container_ty)) => container_ty,
+            _ => return Err(mismatch()),
+        };
+        match (value, &**container_ty) {
+            (&Value::ContainerValue(ContainerValue::Array(ref elems)),
+             &ContainerType::Array(Type::BasicType(BasicType::Byte))) => {
+                let mut bytes = Vec::with_capacity(elems.len());
+                for (i, elem) in elems.iter().enumerate() {
+                    match *elem {
+                        Value::BasicValue(BasicValue::Byte(b)) => bytes.push(b),
+                        _ => {
+                            let byte_ty = Type::BasicType(BasicType::Byte);
+                            let err = JsonError::new(&byte_ty,
+                                                     format!("found {}", elem.kind_name()));
+                            return Err(err.at_index(i));
+                        }
+                    }
+                }
+                Ok(Json::String(encode_base64(&bytes)))
+            }
+            (&Value::ContainerValue(ContainerValue::Array(ref elems)),
+             &ContainerType::Array(ref elem_ty)) => {
+                let mut array = Vec::with_capacity(elems.len());
+                for (i, elem) in elems.iter().enumerate() {
+                    array.push(self.value(elem, elem_ty).map_err(|err| err.at_index(i))?);
+                }
+                Ok(Json::Array(array))
+            }
+            (&Value::ContainerValue(ContainerValue::Dict(ref entries)),
+             &ContainerType::Dict(ref key_ty, ref value_ty)) => {
+                let mut object = Map::new();
+                for &(ref key, ref value) in entries {
+                    if key.basic_type() != *key_ty {
+                        let found = Type::BasicType(key.basic_type());
+                        return Err(JsonError::new(&Type::BasicType(key_ty.clone()),
+                                                  format!("found a key of type `{}`", found)));
+                    }
+                    let key = key_string(key);
+                    let value = self.value(value, value_ty).map_err(|err| err.at_key(&key))?;
+                    object.insert(key, value);
+                }
+                Ok(Json::Object(object))
+            }
+            (&Value::ContainerValue(ContainerValue::Struct(ref fields)),
+             &ContainerType::Struct(ref field_tys)) => self.fields(fields, field_tys, ty),
+            (&Value::ContainerValue(ContainerValue::Variant(ref inner_ty, ref inner)),
+             &ContainerType::Variant) => {
+                let value = self.value(inner, inner_ty).map_err(|err| err.at_key("value"))?;
+                let mut object = Map::new();
+                object.insert("signature".to_owned(), Json::String(inner_ty.to_string()));
+                object.insert("value".to_owned(), value);
+                Ok(Json::Object(object))
+            }
+            #[cfg(feature = "maybe")]
+            (&Value::ContainerValue(ContainerValue::Maybe(ref inner)),
+             &ContainerType::Maybe(ref inner_ty)) => {
+                match *inner {
+                    None => Ok(Json::Null),
+                    Some(ref inner) if is_maybe(inner_ty) => {
+                        let value = self.value(inner, inner_ty).map_err(|err| err.at_index(0))?;
+                        Ok(Json::Array(vec![value]))
+                    }
+                    Some(ref inner) => self.value(inner, inner_ty),
+                }
+            }
+            _ => Err(mismatch()),
+        }
+    }
+
+    fn fields(&self, fields: &[Value], field_tys: &[Type], ty: &Type) -> Result<Json> {
+        if fields.len() != field_tys.len() {
+            return Err(JsonError::new(ty,
+                                      format!("expected {} fields, found {}",
+                                              field_tys.len(),
+                                              fields.len())));
+        }
+        let mut array = Vec::with_capacity(fields.len());
+        for (i, (field, field_ty)) in fields.iter().zip(field_tys).enumerate() {
+            array.push(self.value(field, field_ty).map_err(|err| err.at_index(i))?);
+        }
+        Ok(Json::Array(array))
+    }
+
+    fn basic_value(&self, value: &BasicValue, ty: &Type) -> Result<Json> {
+        Ok(match *value {
+            BasicValue::Byte(n) => Json::from(n),
+            BasicValue::Bool(b) => Json::Bool(b),
+            BasicValue::Int16(n) => Json::from(n),
+            BasicValue::UInt16(n) => Json::from(n),
+            BasicValue::Int32(n) => Json::from(n),
+            BasicValue::UInt32(n) => Json::from(n),
+            BasicValue::Int64(n) if self.options.lossless_int64 => Json::String(n.to_string()),
+            BasicValue::Int64(n) => Json::from(n),
+            BasicValue::UInt64(n) if self.options.lossless_int64 => Json::String(n.to_string()),
+            BasicValue::UInt64(n) => Json::from(n),
+            BasicValue::Double(n) => {
+                match Number::from_f64(n) {
+                    Some(n) => Json::Number(n),
+                    None => return Err(JsonError::new(ty, format!("{} has no JSON form", n))),
+                }
+            }
+            BasicValue::UnixFd(n) => Json::from(n),
+            BasicValue::String(_) |
+            BasicValue::ObjectPath(_) |
+            BasicValue::Signature(_) => Json::String(key_string(value)),
+        })
+    }
+}
+
+fn key_string(value: &BasicValue) -> String {
+    match *value {
+        BasicValue::Byte(n) => n.to_string(),
+        BasicValue::Bool(b) => b.to_string(),
+        BasicValue::Int16(n) => n.to_string(),
+        BasicValue::UInt16(n) => n.to_string(),
+        BasicValue::Int32(n) => n.to_string(),
+        BasicValue::UInt32(n) => n.to_string(),
+        BasicValue::Int64(n) => n.to_string(),
+        BasicValue::UInt64(n) => n.to_string(),
+        BasicValue::Double(n) => n.to_string(),
+        BasicValue::String(ref s) => s.clone().into_owned(),
+        BasicValue::ObjectPath(ref path) => String::from_utf8_lossy(path).into_owned(),
+        BasicValue::Signature(ref signature) => signature.to_string(),
+        BasicValue::UnixFd(n) => n.to_string(),
+    }
+}
+
+struct Reader;
+
+impl Reader {
+    fn value(&self, json: &Json, ty: &Type) -> Result<Value> {
+        let container_ty = match *ty {
+            Type::BasicType(ref basic_ty) => {
+                return self.basic_value(json, basic_ty, ty).map(Value::BasicValue)
+            }
+            Type::ContainerType(ref container_ty) => container_ty,
+        };
+        let value = match (json, &**container_ty) {
+            (&Json::String(ref s), &ContainerType::Array(Type::BasicType(BasicType::Byte))) => {
+                let bytes = decode_base64(s)
+                    .ok_or_else(|| JsonError::new(ty, "expected a base64 string"))?;
+                ContainerValue::Array(bytes.into_iter()
+                    .map(|b| Value::BasicValue(BasicValue::Byte(b)))
+                    .collect())
+            }
+            (_, &ContainerType::Array(Type::BasicType(BasicType::Byte))) => {
+                return Err(JsonError::mismatch(ty, json))
+            }
+            (&Json::Array(ref elems), &ContainerType::Array(ref elem_ty)) => {
+                let mut array = Vec::with_capacity(elems.len());
+                for (i, elem) in elems.iter().enumerate() {
+                    array.push(self.value(elem, elem_ty).map_err(|err| err.at_index(i))?);
+                }
+                ContainerValue::Array(array)
+            }
+            (&Json::Object(ref object), &ContainerType::Dict(ref key_ty, ref value_ty)) => {
+                let mut entries = Vec::with_capacity(object.len());
+                for (key, value) in object {
+                    let key_value = parse_key(key, key_ty).map_err(|err| err.at_key(key))?;
+                    let value = self.value(value, value_ty).map_err(|err| err.at_key(key))?;
+                    entries.push((key_value, value));
+                }
+                ContainerValue::Dict(entries)
+            }
+            (_, &ContainerType::Struct(ref field_tys)) => {
+                return self.fields(json, field_tys, ty).map(|fields| {
+                    Value::ContainerValue(ContainerValue::Struct(fields))
+                })
+            }
+            (&Json::Object(ref object), &ContainerType::Variant) => {
+                let (signature, value) = match (object.get("signature"), object.get("value")) {
+                    (Some(signature), Some(value)) if object.len() == 2 => (signature, value),
+                    _ => {
+                        return Err(JsonError::new(ty,
+                                                  "expected an object with only `signature` \
+                                                   and `value` keys"))
+                    }
+                };
+                let signature_ty = Type::BasicType(BasicType::Signature);
+                let inner_ty = signature.as_str()
+                    .ok_or_else(|| JsonError::mismatch(&signature_ty, signature))
+                    .and_then(|s| {
+                        types::validate_gvariant_type(s.as_bytes())
+                            .map_err(|err| JsonError::new(&signature_ty, err.to_string()))
+                    })
+                    .map_err(|err| err.at_key("signature"))?;
+                let value = self.value(value, &inner_ty).map_err(|err| err.at_key("value"))?;
+                ContainerValue::Variant(inner_ty, Box::new(value))
+            }
+            #[cfg(feature = "maybe")]
+            (&Json::Null, &ContainerType::Maybe(_)) => ContainerValue::Maybe(None),
+            #[cfg(feature = "maybe")]
+            (_, &ContainerType::Maybe(ref inner_ty)) if is_maybe(inner_ty) => {
+                match *json {
+                    Json::Array(ref array) if array.len() == 1 => {
+                        let value = self.value(&array[0], inner_ty)
+                            .map_err(|err| err.at_index(0))?;
+                        ContainerValue::Maybe(Some(Box::new(value)))
+                    }
+                    _ => {
+                        return Err(JsonError::new(ty,
+                                                  format!("expected null or a one-element \
+                                                           array, found {}",
+                                                          json_name(json))))
+                    }
+                }
+            }
+            #[cfg(feature = "maybe")]
+            (_, &ContainerType::Maybe(ref inner_ty)) => {
+                ContainerValue::Maybe(Some(Box::new(self.value(json, inner_ty)?)))
+            }
+            _ => return Err(JsonError::mismatch(ty, json)),
+        };
+        Ok(Value::ContainerValue(value))
+    }
+
+    fn fields(&self, json: &Json, field_tys: &[Type], ty: &Type) -> Result<Vec<Value>> {
+        let array = match *json {
+            Json::Array(ref array) => array,
+            _ => return Err(JsonError::mismatch(ty, json)),
+        };
+        if array.len() != field_tys.len() {
+            return Err(JsonError::new(ty,
+                                      format!("expected {} fields, found {}",
+                                              field_tys.len(),
+                                              array.len())));
+        }
+        array.iter()
+            .zip(field_tys)
+            .enumerate()
+            .map(|(i, (field, field_ty))| {
+                self.value(field, field_ty).map_err(|err| err.at_index(i))
+            })
+            .collect()
+    }
+
+    fn basic_value(&self, json: &Json, basic_ty: &BasicType, ty: &Type) -> Result<BasicValue> {
+        let mismatch = || JsonError::mismatch(ty, json);
+        let lossless = *basic_ty == BasicType::Int64 || *basic_ty == BasicType::UInt64;
+        let n = match *json {
+            Json::Number(ref n) => n.as_i64().map(i128::from).or(n.as_u64().map(i128::from)),
+            Json::String(ref s) if lossless => Some(s.parse().map_err(|_| mismatch())?),
+            _ => None,
+        };
+        let out_of_range = || JsonError::new(ty, format!("{} is out of range", n.unwrap_or(0)));
+        Ok(match *basic_ty {
+            BasicType::Byte => BasicValue::Byte(int(n, mismatch, out_of_range)?),
+            BasicType::Int16 => BasicValue::Int16(int(n, mismatch, out_of_range)?),
+            BasicType::UInt16 => BasicValue::UInt16(int(n, mismatch, out_of_range)?),
+            BasicType::Int32 => BasicValue::Int32(int(n, mismatch, out_of_range)?),
+            BasicType::UInt32 => BasicValue::UInt32(int(n, mismatch, out_of_range)?),
+            BasicType::Int64 => BasicValue::Int64(int(n, mismatch, out_of_range)?),
+            BasicType::UInt64 => BasicValue::UInt64(int(n, mismatch, out_of_range)?),
+            BasicType::UnixFd => BasicValue::UnixFd(int(n, mismatch, out_of_range)?),
+            BasicType::Bool => BasicValue::Bool(json.as_bool().ok_or_else(mismatch)?),
+            BasicType::Double => BasicValue::Double(json.as_f64().ok_or_else(mismatch)?),
+            BasicType::String | BasicType::ObjectPath | BasicType::Signature => {
+                return parse_key(json.as_str().ok_or_else(mismatch)?, basic_ty)
+            }
+        })
+    }
+}
+
+fn int<T, M, R>(n: Option<i128>, mismatch: M, out_of_range: R) -> Result<T>
+    where T: TryFrom<i128>,
+          M: FnOnce() -> JsonError,
+          R: FnOnce() -> JsonError
+{
+    T::try_from(n.ok_or_else(mismatch)?).map_err(|_| out_of_range())
+}
+
+// Reads a basic value from its text form, as for dict keys and strings.
+fn parse_key(s: &str, basic_ty: &BasicType) -> Result<BasicValue> {
+    let ty = Type::BasicType(basic_ty.clone());
+    let invalid = || JsonError::new(&ty, format!("`{}` is not {}", s, json_kind(&ty)));
+    let n = || s.parse::<i128>().map_err(|_| invalid());
+    let out_of_range = || JsonError::new(&ty, format!("{} is out of range", s));
+    Ok(match *basic_ty {
+        BasicType::Byte => BasicValue::Byte(int(Some(n()?), invalid, out_of_range)?),
+        BasicType::Int16 => BasicValue::Int16(int(Some(n()?), invalid, out_of_range)?),
+        BasicType::UInt16 => BasicValue::UInt16(int(Some(n()?), invalid, out_of_range)?),
+        BasicType::Int32 => BasicValue::Int32(int(Some(n()?), invalid, out_of_range)?),
+        BasicType::UInt32 => BasicValue::UInt32(int(Some(n()?), invalid, out_of_range)?),
+        BasicType::Int64 => BasicValue::Int64(int(Some(n()?), invalid, out_of_range)?),
+        BasicType::UInt64 => BasicValue::UInt64(int(Some(n()?), invalid, out_of_range)?),
+        BasicType::UnixFd => BasicValue::UnixFd(int(Some(n()?), invalid, out_of_range)?),
+        BasicType::Bool => BasicValue::Bool(s.parse().map_err(|_| invalid())?),
+        BasicType::Double => BasicValue::Double(s.parse().map_err(|_| invalid())?),
+        BasicType::String => BasicValue::String(s.to_owned().into()),
+        BasicType::ObjectPath => {
+            if !is_valid_object_path(s) {
+                return Err(invalid());
+            }
+            BasicValue::ObjectPath(s.as_bytes().to_vec().into())
+        }
+        BasicType::Signature => BasicValue::Signature(s.parse().map_err(|_| invalid())?),
+    })
+}
+
+#[cfg(feature = "maybe")]
+fn is_maybe(ty: &Type) -> bool {
+    match *ty {
+        Type::ContainerType(ref container_ty) => {
+            match **container_ty {
+                ContainerType::Maybe(_) => true,
+                _ => false,
+            }
+        }
+        Type::BasicType(_) => false,
+    }
+}
+
+// What JSON a value of type `ty` converts to, for error messages.
+fn json_kind(ty: &Type) -> &'static str {
+    match *ty {
+        Type::BasicType(BasicType::Bool) => "a boolean",
+        Type::BasicType(BasicType::Double) => "a number",
+        Type::BasicType(BasicType::Int64) |
+        Type::BasicType(BasicType::UInt64) => "an integer or a string of one",
+        Type::BasicType(BasicType::String) => "a string",
+        Type::BasicType(BasicType::ObjectPath) => "an object path",
+        Type::BasicType(BasicType::Signature) => "a signature",
+        Type::BasicType(_) => "an integer",
+        Type::ContainerType(ref container_ty) => {
+            match **container_ty {
+                ContainerType::Array(Type::BasicType(BasicType::Byte)) => "a base64 string",
+                ContainerType::Array(_) |
+                ContainerType::Struct(_) => "an array",
+                ContainerType::Dict(..) |
+                ContainerType::Variant => "an object",
+                #[cfg(feature = "maybe")]
+                ContainerType::Maybe(_) => "null or a value",
+            }
+        }
+    }
+}
+
+fn json_name(json: &Json) -> &'static str {
+    match *json {
+        Json::Null => "null",
+        Json::Bool(_) => "a boolean",
+        Json::Number(ref n) if n.is_f64() => "a fractional number",
+        Json::Number(_) => "an integer",
+        Json::String(_) => "a string",
+        Json::Array(_) => "an array",
+        Json::Object(_) => "an object",
+    }
+}
+
+const BASE64_ALPHABET: &'static [u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut output = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(BASE64_ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    let s = s.as_bytes();
+    if s.len() % 4 != 0 {
+        return None;
+    }
+    let mut output = Vec::with_capacity(s.len() / 4 * 3);
+    for (i, chunk) in s.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && i + 1 < s.len() / 4) {
+            return None;
+        }
+        let mut n = 0u32;
+        for &c in &chunk[..4 - padding] {
+            let digit = BASE64_ALPHABET.iter().position(|&d| d == c)?;
+            n = n << 6 | digit as u32;
+        }
+        n <<= 6 * padding as u32;
+        // Unused bits in the last digit must be zero, so each byte string
+        // has one encoding.
+        if n & ((1 << (8 * padding as u32)) - 1) != 0 {
+            return None;
+        }
+        for j in 0..3 - padding {
+            output.push((n >> (16 - 8 * j)) as u8);
+        }
+    }
+    Some(output)
+}
@@ -0,0 +1,285 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fmt::{self, Debug, Display, Formatter};
+use std::str;
+
+use bus::types::{self, Signature, Type, MAX_ARRAY_DEPTH, MAX_SIGNATURE_LEN, MAX_STRUCT_DEPTH};
+
+/// Checks a signature string at compile time, and expands to the
+/// `Signature` it describes.
+///
+/// ```
+/// # #[macro_use] extern crate tokio_dbus;
+/// # fn main() {
+/// let signature = signature!("a{sv}");
+/// assert_eq!(signature.to_string(), "a{sv}");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! signature {
+    ($signature:expr) => {{
+        const SIGNATURE: $crate::ConstSignature = $crate::ConstSignature::new($signature);
+        SIGNATURE.to_signature()
+    }}
+}
+
+/// Fails the build unless the D-Bus type of a Rust type has the given
+/// signature, as in `assert_signature!(Vec<(String, u32)>, "a(su)");`.
+#[macro_export]
+macro_rules! assert_signature {
+    ($ty:ty, $signature:expr) => {
+        const _: () = assert!(<$ty as $crate::DBusType>::SIGNATURE.eq_str($signature),
+                              concat!("the D-Bus signature of `", stringify!($ty),
+                                      "` is not ", stringify!($signature)));
+    }
+}
+
+/// A signature that can be built and checked in constant expressions, as
+/// for `DBusType::SIGNATURE`.
+///
+/// Every constructor validates its result as `validate_gvariant_signature`
+/// would, panicking if it is invalid; in a constant, that fails the build.
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+pub struct ConstSignature {
+    bytes: [u8; MAX_SIGNATURE_LEN],
+    len: usize,
+}
+
+impl ConstSignature {
+    pub const fn new(signature: &str) -> Self {
+        ConstSignature::empty().push(signature.as_bytes()).checked()
+    }
+
+    /// The signature of an array of `elem`.
+    pub const fn array(elem: ConstSignature) -> Self {
+        ConstSignature::empty().push(b"a").push(elem.as_bytes()).checked()
+    }
+
+    /// The signature of a dict from `key`, which must be basic, to `value`.
+    pub const fn dict(key: ConstSignature, value: ConstSignature) -> Self {
+        ConstSignature::empty()
+            .push(b"a{")
+            .push(key.as_bytes())
+            .push(value.as_bytes())
+            .push(b"}")
+            .checked()
+    }
+
+    /// The signature of a struct of `fields`.
+    pub const fn structure(fields: &[ConstSignature]) -> Self {
+        let mut signature = ConstSignature::empty().push(b"(");
+        let mut i = 0;
+        while i < fields.len() {
+            signature = signature.push(fields[i].as_bytes());
+            i += 1;
+        }
+        signature.push(b")").checked()
+    }
+
+    /// The signature of a GVariant maybe of `inner`.
+    #[cfg(feature = "maybe")]
+    pub const fn maybe(inner: ConstSignature) -> Self {
+        ConstSignature::empty().push(b"m").push(inner.as_bytes()).checked()
+    }
+
+    pub const fn as_bytes(&self) -> &[u8] {
+        self.bytes.split_at(self.len).0
+    }
+
+    pub const fn as_str(&self) -> &str {
+        match str::from_utf8(self.as_bytes()) {
+            Ok(s) => s,
+            // Valid signatures are all ASCII.
+            Err(_) => panic!("signature is not ASCII"),
+        }
+    }
+
+    /// Compares with a signature string, in constant expressions too.
+    pub const fn eq_str(&self, signature: &str) -> bool {
+        let bytes = signature.as_bytes();
+        if bytes.len() != self.len {
+            return false;
+        }
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] != self.bytes[i] {
+                return false;
+            }
+            i += 1;
+        }
+        true
+    }
+
+    pub fn to_signature(&self) -> Signature {
+        types::validate_gvariant_signature(self.as_bytes())
+            .expect("ConstSignature is always valid")
+    }
+
+    /// The single complete type the signature holds.
+    ///
+    /// # Panics
+    ///
+    /// If the signature is empty or holds several types.
+    pub fn to_type(&self) -> Type {
+        types::validate_gvariant_type(self.as_bytes())
+            .unwrap_or_else(|_| panic!("`{}` is not a single complete type", self))
+    }
+
+    const fn empty() -> Self {
+        ConstSignature {
+            bytes: [0; MAX_SIGNATURE_LEN],
+            len: 0,
+        }
+    }
+
+    const fn push(mut self, bytes: &[u8]) -> Self {
+        if self.len + bytes.len() > MAX_SIGNATURE_LEN {
+            panic!("D-Bus signature is too long");
+        }
+        let mut i = 0;
+        while i < bytes.len() {
+            self.bytes[self.len + i] = bytes[i];
+            i += 1;
+        }
+        self.len += bytes.len();
+        self
+    }
+
+    const fn checked(self) -> Self {
+        let bytes = self.as_bytes();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            match complete_type(bytes, offset, 0, 0) {
+                Ok(end) => offset = end,
+                Err(reason) => panic!("{}", reason),
+            }
+        }
+        self
+    }
+}
+
+impl Display for ConstSignature {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for ConstSignature {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "ConstSignature({:?})", self.as_str())
+    }
+}
+
+impl From<ConstSignature> for Signature {
+    fn from(signature: ConstSignature) -> Self {
+        signature.to_signature()
+    }
+}
+
+// The checks of `StrictParser` in `types`, in a form that can run in constant
+// expressions. Returns the offset just past the complete type at `offset`.
+// The array and struct limits keep the total depth within its own limit.
+const fn complete_type(input: &[u8],
+                       offset: usize,
+                       arrays: usize,
+                       structs: usize)
+                       -> Result<usize, &'static str> {
+    if offset >= input.len() {
+        return Err("D-Bus signature ends in the middle of a type");
+    }
+    let code = input[offset];
+    if is_basic(code) || code == b'v' {
+        return Ok(offset + 1);
+    }
+    match code {
+        b'a' => {
+            if arrays == MAX_ARRAY_DEPTH {
+                return Err("D-Bus signature nests arrays too deeply");
+            }
+            if offset + 1 < input.len() && input[offset + 1] == b'{' {
+                dict_entry(input, offset + 1, arrays + 1, structs)
+            } else {
+                complete_type(input, offset + 1, arrays + 1, structs)
+            }
+        }
+        b'm' if cfg!(feature = "maybe") => {
+            if arrays == MAX_ARRAY_DEPTH {
+                return Err("D-Bus signature nests arrays too deeply");
+            }
+            complete_type(input, offset + 1, arrays + 1, structs)
+        }
+        b'(' => {
+            if structs == MAX_STRUCT_DEPTH {
+                return Err("D-Bus signature nests structs too deeply");
+            }
+            let mut offset = offset + 1;
+            if offset < input.len() && input[offset] == b')' {
+                return Err("D-Bus signature has an empty struct");
+            }
+            loop {
+                if offset >= input.len() {
+                    return Err("D-Bus signature ends in the middle of a type");
+                }
+                if input[offset] == b')' {
+                    return Ok(offset + 1);
+                }
+                match complete_type(input, offset, arrays, structs + 1) {
+                    Ok(end) => offset = end,
+                    Err(reason) => return Err(reason),
+                }
+            }
+        }
+        b'{' => Err("D-Bus signature has a dict entry that is not the element type of an array"),
+        b')' | b'}' => Err("D-Bus signature is unbalanced"),
+        _ => Err("D-Bus signature has an invalid type code"),
+    }
+}
+
+// Checks the dict entry whose `{` is at `offset`.
+const fn dict_entry(input: &[u8],
+                    offset: usize,
+                    arrays: usize,
+                    structs: usize)
+                    -> Result<usize, &'static str> {
+    if structs == MAX_STRUCT_DEPTH {
+        return Err("D-Bus signature nests structs too deeply");
+    }
+    let key = offset + 1;
+    if key >= input.len() {
+        return Err("D-Bus signature ends in the middle of a type");
+    }
+    if !is_basic(input[key]) {
+        return match input[key] {
+            b'}' => Err("D-Bus signature has a dict entry without exactly two types"),
+            b'a' | b'(' | b'v' | b'{' => Err("D-Bus signature has a dict key that is not basic"),
+            b'm' if cfg!(feature = "maybe") => {
+                Err("D-Bus signature has a dict key that is not basic")
+            }
+            _ => Err("D-Bus signature has an invalid type code"),
+        };
+    }
+    if key + 1 < input.len() && input[key + 1] == b'}' {
+        return Err("D-Bus signature has a dict entry without exactly two types");
+    }
+    let end = match complete_type(input, key + 1, arrays, structs + 1) {
+        Ok(end) => end,
+        Err(reason) => return Err(reason),
+    };
+    if end >= input.len() {
+        return Err("D-Bus signature ends in the middle of a type");
+    }
+    if input[end] != b'}' {
+        return Err("D-Bus signature has a dict entry without exactly two types");
+    }
+    Ok(end + 1)
+}
+
+const fn is_basic(code: u8) -> bool {
+    match code {
+        b'y' | b'b' | b'n' | b'q' | b'i' | b'u' | b'x' | b't' | b'd' | b's' | b'o' | b'g' |
+        b'h' => true,
+        _ => false,
+    }
+}
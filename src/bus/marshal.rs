@@ -0,0 +1,690 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io::{Error, ErrorKind, Result};
+use std::str;
+
+use bus::borrowed::{BasicValueRef, ContainerValueRef, ValueRef};
+use bus::message::LimitError;
+use bus::types::{self, BasicType, ContainerType, Depth, Signature, Type, MAX_SIGNATURE_LEN,
+                 encode_signature};
+use bus::wire::{self, BasicValue, ContainerValue, Value};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+    pub fn native() -> Self {
+        if cfg!(target_endian = "little") {
+            Endianness::Little
+        } else {
+            Endianness::Big
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            b'l' => Some(Endianness::Little),
+            b'B' => Some(Endianness::Big),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match *self {
+            Endianness::Little => b'l',
+            Endianness::Big => b'B',
+        }
+    }
+
+    pub fn read_u16(&self, bytes: &[u8]) -> u16 {
+        match *self {
+            Endianness::Little => bytes[0] as u16 | (bytes[1] as u16) << 8,
+            Endianness::Big => (bytes[0] as u16) << 8 | bytes[1] as u16,
+        }
+    }
+
+    pub fn read_u32(&self, bytes: &[u8]) -> u32 {
+        let (lo, hi) = match *self {
+            Endianness::Little => (self.read_u16(&bytes[0..2]), self.read_u16(&bytes[2..4])),
+            Endianness::Big => (self.read_u16(&bytes[2..4]), self.read_u16(&bytes[0..2])),
+        };
+        lo as u32 | (hi as u32) << 16
+    }
+
+    pub fn read_u64(&self, bytes: &[u8]) -> u64 {
+        let (lo, hi) = match *self {
+            Endianness::Little => (self.read_u32(&bytes[0..4]), self.read_u32(&bytes[4..8])),
+            Endianness::Big => (self.read_u32(&bytes[4..8]), self.read_u32(&bytes[0..4])),
+        };
+        lo as u64 | (hi as u64) << 32
+    }
+
+    pub fn write_u16(&self, n: u16, output: &mut Vec<u8>) {
+        match *self {
+            Endianness::Little => output.extend_from_slice(&[n as u8, (n >> 8) as u8]),
+            Endianness::Big => output.extend_from_slice(&[(n >> 8) as u8, n as u8]),
+        }
+    }
+
+    pub fn write_u32(&self, n: u32, output: &mut Vec<u8>) {
+        let (first, second) = match *self {
+            Endianness::Little => (n as u16, (n >> 16) as u16),
+            Endianness::Big => ((n >> 16) as u16, n as u16),
+        };
+        self.write_u16(first, output);
+        self.write_u16(second, output);
+    }
+
+    pub fn write_u64(&self, n: u64, output: &mut Vec<u8>) {
+        let (first, second) = match *self {
+            Endianness::Little => (n as u32, (n >> 32) as u32),
+            Endianness::Big => ((n >> 32) as u32, n as u32),
+        };
+        self.write_u32(first, output);
+        self.write_u32(second, output);
+    }
+}
+
+pub fn alignment(ty: &Type) -> usize {
+    match *ty {
+        Type::BasicType(ref basic_ty) => basic_alignment(basic_ty),
+        Type::ContainerType(ref container_ty) => {
+            match **container_ty {
+                ContainerType::Array(_) |
+                ContainerType::Dict(..) => 4,
+                ContainerType::Struct(_) => 8,
+                ContainerType::Variant => 1,
+                #[cfg(feature = "maybe")]
+                ContainerType::Maybe(ref inner_ty) => alignment(inner_ty),
+            }
+        }
+    }
+}
+
+fn basic_alignment(ty: &BasicType) -> usize {
+    match *ty {
+        BasicType::Byte | BasicType::Signature => 1,
+        BasicType::Int16 | BasicType::UInt16 => 2,
+        BasicType::Bool | BasicType::Int32 | BasicType::UInt32 | BasicType::String |
+        BasicType::ObjectPath | BasicType::UnixFd => 4,
+        BasicType::Int64 | BasicType::UInt64 | BasicType::Double => 8,
+    }
+}
+
+pub fn malformed(what: &str) -> Error {
+    Error::new(ErrorKind::InvalidData,
+               format!("malformed D-Bus message: {}", what))
+}
+
+/// Marshals `values` according to `signature`, appending to `output`.
+/// Alignment is computed from the start of `output`, which must therefore
+/// begin on an 8-byte boundary of the message, as a message body does.
+pub fn encode_values(values: &[Value],
+                     signature: &Signature,
+                     endianness: Endianness,
+                     output: &mut Vec<u8>)
+                     -> Result<()> {
+    wire::check_signature(values, signature)?;
+    #[cfg(feature = "maybe")]
+    check_no_maybe(values, signature)?;
+    check_signature_len(signature)?;
+    for value in values {
+        check_signature_lens(value)?;
+    }
+
+    let mut encoder = Encoder {
+        output: output,
+        endianness: endianness,
+    };
+    for (value, ty) in values.iter().zip(signature) {
+        encoder.value(value, ty);
+    }
+    Ok(())
+}
+
+/// Checks that `signature` fits in the one byte the wire format gives its
+/// length. Longer signatures can be built, but not marshalled.
+pub fn check_signature_len(signature: &Signature) -> Result<()> {
+    let len = signature.to_string().len();
+    if len > MAX_SIGNATURE_LEN {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              format!("signature of {} bytes exceeds the limit of {}",
+                                      len,
+                                      MAX_SIGNATURE_LEN)));
+    }
+    Ok(())
+}
+
+// Checks the signatures inside `value`: signature values, and the types of
+// variants' contents.
+fn check_signature_lens(value: &Value) -> Result<()> {
+    match *value {
+        Value::BasicValue(ref basic) => check_basic_signature_len(basic),
+        Value::ContainerValue(ContainerValue::Array(ref elems)) |
+        Value::ContainerValue(ContainerValue::Struct(ref elems)) => {
+            for elem in elems {
+                check_signature_lens(elem)?;
+            }
+            Ok(())
+        }
+        Value::ContainerValue(ContainerValue::Variant(ref inner_ty, ref inner)) => {
+            check_signature_len(&Signature::new(vec![inner_ty.clone()]))?;
+            check_signature_lens(inner)
+        }
+        Value::ContainerValue(ContainerValue::Dict(ref entries)) => {
+            for &(ref key, ref value) in entries {
+                check_basic_signature_len(key)?;
+                check_signature_lens(value)?;
+            }
+            Ok(())
+        }
+        #[cfg(feature = "maybe")]
+        Value::ContainerValue(ContainerValue::Maybe(ref inner)) => {
+            inner.as_ref().map_or(Ok(()), |inner| check_signature_lens(inner))
+        }
+    }
+}
+
+fn check_basic_signature_len(value: &BasicValue) -> Result<()> {
+    match *value {
+        BasicValue::Signature(ref signature) => check_signature_len(signature),
+        _ => Ok(()),
+    }
+}
+
+// Maybe types exist only in GVariant, so they can't be marshalled here, even
+// inside a variant.
+#[cfg(feature = "maybe")]
+fn check_no_maybe(values: &[Value], signature: &Signature) -> Result<()> {
+    fn contains_maybe(value: &Value) -> bool {
+        match *value {
+            Value::BasicValue(_) => false,
+            Value::ContainerValue(ContainerValue::Array(ref elems)) |
+            Value::ContainerValue(ContainerValue::Struct(ref elems)) => {
+                elems.iter().any(contains_maybe)
+            }
+            Value::ContainerValue(ContainerValue::Variant(ref inner_ty, ref inner)) => {
+                inner_ty.to_string().contains('m') || contains_maybe(inner)
+            }
+            Value::ContainerValue(ContainerValue::Dict(ref entries)) => {
+                entries.iter().any(|&(_, ref value)| contains_maybe(value))
+            }
+            Value::ContainerValue(ContainerValue::Maybe(_)) => true,
+        }
+    }
+
+    if signature.to_string().contains('m') || values.iter().any(contains_maybe) {
+        return Err(Error::new(ErrorKind::InvalidInput,
+                              "the D-Bus wire format has no maybe type"));
+    }
+    Ok(())
+}
+
+/// Unmarshals a sequence of values of the types in `signature`, which must
+/// consume all of `input`.
+pub fn decode_values(input: &[u8],
+                     signature: &Signature,
+                     endianness: Endianness,
+                     max_array_len: u32)
+                     -> Result<Vec<Value>> {
+    decode_values_ref(input, signature, endianness, max_array_len)
+        .map(|values| values.into_iter().map(ValueRef::into_owned).collect())
+}
+
+/// Like `decode_values`, but the values borrow from `input` rather than
+/// copying out of it.
+pub fn decode_values_ref<'a>(input: &'a [u8],
+                             signature: &Signature,
+                             endianness: Endianness,
+                             max_array_len: u32)
+                             -> Result<Vec<ValueRef<'a>>> {
+    let mut decoder = Decoder::new(input, 0, endianness, max_array_len);
+    let mut values = Vec::with_capacity(signature.len());
+    for ty in signature {
+        values.push(decoder.value(ty, Depth::new())?);
+    }
+    decoder.finish()?;
+    Ok(values)
+}
+
+pub struct Encoder<'a> {
+    output: &'a mut Vec<u8>,
+    endianness: Endianness,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn new(output: &'a mut Vec<u8>, endianness: Endianness) -> Self {
+        Encoder {
+            output: output,
+            endianness: endianness,
+        }
+    }
+
+    pub fn align(&mut self, alignment: usize) {
+        while self.output.len() % alignment != 0 {
+            self.output.push(0);
+        }
+    }
+
+    pub fn byte(&mut self, n: u8) {
+        self.output.push(n);
+    }
+
+    pub fn u32(&mut self, n: u32) {
+        self.align(4);
+        self.endianness.write_u32(n, self.output);
+    }
+
+    pub fn signature(&mut self, signature: &Signature) {
+        let len_pos = self.output.len();
+        self.output.push(0);
+        encode_signature(signature, self.output);
+        self.output[len_pos] = (self.output.len() - len_pos - 1) as u8;
+        self.output.push(0);
+    }
+
+    fn single_type_signature(&mut self, ty: &Type) {
+        self.signature(&Signature::new(vec![ty.clone()]));
+    }
+
+    fn string(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.output.extend_from_slice(bytes);
+        self.output.push(0);
+    }
+
+    // The value is expected to have been checked against `ty` already.
+    pub fn value(&mut self, value: &Value, ty: &Type) {
+        match (value, ty) {
+            (&Value::BasicValue(ref basic), _) => self.basic_value(basic),
+            (&Value::ContainerValue(ref container), &Type::ContainerType(ref container_ty)) => {
+                self.container_value(container, container_ty)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn basic_value(&mut self, value: &BasicValue) {
+        let endianness = self.endianness;
+        match *value {
+            BasicValue::Byte(n) => self.byte(n),
+            BasicValue::Bool(b) => self.u32(b as u32),
+            BasicValue::Int16(n) => {
+                self.align(2);
+                endianness.write_u16(n as u16, self.output);
+            }
+            BasicValue::UInt16(n) => {
+                self.align(2);
+                endianness.write_u16(n, self.output);
+            }
+            BasicValue::Int32(n) => self.u32(n as u32),
+            BasicValue::UInt32(n) => self.u32(n),
+            BasicValue::Int64(n) => {
+                self.align(8);
+                endianness.write_u64(n as u64, self.output);
+            }
+            BasicValue::UInt64(n) => {
+                self.align(8);
+                endianness.write_u64(n, self.output);
+            }
+            BasicValue::Double(n) => {
+                self.align(8);
+                endianness.write_u64(n.to_bits(), self.output);
+            }
+            BasicValue::String(ref s) => self.string(s.as_bytes()),
+            BasicValue::ObjectPath(ref path) => self.string(path),
+            BasicValue::Signature(ref signature) => self.signature(signature),
+            BasicValue::UnixFd(index) => self.u32(index),
+        }
+    }
+
+    fn container_value(&mut self, value: &ContainerValue, ty: &ContainerType) {
+        match (value, ty) {
+            (&ContainerValue::Array(ref elems), &ContainerType::Array(ref elem_ty)) => {
+                let start = self.begin_array(alignment(elem_ty));
+                for elem in elems {
+                    self.value(elem, elem_ty);
+                }
+                self.end_array(start);
+            }
+            (&ContainerValue::Dict(ref entries), &ContainerType::Dict(_, ref value_ty)) => {
+                let start = self.begin_array(8);
+                for &(ref key, ref value) in entries {
+                    self.align(8);
+                    self.basic_value(key);
+                    self.value(value, value_ty);
+                }
+                self.end_array(start);
+            }
+            (&ContainerValue::Struct(ref fields), &ContainerType::Struct(ref field_tys)) => {
+                self.align(8);
+                for (field, field_ty) in fields.iter().zip(field_tys) {
+                    self.value(field, field_ty);
+                }
+            }
+            (&ContainerValue::Variant(ref inner_ty, ref inner), &ContainerType::Variant) => {
+                self.single_type_signature(inner_ty);
+                self.value(inner, inner_ty);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // Writes a placeholder length and the padding up to the first element,
+    // returning the offsets of the length and of the first element.
+    pub fn begin_array(&mut self, elem_alignment: usize) -> (usize, usize) {
+        self.u32(0);
+        let len_pos = self.output.len() - 4;
+        self.align(elem_alignment);
+        (len_pos, self.output.len())
+    }
+
+    // The length excludes the padding before the first element.
+    pub fn end_array(&mut self, (len_pos, elems_start): (usize, usize)) {
+        let len = (self.output.len() - elems_start) as u32;
+        let mut len_bytes = Vec::with_capacity(4);
+        self.endianness.write_u32(len, &mut len_bytes);
+        self.output[len_pos..len_pos + 4].copy_from_slice(&len_bytes);
+    }
+}
+
+pub struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+    // Alignment is relative to the start of the message, which may lie
+    // before the start of `input`.
+    base: usize,
+    endianness: Endianness,
+    max_array_len: u32,
+    // `UnixFd` values must index into this many attached descriptors, if set.
+    unix_fds: Option<u32>,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(input: &'a [u8],
+               base: usize,
+               endianness: Endianness,
+               max_array_len: u32)
+               -> Self {
+        Decoder {
+            input: input,
+            pos: 0,
+            base: base,
+            endianness: endianness,
+            max_array_len: max_array_len,
+            unix_fds: None,
+        }
+    }
+
+    pub fn with_unix_fds(self, unix_fds: u32) -> Self {
+        Decoder { unix_fds: Some(unix_fds), ..self }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn finish(&self) -> Result<()> {
+        if self.pos == self.input.len() {
+            Ok(())
+        } else {
+            Err(malformed("trailing bytes after last value"))
+        }
+    }
+
+    pub fn align(&mut self, alignment: usize) -> Result<()> {
+        let misalignment = (self.base + self.pos) % alignment;
+        if misalignment != 0 {
+            let padding = self.take(alignment - misalignment)?;
+            if padding.iter().any(|&b| b != 0) {
+                return Err(malformed("nonzero alignment padding"));
+            }
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.input.len() - self.pos < n {
+            return Err(malformed("value extends past the end of the message"));
+        }
+        let bytes = &self.input[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    pub fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        self.align(2)?;
+        let bytes = self.take(2)?;
+        Ok(self.endianness.read_u16(bytes))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        self.align(4)?;
+        let bytes = self.take(4)?;
+        Ok(self.endianness.read_u32(bytes))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        self.align(8)?;
+        let bytes = self.take(8)?;
+        Ok(self.endianness.read_u64(bytes))
+    }
+
+    fn string_bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        if self.byte()? != 0 {
+            return Err(malformed("string is not nul-terminated"));
+        }
+        Ok(bytes)
+    }
+
+    fn str(&mut self) -> Result<&'a str> {
+        let bytes = self.string_bytes()?;
+        if bytes.contains(&0) {
+            return Err(malformed("string contains a nul byte"));
+        }
+        str::from_utf8(bytes).map_err(|_| malformed("string is not valid UTF-8"))
+    }
+
+    fn signature_bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.byte()? as usize;
+        let bytes = self.take(len)?;
+        if self.byte()? != 0 {
+            return Err(malformed("signature is not nul-terminated"));
+        }
+        Ok(bytes)
+    }
+
+    pub fn signature(&mut self) -> Result<Signature> {
+        Ok(types::validate_signature(self.signature_bytes()?)?)
+    }
+
+    pub fn single_type_signature(&mut self) -> Result<Type> {
+        Ok(types::validate_single_type(self.signature_bytes()?)?)
+    }
+
+    // Reads an array length and the padding before its first element,
+    // returning the offset at which the elements end.
+    pub fn begin_array(&mut self, elem_alignment: usize) -> Result<usize> {
+        let len = self.u32()?;
+        if len > self.max_array_len {
+            return Err(LimitError::ArrayTooLarge {
+                    len: len,
+                    limit: self.max_array_len,
+                }
+                .into());
+        }
+        self.align(elem_alignment)?;
+        let end = self.pos + len as usize;
+        if end > self.input.len() {
+            return Err(malformed("array extends past the end of the message"));
+        }
+        Ok(end)
+    }
+
+    pub fn end_array(&self, end: usize) -> Result<()> {
+        if self.pos == end {
+            Ok(())
+        } else {
+            Err(malformed("array elements overrun the array length"))
+        }
+    }
+
+    pub fn value(&mut self, ty: &Type, depth: Depth) -> Result<ValueRef<'a>> {
+        match *ty {
+            Type::BasicType(ref basic_ty) => self.basic_value(basic_ty).map(ValueRef::BasicValue),
+            Type::ContainerType(ref container_ty) => {
+                self.container_value(container_ty, depth).map(ValueRef::ContainerValue)
+            }
+        }
+    }
+
+    pub fn basic_value(&mut self, ty: &BasicType) -> Result<BasicValueRef<'a>> {
+        Ok(match *ty {
+            BasicType::Byte => BasicValueRef::Byte(self.byte()?),
+            BasicType::Bool => {
+                match self.u32()? {
+                    0 => BasicValueRef::Bool(false),
+                    1 => BasicValueRef::Bool(true),
+                    _ => return Err(malformed("boolean is neither 0 nor 1")),
+                }
+            }
+            BasicType::Int16 => BasicValueRef::Int16(self.u16()? as i16),
+            BasicType::UInt16 => BasicValueRef::UInt16(self.u16()?),
+            BasicType::Int32 => BasicValueRef::Int32(self.u32()? as i32),
+            BasicType::UInt32 => BasicValueRef::UInt32(self.u32()?),
+            BasicType::Int64 => BasicValueRef::Int64(self.u64()? as i64),
+            BasicType::UInt64 => BasicValueRef::UInt64(self.u64()?),
+            BasicType::Double => BasicValueRef::Double(f64::from_bits(self.u64()?)),
+            BasicType::String => BasicValueRef::String(self.str()?),
+            BasicType::ObjectPath => BasicValueRef::ObjectPath(self.str()?),
+            BasicType::Signature => {
+                let bytes = self.signature_bytes()?;
+                types::validate_signature(bytes)?;
+                // Valid signatures are all ASCII.
+                BasicValueRef::Signature(str::from_utf8(bytes).unwrap())
+            }
+            BasicType::UnixFd => {
+                let index = self.u32()?;
+                if self.unix_fds.map_or(false, |unix_fds| index >= unix_fds) {
+                    return Err(malformed("file descriptor index out of range"));
+                }
+                BasicValueRef::UnixFd(index)
+            }
+        })
+    }
+
+    fn container_value(&mut self,
+                       ty: &ContainerType,
+                       depth: Depth)
+                       -> Result<ContainerValueRef<'a>> {
+        let offset = self.pos;
+        Ok(match *ty {
+            ContainerType::Array(Type::BasicType(BasicType::Byte)) => {
+                depth.enter_array(offset)?;
+                let end = self.begin_array(1)?;
+                let len = end - self.pos;
+                ContainerValueRef::ByteArray(self.take(len)?)
+            }
+            ContainerType::Array(ref elem_ty) => {
+                let depth = depth.enter_array(offset)?;
+                let end = self.begin_array(alignment(elem_ty))?;
+                let mut elems = Vec::new();
+                while self.pos < end {
+                    elems.push(self.value(elem_ty, depth)?);
+                }
+                self.end_array(end)?;
+                ContainerValueRef::Array(elems)
+            }
+            ContainerType::Dict(ref key_ty, ref value_ty) => {
+                let depth = depth.enter_array(offset)?.enter_struct(offset)?;
+                let end = self.begin_array(8)?;
+                let mut entries = Vec::new();
+                while self.pos < end {
+                    self.align(8)?;
+                    let key = self.basic_value(key_ty)?;
+                    let value = self.value(value_ty, depth)?;
+                    entries.push((key, value));
+                }
+                self.end_array(end)?;
+                ContainerValueRef::Dict(entries)
+            }
+            ContainerType::Struct(ref field_tys) => {
+                let depth = depth.enter_struct(offset)?;
+                self.align(8)?;
+                let mut fields = Vec::with_capacity(field_tys.len());
+                for field_ty in field_tys {
+                    fields.push(self.value(field_ty, depth)?);
+                }
+                ContainerValueRef::Struct(fields)
+            }
+            ContainerType::Variant => {
+                let depth = depth.enter_variant(offset)?;
+                let inner_ty = self.single_type_signature()?;
+                let inner = self.value(&inner_ty, depth)?;
+                ContainerValueRef::Variant(inner_ty, Box::new(inner))
+            }
+            #[cfg(feature = "maybe")]
+            ContainerType::Maybe(_) => return Err(malformed("maybe types are GVariant-only")),
+        })
+    }
+
+    /// Validates a value of type `ty` and steps over it without building it.
+    pub fn skip(&mut self, ty: &Type, depth: Depth) -> Result<()> {
+        let offset = self.pos;
+        match *ty {
+            Type::BasicType(BasicType::String) |
+            Type::BasicType(BasicType::ObjectPath) => self.str().map(|_| ()),
+            Type::BasicType(BasicType::Signature) => self.signature().map(|_| ()),
+            Type::BasicType(ref basic_ty) => self.basic_value(basic_ty).map(|_| ()),
+            Type::ContainerType(ref container_ty) => {
+                match **container_ty {
+                    ContainerType::Array(ref elem_ty) => {
+                        let depth = depth.enter_array(offset)?;
+                        let end = self.begin_array(alignment(elem_ty))?;
+                        while self.pos < end {
+                            self.skip(elem_ty, depth)?;
+                        }
+                        self.end_array(end)
+                    }
+                    ContainerType::Dict(ref key_ty, ref value_ty) => {
+                        let depth = depth.enter_array(offset)?.enter_struct(offset)?;
+                        let end = self.begin_array(8)?;
+                        let key_ty = Type::BasicType(key_ty.clone());
+                        while self.pos < end {
+                            self.align(8)?;
+                            self.skip(&key_ty, depth)?;
+                            self.skip(value_ty, depth)?;
+                        }
+                        self.end_array(end)
+                    }
+                    ContainerType::Struct(ref field_tys) => {
+                        let depth = depth.enter_struct(offset)?;
+                        self.align(8)?;
+                        for field_ty in field_tys {
+                            self.skip(field_ty, depth)?;
+                        }
+                        Ok(())
+                    }
+                    ContainerType::Variant => {
+                        let depth = depth.enter_variant(offset)?;
+                        let inner_ty = self.single_type_signature()?;
+                        self.skip(&inner_ty, depth)
+                    }
+                    #[cfg(feature = "maybe")]
+                    ContainerType::Maybe(_) => Err(malformed("maybe types are GVariant-only")),
+                }
+            }
+        }
+    }
+}
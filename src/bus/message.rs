@@ -0,0 +1,643 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::cmp;
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{Error, ErrorKind, Result};
+use std::mem;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use bus::borrowed::{BasicValueRef, ValueRef};
+use bus::cursor::ValueCursor;
+use bus::fd::OwnedFd;
+use bus::marshal::{self, Decoder, Encoder, Endianness};
+use bus::types::{Depth, Signature, Type};
+use bus::wire::{BasicValue, Value};
+
+/// The largest message the D-Bus specification allows, in bytes.
+pub const MAX_MESSAGE_SIZE: u32 = 128 * 1024 * 1024;
+/// The largest array the D-Bus specification allows, in bytes.
+pub const MAX_ARRAY_LEN: u32 = 64 * 1024 * 1024;
+
+/// A header flag: the caller doesn't want a reply to this method call.
+pub const NO_REPLY_EXPECTED: u8 = 0x1;
+/// A header flag: the bus must not start a service to receive this message.
+pub const NO_AUTO_START: u8 = 0x2;
+/// A header flag: the caller is prepared to wait for the receiver to ask the
+/// user whether to allow the call.
+pub const ALLOW_INTERACTIVE_AUTHORIZATION: u8 = 0x4;
+
+// The fixed part of the header, up to and including the length of the header
+// field array.
+const FIXED_HEADER_LEN: usize = 16;
+
+/// Size limits enforced on incoming messages. The defaults are the maxima
+/// from the specification; lower limits can be set per connection.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Limits {
+    max_message_size: u32,
+    max_array_len: u32,
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Limits {
+            max_message_size: MAX_MESSAGE_SIZE,
+            max_array_len: MAX_ARRAY_LEN,
+        }
+    }
+
+    /// Sets the message size limit, which is capped at `MAX_MESSAGE_SIZE`.
+    pub fn with_max_message_size(self, size: u32) -> Self {
+        Limits { max_message_size: cmp::min(size, MAX_MESSAGE_SIZE), ..self }
+    }
+
+    /// Sets the array length limit, which is capped at `MAX_ARRAY_LEN`.
+    pub fn with_max_array_len(self, len: u32) -> Self {
+        Limits { max_array_len: cmp::min(len, MAX_ARRAY_LEN), ..self }
+    }
+
+    pub fn max_message_size(&self) -> u32 {
+        self.max_message_size
+    }
+
+    pub fn max_array_len(&self) -> u32 {
+        self.max_array_len
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits::new()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LimitError {
+    MessageTooLarge { size: u64, limit: u32 },
+    ArrayTooLarge { len: u32, limit: u32 },
+}
+
+impl Display for LimitError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            LimitError::MessageTooLarge { size, limit } => {
+                write!(f,
+                       "D-Bus message of {} bytes exceeds the maximum message size of {} bytes.",
+                       size,
+                       limit)
+            }
+            LimitError::ArrayTooLarge { len, limit } => {
+                write!(f,
+                       "D-Bus array of {} bytes exceeds the maximum array length of {} bytes.",
+                       len,
+                       limit)
+            }
+        }
+    }
+}
+
+impl error::Error for LimitError {
+    fn description(&self) -> &str {
+        match *self {
+            LimitError::MessageTooLarge { .. } => "D-Bus message size limit exceeded",
+            LimitError::ArrayTooLarge { .. } => "D-Bus array length limit exceeded",
+        }
+    }
+}
+
+impl From<LimitError> for Error {
+    fn from(err: LimitError) -> Self {
+        Error::new(ErrorKind::InvalidData, err)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MessageType {
+    MethodCall,
+    MethodReturn,
+    Error,
+    Signal,
+    Unknown(u8),
+}
+
+impl MessageType {
+    fn from_code(code: u8) -> Self {
+        match code {
+            1 => MessageType::MethodCall,
+            2 => MessageType::MethodReturn,
+            3 => MessageType::Error,
+            4 => MessageType::Signal,
+            code => MessageType::Unknown(code),
+        }
+    }
+
+    fn code(&self) -> u8 {
+        match *self {
+            MessageType::MethodCall => 1,
+            MessageType::MethodReturn => 2,
+            MessageType::Error => 3,
+            MessageType::Signal => 4,
+            MessageType::Unknown(code) => code,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct HeaderFields {
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub reply_serial: Option<u32>,
+    pub destination: Option<String>,
+    pub sender: Option<String>,
+    pub signature: Option<Signature>,
+    pub unix_fds: Option<u32>,
+}
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SENDER: u8 = 7;
+const FIELD_SIGNATURE: u8 = 8;
+const FIELD_UNIX_FDS: u8 = 9;
+
+/// A D-Bus message. The body is kept in its marshalled form and only
+/// unmarshalled on request; a received message shares the buffer it was read
+/// into rather than copying its body out. `UnixFd` values in the body are
+/// indexes into the descriptors attached to the message.
+#[derive(Debug, PartialEq)]
+pub struct Message {
+    pub message_type: MessageType,
+    pub flags: u8,
+    pub serial: u32,
+    pub fields: HeaderFields,
+    endianness: Endianness,
+    body: Body,
+    fds: Vec<OwnedFd>,
+}
+
+// A marshalled body: a range of a buffer that may be shared with other
+// messages, such as the ones received alongside it.
+#[derive(Clone)]
+struct Body {
+    buf: Arc<Vec<u8>>,
+    start: usize,
+    end: usize,
+}
+
+impl Body {
+    fn new(bytes: Vec<u8>) -> Self {
+        Body {
+            start: 0,
+            end: bytes.len(),
+            buf: Arc::new(bytes),
+        }
+    }
+}
+
+impl Deref for Body {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[self.start..self.end]
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl PartialEq for Body {
+    fn eq(&self, other: &Body) -> bool {
+        **self == **other
+    }
+}
+
+impl Message {
+    pub fn new(message_type: MessageType) -> Self {
+        Message {
+            message_type: message_type,
+            flags: 0,
+            serial: 0,
+            fields: HeaderFields::default(),
+            endianness: Endianness::native(),
+            body: Body::new(Vec::new()),
+            fds: Vec::new(),
+        }
+    }
+
+    /// Copies the message, duplicating any attached descriptors.
+    pub fn try_clone(&self) -> Result<Self> {
+        let mut fds = Vec::with_capacity(self.fds.len());
+        for fd in &self.fds {
+            fds.push(fd.try_clone()?);
+        }
+        Ok(Message {
+            message_type: self.message_type,
+            flags: self.flags,
+            serial: self.serial,
+            fields: self.fields.clone(),
+            endianness: self.endianness,
+            body: self.body.clone(),
+            fds: fds,
+        })
+    }
+
+    pub fn method_call<P, M>(path: P, member: M) -> Self
+        where P: Into<String>,
+              M: Into<String>
+    {
+        let mut msg = Message::new(MessageType::MethodCall);
+        msg.fields.path = Some(path.into());
+        msg.fields.member = Some(member.into());
+        msg
+    }
+
+    pub fn signal<P, I, M>(path: P, interface: I, member: M) -> Self
+        where P: Into<String>,
+              I: Into<String>,
+              M: Into<String>
+    {
+        let mut msg = Message::new(MessageType::Signal);
+        msg.fields.path = Some(path.into());
+        msg.fields.interface = Some(interface.into());
+        msg.fields.member = Some(member.into());
+        msg
+    }
+
+    pub fn method_return(call: &Message) -> Self {
+        let mut msg = Message::new(MessageType::MethodReturn);
+        msg.fields.reply_serial = Some(call.serial);
+        msg.fields.destination = call.fields.sender.clone();
+        msg
+    }
+
+    pub fn error<N>(call: &Message, error_name: N) -> Self
+        where N: Into<String>
+    {
+        let mut msg = Message::new(MessageType::Error);
+        msg.fields.error_name = Some(error_name.into());
+        msg.fields.reply_serial = Some(call.serial);
+        msg.fields.destination = call.fields.sender.clone();
+        msg
+    }
+
+    /// Whether the header flag `flag`, such as `NO_REPLY_EXPECTED`, is set.
+    pub fn has_flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub fn set_flag(&mut self, flag: u8, on: bool) {
+        if on {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    pub fn signature(&self) -> Signature {
+        self.fields.signature.clone().unwrap_or_default()
+    }
+
+    /// Attaches a descriptor to be sent with the message, returning the index
+    /// by which `UnixFd` values in the body refer to it.
+    pub fn attach_fd(&mut self, fd: OwnedFd) -> u32 {
+        self.fds.push(fd);
+        self.fields.unix_fds = Some(self.fds.len() as u32);
+        self.fds.len() as u32 - 1
+    }
+
+    pub fn fds(&self) -> &[OwnedFd] {
+        &self.fds
+    }
+
+    /// Looks up the descriptor that a `UnixFd` value refers to.
+    pub fn fd(&self, index: u32) -> Option<&OwnedFd> {
+        self.fds.get(index as usize)
+    }
+
+    /// Takes ownership of the attached descriptors. `UnixFd` values in the
+    /// body still index into the returned vector.
+    pub fn take_fds(&mut self) -> Vec<OwnedFd> {
+        mem::replace(&mut self.fds, Vec::new())
+    }
+
+    pub fn body_bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Unmarshals the body according to the message's signature.
+    pub fn body(&self) -> Result<Vec<Value>> {
+        marshal::decode_values(&self.body,
+                               &self.signature(),
+                               self.endianness,
+                               MAX_ARRAY_LEN)
+    }
+
+    /// Unmarshals the body without copying strings, object paths, signatures
+    /// or byte arrays out of the message. For a received message they point
+    /// into the buffer it was read into.
+    pub fn body_ref<'a>(&'a self) -> Result<Vec<ValueRef<'a>>> {
+        marshal::decode_values_ref(&self.body,
+                                   &self.signature(),
+                                   self.endianness,
+                                   MAX_ARRAY_LEN)
+    }
+
+    /// A cursor over the body, for reading parts of it without unmarshalling
+    /// the rest.
+    pub fn body_cursor<'a>(&'a self) -> ValueCursor<'a, 'a> {
+        ValueCursor::new(&self.body, &self.signature(), self.endianness, MAX_ARRAY_LEN)
+    }
+
+    /// Replaces the body, inferring its signature from the values.
+    pub fn set_body(&mut self, values: &[Value]) -> Result<()> {
+        let mut tys = Vec::with_capacity(values.len());
+        for value in values {
+            tys.push(value.signature()?);
+        }
+        self.set_body_with_signature(values, Signature::new(tys))
+    }
+
+    pub fn set_body_with_signature(&mut self,
+                                   values: &[Value],
+                                   signature: Signature)
+                                   -> Result<()> {
+        let mut body = Vec::new();
+        marshal::encode_values(values, &signature, self.endianness, &mut body)?;
+        self.body = Body::new(body);
+        self.fields.signature = if signature.is_empty() {
+            None
+        } else {
+            Some(signature)
+        };
+        Ok(())
+    }
+}
+
+fn malformed(what: &str) -> Error {
+    Error::new(ErrorKind::InvalidData,
+               format!("malformed D-Bus message: {}", what))
+}
+
+/// Decodes one message from the front of `input`, checking it against
+/// `limits`. A message that is too large is rejected as soon as its fixed
+/// header has arrived, before any more of it is buffered. The body is copied
+/// out of `input`; `decode_shared_message` avoids that.
+pub fn decode_message<'a>(input: &'a [u8],
+                          limits: &Limits)
+                          -> Result<Option<(Message, &'a [u8])>> {
+    let decoded = decode_message_with(input,
+                                      limits,
+                                      |start, end| Body::new(input[start..end].to_vec()))?;
+    Ok(decoded.map(|(msg, size)| (msg, &input[size..])))
+}
+
+/// Decodes one message from `buf`, starting at `offset`, like
+/// `decode_message`. Rather than being copied, the body stays in `buf`, which
+/// the message holds on to. Returns the offset just past the message.
+pub fn decode_shared_message(buf: &Arc<Vec<u8>>,
+                             offset: usize,
+                             limits: &Limits)
+                             -> Result<Option<(Message, usize)>> {
+    let decoded = decode_message_with(&buf[offset..], limits, |start, end| {
+        Body {
+            buf: buf.clone(),
+            start: offset + start,
+            end: offset + end,
+        }
+    })?;
+    Ok(decoded.map(|(msg, size)| (msg, offset + size)))
+}
+
+// Decodes a message from the front of `input`, making its body out of the
+// range of `input` it occupies. Returns the message and its size.
+fn decode_message_with<F>(input: &[u8],
+                          limits: &Limits,
+                          body: F)
+                          -> Result<Option<(Message, usize)>>
+    where F: FnOnce(usize, usize) -> Body
+{
+    if input.len() < FIXED_HEADER_LEN {
+        return Ok(None);
+    }
+
+    let endianness = match Endianness::from_code(input[0]) {
+        Some(endianness) => endianness,
+        None => return Err(malformed("unknown endianness")),
+    };
+    if input[3] != 1 {
+        return Err(malformed("unsupported protocol version"));
+    }
+    let body_len = endianness.read_u32(&input[4..8]);
+    let fields_len = endianness.read_u32(&input[12..16]);
+
+    if fields_len > limits.max_array_len {
+        return Err(LimitError::ArrayTooLarge {
+                len: fields_len,
+                limit: limits.max_array_len,
+            }
+            .into());
+    }
+    let body_start = align8(FIXED_HEADER_LEN as u64 + fields_len as u64);
+    let size = body_start + body_len as u64;
+    if size > limits.max_message_size as u64 {
+        return Err(LimitError::MessageTooLarge {
+                size: size,
+                limit: limits.max_message_size,
+            }
+            .into());
+    }
+    if (input.len() as u64) < size {
+        return Ok(None);
+    }
+
+    let fields_end = FIXED_HEADER_LEN + fields_len as usize;
+    let body_start = body_start as usize;
+    let size = size as usize;
+
+    let serial = endianness.read_u32(&input[8..12]);
+    if serial == 0 {
+        return Err(malformed("zero serial"));
+    }
+    let message_type = MessageType::from_code(input[1]);
+    let fields = decode_header_fields(&input[FIXED_HEADER_LEN..fields_end],
+                                      endianness,
+                                      limits.max_array_len)?;
+    check_required_fields(message_type, &fields)?;
+    if input[fields_end..body_start].iter().any(|&b| b != 0) {
+        return Err(malformed("nonzero header padding"));
+    }
+
+    let signature = fields.signature.clone().unwrap_or_default();
+    let mut decoder =
+        Decoder::new(&input[body_start..size], 0, endianness, limits.max_array_len)
+            .with_unix_fds(fields.unix_fds.unwrap_or(0));
+    for ty in &signature {
+        decoder.skip(ty, Depth::new())?;
+    }
+    decoder.finish()?;
+
+    let msg = Message {
+        message_type: message_type,
+        flags: input[2],
+        serial: serial,
+        fields: fields,
+        endianness: endianness,
+        body: body(body_start, size),
+        // The transport attaches the descriptors that arrived with the
+        // message.
+        fds: Vec::new(),
+    };
+    Ok(Some((msg, size)))
+}
+
+fn align8(n: u64) -> u64 {
+    (n + 7) & !7
+}
+
+fn decode_header_fields(input: &[u8],
+                        endianness: Endianness,
+                        max_array_len: u32)
+                        -> Result<HeaderFields> {
+    // Header fields are (yv) structs inside an array, so their values start
+    // three containers deep.
+    let depth = Depth::new().enter_array(0)?.enter_struct(0)?.enter_variant(0)?;
+
+    let mut fields = HeaderFields::default();
+    let mut decoder = Decoder::new(input, FIXED_HEADER_LEN, endianness, max_array_len);
+    while decoder.position() < input.len() {
+        decoder.align(8)?;
+        let code = decoder.byte()?;
+        let ty = decoder.single_type_signature()?;
+        let value = match decoder.value(&ty, depth)? {
+            ValueRef::BasicValue(value) => value,
+            // Unknown fields must be ignored, whatever their type.
+            ValueRef::ContainerValue(_) if code > FIELD_UNIX_FDS => continue,
+            ValueRef::ContainerValue(_) => return Err(malformed("header field has wrong type")),
+        };
+        match (code, value) {
+            (FIELD_PATH, BasicValueRef::ObjectPath(path)) => fields.path = Some(path.to_owned()),
+            (FIELD_INTERFACE, BasicValueRef::String(s)) => fields.interface = Some(s.to_owned()),
+            (FIELD_MEMBER, BasicValueRef::String(s)) => fields.member = Some(s.to_owned()),
+            (FIELD_ERROR_NAME, BasicValueRef::String(s)) => fields.error_name = Some(s.to_owned()),
+            (FIELD_REPLY_SERIAL, BasicValueRef::UInt32(n)) => fields.reply_serial = Some(n),
+            (FIELD_DESTINATION, BasicValueRef::String(s)) => {
+                fields.destination = Some(s.to_owned())
+            }
+            (FIELD_SENDER, BasicValueRef::String(s)) => fields.sender = Some(s.to_owned()),
+            (FIELD_SIGNATURE, BasicValueRef::Signature(sig)) => {
+                fields.signature = Some(sig.parse()?)
+            }
+            (FIELD_UNIX_FDS, BasicValueRef::UInt32(n)) => fields.unix_fds = Some(n),
+            (code, _) if code > FIELD_UNIX_FDS => {}
+            _ => return Err(malformed("header field has wrong type")),
+        }
+    }
+    Ok(fields)
+}
+
+fn check_required_fields(message_type: MessageType, fields: &HeaderFields) -> Result<()> {
+    let ok = match message_type {
+        MessageType::MethodCall => fields.path.is_some() && fields.member.is_some(),
+        MessageType::MethodReturn => fields.reply_serial.is_some(),
+        MessageType::Error => fields.error_name.is_some() && fields.reply_serial.is_some(),
+        MessageType::Signal => {
+            fields.path.is_some() && fields.interface.is_some() && fields.member.is_some()
+        }
+        MessageType::Unknown(_) => true,
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(malformed("missing required header field"))
+    }
+}
+
+/// Marshals `msg`, appending it to `output`. It fails, writing nothing, if
+/// the body's signature is too long for the header.
+pub fn encode_message(msg: &Message, output: &mut Vec<u8>) -> Result<()> {
+    if let Some(ref signature) = msg.fields.signature {
+        marshal::check_signature_len(signature)?;
+    }
+    // Alignment is relative to the start of the message, so build it in a
+    // buffer of its own.
+    let mut buf = Vec::with_capacity(FIXED_HEADER_LEN + 64 + msg.body.len());
+    {
+        let mut encoder = Encoder::new(&mut buf, msg.endianness);
+        encoder.byte(msg.endianness.code());
+        encoder.byte(msg.message_type.code());
+        encoder.byte(msg.flags);
+        encoder.byte(1);
+        encoder.u32(msg.body.len() as u32);
+        encoder.u32(msg.serial);
+
+        let fields = &msg.fields;
+        let array = encoder.begin_array(8);
+        if let Some(ref path) = fields.path {
+            let value = BasicValue::ObjectPath(path.as_bytes().to_vec().into());
+            encode_header_field(&mut encoder, FIELD_PATH, value);
+        }
+        if let Some(ref interface) = fields.interface {
+            let value = BasicValue::String(interface.clone().into());
+            encode_header_field(&mut encoder, FIELD_INTERFACE, value);
+        }
+        if let Some(ref member) = fields.member {
+            let value = BasicValue::String(member.clone().into());
+            encode_header_field(&mut encoder, FIELD_MEMBER, value);
+        }
+        if let Some(ref error_name) = fields.error_name {
+            let value = BasicValue::String(error_name.clone().into());
+            encode_header_field(&mut encoder, FIELD_ERROR_NAME, value);
+        }
+        if let Some(reply_serial) = fields.reply_serial {
+            encode_header_field(&mut encoder,
+                                FIELD_REPLY_SERIAL,
+                                BasicValue::UInt32(reply_serial));
+        }
+        if let Some(ref destination) = fields.destination {
+            let value = BasicValue::String(destination.clone().into());
+            encode_header_field(&mut encoder, FIELD_DESTINATION, value);
+        }
+        if let Some(ref sender) = fields.sender {
+            let value = BasicValue::String(sender.clone().into());
+            encode_header_field(&mut encoder, FIELD_SENDER, value);
+        }
+        if let Some(ref signature) = fields.signature {
+            let value = BasicValue::Signature(signature.clone());
+            encode_header_field(&mut encoder, FIELD_SIGNATURE, value);
+        }
+        if let Some(unix_fds) = fields.unix_fds {
+            encode_header_field(&mut encoder, FIELD_UNIX_FDS, BasicValue::UInt32(unix_fds));
+        }
+        encoder.end_array(array);
+        encoder.align(8);
+    }
+    buf.extend_from_slice(&msg.body);
+
+    output.extend_from_slice(&buf);
+    Ok(())
+}
+
+fn encode_header_field(encoder: &mut Encoder, code: u8, value: BasicValue) {
+    let ty = Type::BasicType(value.basic_type());
+    encoder.align(8);
+    encoder.byte(code);
+    encoder.signature(&Signature::new(vec![ty.clone()]));
+    encoder.value(&Value::BasicValue(value), &ty);
+}
+
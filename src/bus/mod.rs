@@ -2,10 +2,70 @@
 // v. 2.0. If a copy of the MPL was not distributed with this file, You can
 // obtain one at http://mozilla.org/MPL/2.0/.
 
+mod borrowed;
 mod client;
+mod convert;
+mod cursor;
+#[cfg(feature = "serde")]
+mod de;
+// Support code for the macros in `tokio-dbus-derive`; not a stable API.
+#[doc(hidden)]
+pub mod derive;
+mod error;
+mod fd;
+mod gvariant;
+#[cfg(feature = "serde_json")]
+mod json;
+mod literal;
+mod marshal;
+mod message;
+mod names;
+mod rule;
+#[cfg(feature = "serde")]
+mod ser;
+mod server;
+#[cfg(feature = "serde")]
+mod trace;
+mod text;
+mod transport;
 mod types;
+mod variant;
 mod wire;
 
-pub use bus::client::Bus;
-pub use bus::types::{Signature, BasicType, ContainerType, Type, decode_signature, encode_signature};
-pub use bus::wire::{BasicValue, ContainerValue, Value};
+pub use bus::borrowed::{BasicValueRef, ContainerValueRef, ValueRef};
+pub use bus::client::{Bus, BusDriver, BusHandle, Closed, PendingCall, Reconnected, Reconnects,
+                      SignalStream, Supervisor, DEFAULT_CALL_TIMEOUT, NAME_ALLOW_REPLACEMENT,
+                      NAME_DO_NOT_QUEUE, NAME_REPLACE_EXISTING};
+pub use bus::convert::{BasicDBusType, DBusType, FromDBus, ToDBus};
+pub use bus::cursor::ValueCursor;
+#[cfg(feature = "serde")]
+pub use bus::de::{Deserializer, from_bytes, from_value};
+pub use bus::error::{DBusError, StandardError};
+pub use bus::fd::OwnedFd;
+pub use bus::gvariant::{decode_gvariant, encode_gvariant};
+#[cfg(feature = "serde_json")]
+pub use bus::json::{JsonError, JsonOptions, value_from_json, value_to_json, values_from_json,
+                    values_to_json};
+pub use bus::literal::ConstSignature;
+pub use bus::marshal::{Endianness, decode_values, decode_values_ref, encode_values};
+pub use bus::message::{HeaderFields, LimitError, Limits, Message, MessageType,
+                       ALLOW_INTERACTIVE_AUTHORIZATION, MAX_ARRAY_LEN, MAX_MESSAGE_SIZE,
+                       NO_AUTO_START, NO_REPLY_EXPECTED, decode_message,
+                       decode_shared_message, encode_message};
+pub use bus::names::{BusName, ErrorName, InterfaceName, MemberName, NameError, ObjectPath,
+                     MAX_NAME_LEN};
+pub use bus::rule::{MatchRule, RuleError, MAX_MATCH_ARG};
+#[cfg(feature = "serde")]
+pub use bus::ser::{AsVariant, Serializer, to_bytes, to_value};
+pub use bus::server::{MethodResult, ObjectServer};
+#[cfg(feature = "serde")]
+pub use bus::trace::signature_of;
+pub use bus::text::{TextError, parse_typed_value, parse_value, print_typed_value,
+                    print_value};
+pub use bus::types::{Signature, SignatureError, BasicType, ContainerType, Type, MAX_ARRAY_DEPTH,
+                     MAX_SIGNATURE_LEN, MAX_STRUCT_DEPTH, MAX_TOTAL_DEPTH, decode_signature,
+                     encode_signature, validate_gvariant_signature, validate_gvariant_type,
+                     validate_signature, validate_single_type};
+pub use bus::variant::{PropertyMap, Variant};
+pub use bus::wire::{BasicValue, ContainerValue, PathSegment, TypeError, TypeErrorKind, Value,
+                    check_signature};
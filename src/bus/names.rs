@@ -0,0 +1,170 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{Error, ErrorKind};
+use std::ops::Deref;
+use std::result;
+use std::str::FromStr;
+
+/// The longest bus, interface, member or error name the specification allows.
+pub const MAX_NAME_LEN: usize = 255;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NameError {
+    pub kind: &'static str,
+    pub name: String,
+}
+
+impl Display for NameError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:?} is not a valid D-Bus {}.", self.name, self.kind)
+    }
+}
+
+impl error::Error for NameError {
+    fn description(&self) -> &str {
+        "invalid D-Bus name"
+    }
+}
+
+impl From<NameError> for Error {
+    fn from(err: NameError) -> Self {
+        Error::new(ErrorKind::InvalidInput, err)
+    }
+}
+
+macro_rules! name_type {
+    ($(#[$attr:meta])* pub struct $name:ident, $kind:expr, $is_valid:ident) => {
+        $(#[$attr])*
+        #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+        pub struct $name(String);
+
+        impl $name {
+            pub fn new<S>(name: S) -> result::Result<Self, NameError>
+                where S: Into<String>
+            {
+                let name = name.into();
+                if $is_valid(&name) {
+                    Ok($name(name))
+                } else {
+                    Err(NameError {
+                        kind: $kind,
+                        name: name,
+                    })
+                }
+            }
+
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+
+            pub fn into_string(self) -> String {
+                self.0
+            }
+        }
+
+        impl Deref for $name {
+            type Target = str;
+
+            fn deref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = NameError;
+
+            fn from_str(s: &str) -> result::Result<Self, NameError> {
+                $name::new(s)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(name: $name) -> String {
+                name.0
+            }
+        }
+    }
+}
+
+name_type!(pub struct ObjectPath, "object path", is_valid_object_path);
+name_type!(pub struct InterfaceName, "interface name", is_valid_interface_name);
+name_type!(pub struct MemberName, "member name", is_valid_member_name);
+name_type!(pub struct ErrorName, "error name", is_valid_interface_name);
+name_type!(
+    /// A unique connection name, such as `:1.42`, or a well-known name, such
+    /// as `org.freedesktop.DBus`.
+    pub struct BusName, "bus name", is_valid_bus_name);
+
+impl BusName {
+    /// Whether this is a unique connection name rather than a well-known
+    /// one.
+    pub fn is_unique(&self) -> bool {
+        self.0.starts_with(':')
+    }
+}
+
+fn is_name_char(c: u8) -> bool {
+    (c >= b'A' && c <= b'Z') || (c >= b'a' && c <= b'z') || is_digit(c) || c == b'_'
+}
+
+fn is_digit(c: u8) -> bool {
+    c >= b'0' && c <= b'9'
+}
+
+pub fn is_valid_object_path(path: &str) -> bool {
+    let path = path.as_bytes();
+    if path == b"/" {
+        return true;
+    }
+    path.first() == Some(&b'/') &&
+    path[1..].split(|&c| c == b'/').all(|elem| {
+        !elem.is_empty() && elem.iter().all(|&c| is_name_char(c))
+    })
+}
+
+fn is_valid_element(elem: &[u8], allow_hyphen: bool, allow_leading_digit: bool) -> bool {
+    match elem.first() {
+        None => false,
+        Some(&c) if is_digit(c) && !allow_leading_digit => false,
+        Some(_) => elem.iter().all(|&c| is_name_char(c) || (allow_hyphen && c == b'-')),
+    }
+}
+
+pub fn is_valid_interface_name(name: &str) -> bool {
+    let name = name.as_bytes();
+    name.len() <= MAX_NAME_LEN && name.contains(&b'.') &&
+    name.split(|&c| c == b'.').all(|elem| is_valid_element(elem, false, false))
+}
+
+pub fn is_valid_member_name(name: &str) -> bool {
+    name.len() <= MAX_NAME_LEN && is_valid_element(name.as_bytes(), false, false)
+}
+
+pub fn is_valid_bus_name(name: &str) -> bool {
+    let name = name.as_bytes();
+    if name.len() > MAX_NAME_LEN {
+        return false;
+    }
+    let (name, unique) = match name.first() {
+        Some(&b':') => (&name[1..], true),
+        _ => (name, false),
+    };
+    name.contains(&b'.') &&
+    name.split(|&c| c == b'.').all(|elem| is_valid_element(elem, true, unique))
+}
@@ -0,0 +1,472 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{Error, ErrorKind};
+use std::result;
+use std::str::FromStr;
+
+use bus::borrowed::{BasicValueRef, ValueRef};
+use bus::message::{Message, MessageType};
+use bus::names::{BusName, InterfaceName, MemberName, NameError, ObjectPath};
+
+/// The highest argument index a rule can test.
+pub const MAX_MATCH_ARG: u8 = 63;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RuleError {
+    pub reason: String,
+}
+
+impl Display for RuleError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Invalid D-Bus match rule: {}.", self.reason)
+    }
+}
+
+impl error::Error for RuleError {
+    fn description(&self) -> &str {
+        "invalid D-Bus match rule"
+    }
+}
+
+impl From<RuleError> for Error {
+    fn from(err: RuleError) -> Self {
+        Error::new(ErrorKind::InvalidInput, err)
+    }
+}
+
+impl From<NameError> for RuleError {
+    fn from(err: NameError) -> Self {
+        RuleError { reason: format!("{:?} is not a valid {}", err.name, err.kind) }
+    }
+}
+
+/// A rule selecting the messages a connection wants routed to it, as passed
+/// to the bus's `AddMatch`. A rule with no conditions matches everything.
+///
+/// Rules are built fluently, and formatted and parsed in the text form the
+/// bus uses:
+///
+/// ```
+/// # use tokio_dbus::{InterfaceName, MatchRule, MessageType};
+/// let rule = MatchRule::new()
+///     .message_type(MessageType::Signal)
+///     .interface(InterfaceName::new("org.example.Interface").unwrap())
+///     .arg(0, "it's");
+/// let text = rule.to_string();
+/// assert_eq!(text, r"type='signal',interface='org.example.Interface',arg0='it'\''s'");
+/// assert_eq!(text.parse::<MatchRule>().unwrap(), rule);
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MatchRule {
+    message_type: Option<MessageType>,
+    sender: Option<BusName>,
+    interface: Option<InterfaceName>,
+    member: Option<MemberName>,
+    path: Option<PathCondition>,
+    destination: Option<BusName>,
+    args: BTreeMap<u8, ArgCondition>,
+    eavesdrop: bool,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum PathCondition {
+    Equals(ObjectPath),
+    Namespace(ObjectPath),
+}
+
+// What a rule requires of one argument. An argument can only be tested one
+// way, as the bus stores them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum ArgCondition {
+    Equals(String),
+    Path(String),
+    Namespace(String),
+}
+
+impl MatchRule {
+    pub fn new() -> Self {
+        MatchRule::default()
+    }
+
+    pub fn message_type(mut self, message_type: MessageType) -> Self {
+        self.message_type = Some(message_type);
+        self
+    }
+
+    pub fn sender(mut self, sender: BusName) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    pub fn interface(mut self, interface: InterfaceName) -> Self {
+        self.interface = Some(interface);
+        self
+    }
+
+    pub fn member(mut self, member: MemberName) -> Self {
+        self.member = Some(member);
+        self
+    }
+
+    /// Requires the object path to be `path`. Replaces any `path_namespace`.
+    pub fn path(mut self, path: ObjectPath) -> Self {
+        self.path = Some(PathCondition::Equals(path));
+        self
+    }
+
+    /// Requires the object path to be `path` or a path below it. Replaces
+    /// any `path`.
+    pub fn path_namespace(mut self, path: ObjectPath) -> Self {
+        self.path = Some(PathCondition::Namespace(path));
+        self
+    }
+
+    pub fn destination(mut self, destination: BusName) -> Self {
+        self.destination = Some(destination);
+        self
+    }
+
+    /// Requires argument `index` to be a string equal to `value`.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is above `MAX_MATCH_ARG`.
+    pub fn arg<S>(self, index: u8, value: S) -> Self
+        where S: Into<String>
+    {
+        self.with_arg(index, ArgCondition::Equals(value.into()))
+    }
+
+    /// Requires argument `index` to be a string or object path that is
+    /// `path`, or, where one of them ends in `/`, lies below the other.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is above `MAX_MATCH_ARG`.
+    pub fn arg_path<S>(self, index: u8, path: S) -> Self
+        where S: Into<String>
+    {
+        self.with_arg(index, ArgCondition::Path(path.into()))
+    }
+
+    /// Requires the first argument to be a string that is the bus or
+    /// interface name `namespace` or a name within it, as `org.example.A`
+    /// is within `org.example`.
+    pub fn arg0_namespace<S>(self, namespace: S) -> Self
+        where S: Into<String>
+    {
+        self.with_arg(0, ArgCondition::Namespace(namespace.into()))
+    }
+
+    /// Asks the bus for matching messages addressed to other connections
+    /// too. This is only a request to the bus; it doesn't change `matches`.
+    pub fn eavesdrop(mut self, eavesdrop: bool) -> Self {
+        self.eavesdrop = eavesdrop;
+        self
+    }
+
+    fn with_arg(mut self, index: u8, condition: ArgCondition) -> Self {
+        assert!(index <= MAX_MATCH_ARG,
+                "match rules can only test arguments up to {}",
+                MAX_MATCH_ARG);
+        self.args.insert(index, condition);
+        self
+    }
+
+    /// Whether `msg` meets every condition of the rule, as the bus would
+    /// decide. Peer connections and monitors get messages the bus hasn't
+    /// filtered, so they need to do this themselves.
+    ///
+    /// The bus also matches a well-known `sender` against the unique name
+    /// that owns it; here it is only compared with the message's sender.
+    pub fn matches(&self, msg: &Message) -> bool {
+        field_matches(&self.sender, &msg.fields.sender) && self.matches_all_but_sender(msg)
+    }
+
+    /// Like `matches`, for a message the bus has routed to this connection.
+    /// The bus gives other connections' messages their unique names as
+    /// senders, so a well-known `sender` is taken to match any unique name,
+    /// as only the bus knows which one owns it.
+    pub fn matches_routed(&self, msg: &Message) -> bool {
+        let well_known = self.sender.as_ref().map_or(false, |sender| !sender.is_unique());
+        let from_unique =
+            msg.fields.sender.as_ref().map_or(false, |sender| sender.starts_with(':'));
+        ((well_known && from_unique) || field_matches(&self.sender, &msg.fields.sender)) &&
+        self.matches_all_but_sender(msg)
+    }
+
+    fn matches_all_but_sender(&self, msg: &Message) -> bool {
+        if let Some(message_type) = self.message_type {
+            if msg.message_type != message_type {
+                return false;
+            }
+        }
+        let path_matches = match (self.path.as_ref(), msg.fields.path.as_ref()) {
+            (None, _) => true,
+            (Some(&PathCondition::Equals(ref path)), Some(actual)) => path.as_str() == actual,
+            (Some(&PathCondition::Namespace(ref path)), Some(actual)) => {
+                in_namespace(actual, path, '/') || path.as_str() == "/"
+            }
+            (Some(_), None) => false,
+        };
+        path_matches && field_matches(&self.interface, &msg.fields.interface) &&
+        field_matches(&self.member, &msg.fields.member) &&
+        field_matches(&self.destination, &msg.fields.destination) && self.args_match(msg)
+    }
+
+    // Reads only as far into the body as the last argument tested.
+    fn args_match(&self, msg: &Message) -> bool {
+        let last = match self.args.keys().next_back() {
+            Some(&last) => last,
+            None => return true,
+        };
+        let mut body = msg.body_cursor();
+        for index in 0..last + 1 {
+            let condition = match self.args.get(&index) {
+                Some(condition) => condition,
+                None => {
+                    match body.skip() {
+                        Ok(true) => continue,
+                        _ => return false,
+                    }
+                }
+            };
+            let arg = match body.next_value() {
+                Ok(Some(ValueRef::BasicValue(arg))) => arg,
+                _ => return false,
+            };
+            let matched = match (condition, arg) {
+                (&ArgCondition::Equals(ref value), BasicValueRef::String(arg)) => value == arg,
+                (&ArgCondition::Path(ref path), BasicValueRef::String(arg)) |
+                (&ArgCondition::Path(ref path), BasicValueRef::ObjectPath(arg)) => {
+                    path == arg || (path.ends_with('/') && arg.starts_with(path.as_str())) ||
+                    (arg.ends_with('/') && path.starts_with(arg))
+                }
+                (&ArgCondition::Namespace(ref namespace), BasicValueRef::String(arg)) => {
+                    in_namespace(arg, namespace, '.')
+                }
+                _ => false,
+            };
+            if !matched {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Display for MatchRule {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut sep = "";
+        {
+            let mut pair = |key: &str, value: &str| -> fmt::Result {
+                write!(f, "{}{}=", sep, key)?;
+                sep = ",";
+                write_quoted(f, value)
+            };
+            if let Some(message_type) = self.message_type {
+                pair("type", type_name(message_type))?;
+            }
+            if let Some(ref sender) = self.sender {
+                pair("sender", sender)?;
+            }
+            if let Some(ref interface) = self.interface {
+                pair("interface", interface)?;
+            }
+            if let Some(ref member) = self.member {
+                pair("member", member)?;
+            }
+            match self.path {
+                Some(PathCondition::Equals(ref path)) => pair("path", path)?,
+                Some(PathCondition::Namespace(ref path)) => pair("path_namespace", path)?,
+                None => {}
+            }
+            if let Some(ref destination) = self.destination {
+                pair("destination", destination)?;
+            }
+            for (index, condition) in &self.args {
+                match *condition {
+                    ArgCondition::Equals(ref value) => pair(&format!("arg{}", index), value)?,
+                    ArgCondition::Path(ref path) => pair(&format!("arg{}path", index), path)?,
+                    ArgCondition::Namespace(ref namespace) => pair("arg0namespace", namespace)?,
+                }
+            }
+            if self.eavesdrop {
+                pair("eavesdrop", "true")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for MatchRule {
+    type Err = RuleError;
+
+    /// Parses a rule in the bus's text form, such as
+    /// `type='signal',member='NameOwnerChanged'`.
+    fn from_str(text: &str) -> result::Result<Self, RuleError> {
+        let mut rule = MatchRule::new();
+        let mut rest = text.trim_start();
+        while !rest.is_empty() {
+            let eq = match rest.find('=') {
+                Some(eq) => eq,
+                None => return Err(error(format!("expected `=` after {:?}", rest))),
+            };
+            let key = rest[..eq].trim();
+            let (value, after) = unquote(&rest[eq + 1..])?;
+            set(&mut rule, key, value)?;
+            rest = after.trim_start();
+            if rest.starts_with(',') {
+                rest = rest[1..].trim_start();
+            } else if !rest.is_empty() {
+                return Err(error(format!("expected `,` before {:?}", rest)));
+            }
+        }
+        Ok(rule)
+    }
+}
+
+// Applies one `key=value` pair of a parsed rule.
+fn set(rule: &mut MatchRule, key: &str, value: String) -> result::Result<(), RuleError> {
+    let duplicate = match key {
+        "type" => {
+            let message_type = match &*value {
+                "signal" => MessageType::Signal,
+                "method_call" => MessageType::MethodCall,
+                "method_return" => MessageType::MethodReturn,
+                "error" => MessageType::Error,
+                _ => return Err(error(format!("{:?} is not a message type", value))),
+            };
+            rule.message_type.replace(message_type).is_some()
+        }
+        "sender" => rule.sender.replace(BusName::new(value)?).is_some(),
+        "interface" => rule.interface.replace(InterfaceName::new(value)?).is_some(),
+        "member" => rule.member.replace(MemberName::new(value)?).is_some(),
+        "path" | "path_namespace" => {
+            let path = ObjectPath::new(value)?;
+            let condition = if key == "path" {
+                PathCondition::Equals(path)
+            } else {
+                PathCondition::Namespace(path)
+            };
+            if rule.path.is_some() {
+                return Err(error("a rule can only have one of `path` and `path_namespace`"
+                    .to_owned()));
+            }
+            rule.path = Some(condition);
+            false
+        }
+        "destination" => rule.destination.replace(BusName::new(value)?).is_some(),
+        "eavesdrop" => {
+            rule.eavesdrop = match &*value {
+                "true" => true,
+                "false" => false,
+                _ => return Err(error(format!("{:?} is not `true` or `false`", value))),
+            };
+            false
+        }
+        "arg0namespace" => rule.args.insert(0, ArgCondition::Namespace(value)).is_some(),
+        _ => {
+            let (index, condition) = match parse_arg_key(key) {
+                Some((index, true)) => (index, ArgCondition::Path(value)),
+                Some((index, false)) => (index, ArgCondition::Equals(value)),
+                None => return Err(error(format!("unknown key `{}`", key))),
+            };
+            rule.args.insert(index, condition).is_some()
+        }
+    };
+    if duplicate {
+        return Err(error(format!("`{}` is given more than once", key)));
+    }
+    Ok(())
+}
+
+fn error(reason: String) -> RuleError {
+    RuleError { reason: reason }
+}
+
+// Splits `argN` or `argNpath` into `N` and whether it's the latter.
+fn parse_arg_key(key: &str) -> Option<(u8, bool)> {
+    let key = key.strip_prefix("arg")?;
+    let (digits, is_path) = match key.strip_suffix("path") {
+        Some(digits) => (digits, true),
+        None => (key, false),
+    };
+    if digits.is_empty() || digits.len() > 2 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    match digits.parse() {
+        Ok(index) if index <= MAX_MATCH_ARG => Some((index, is_path)),
+        _ => None,
+    }
+}
+
+// Reads a value up to the next `,` outside quotes, returning it and the
+// rest. Anything between single quotes is taken as it is; outside them,
+// `\'` is a quote and every other character stands for itself.
+fn unquote(text: &str) -> result::Result<(String, &str), RuleError> {
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\'' => quoted = !quoted,
+            _ if quoted => value.push(c),
+            ',' => return Ok((value, &text[i..])),
+            '\\' if chars.peek().map(|&(_, c)| c) == Some('\'') => {
+                chars.next();
+                value.push('\'');
+            }
+            _ => value.push(c),
+        }
+    }
+    if quoted {
+        return Err(error("a quoted value is not closed".to_owned()));
+    }
+    Ok((value, ""))
+}
+
+// Quotes `value` so that `unquote` gives it back, as libdbus does.
+fn write_quoted(f: &mut Formatter, value: &str) -> fmt::Result {
+    let mut parts = value.split('\'');
+    write!(f, "'{}'", parts.next().unwrap_or(""))?;
+    for part in parts {
+        f.write_str("\\'")?;
+        if !part.is_empty() {
+            write!(f, "'{}'", part)?;
+        }
+    }
+    Ok(())
+}
+
+fn type_name(message_type: MessageType) -> &'static str {
+    match message_type {
+        MessageType::MethodCall => "method_call",
+        MessageType::MethodReturn => "method_return",
+        MessageType::Error => "error",
+        MessageType::Signal => "signal",
+        // Not something a bus would accept, but the rule says so.
+        MessageType::Unknown(_) => "unknown",
+    }
+}
+
+// Whether `name` is `namespace` or below it, with elements split by `sep`.
+fn in_namespace(name: &str, namespace: &str, sep: char) -> bool {
+    name.starts_with(namespace) &&
+    (name.len() == namespace.len() || name[namespace.len()..].starts_with(sep))
+}
+
+fn field_matches<N>(expected: &Option<N>, actual: &Option<String>) -> bool
+    where N: AsRef<str>
+{
+    match (expected.as_ref(), actual.as_ref()) {
+        (None, _) => true,
+        (Some(expected), Some(actual)) => expected.as_ref() == actual,
+        (Some(_), None) => false,
+    }
+}
@@ -0,0 +1,409 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use serde::ser::{self, Serialize};
+use std::fmt::Display;
+use std::io::Result;
+use std::result;
+
+use bus::marshal::{self, Endianness};
+use bus::types::Signature;
+use bus::wire::{BasicValue, ContainerValue, PathSegment, TypeError, TypeErrorKind, Value};
+
+/// The newtype struct name `AsVariant` goes by, which the serializer and
+/// deserializer recognize to marshal its contents as a variant.
+pub const VARIANT_NEWTYPE: &'static str = "$tokio_dbus::AsVariant";
+
+/// Marks a value to be marshalled as a variant (`v`) containing it, rather
+/// than as the value itself.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AsVariant<T>(pub T);
+
+impl<T: Serialize> Serialize for AsVariant<T> {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> result::Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(VARIANT_NEWTYPE, &self.0)
+    }
+}
+
+impl ser::Error for TypeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        TypeError::new(TypeErrorKind::Invalid(msg.to_string()))
+    }
+}
+
+fn unsupported(what: &str) -> TypeError {
+    TypeError::new(TypeErrorKind::Invalid(format!("D-Bus has no representation for {}", what)))
+}
+
+/// Converts a serde value to a `Value`. Structs, tuples and tuple structs
+/// become D-Bus structs, sequences become arrays, maps become dicts, and
+/// `AsVariant` becomes a variant. Other newtype structs are transparent.
+///
+/// Enum variants become a `(sv)` struct of their name and their contents.
+/// Unit variants hold an empty string, as they do in derived enums, so every
+/// variant of an enum has the same type.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> result::Result<Value, TypeError> {
+    value.serialize(Serializer)
+}
+
+/// Marshals a serde value according to `signature`, appending to `output` as
+/// `encode_values` does. If `signature` has more than one type, `value` must
+/// serialize as a struct, such as a tuple, with a field for each.
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T,
+                                       signature: &Signature,
+                                       endianness: Endianness,
+                                       output: &mut Vec<u8>)
+                                       -> Result<()> {
+    let value = to_value(value)?;
+    let values = match value {
+        Value::ContainerValue(ContainerValue::Struct(fields)) if signature.len() != 1 => fields,
+        value => vec![value],
+    };
+    marshal::encode_values(&values, signature, endianness, output)
+}
+
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Value;
+    type Error = TypeError;
+
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeStruct;
+    type SerializeTupleStruct = SerializeStruct;
+    type SerializeTupleVariant = SerializeStructVariant;
+    type SerializeMap = SerializeDict;
+    type SerializeStruct = SerializeStruct;
+    type SerializeStructVariant = SerializeStructVariant;
+
+    fn serialize_bool(self, v: bool) -> result::Result<Value, TypeError> {
+        Ok(Value::BasicValue(BasicValue::Bool(v)))
+    }
+
+    // D-Bus has no signed byte, so `i8` widens to an int16.
+    fn serialize_i8(self, v: i8) -> result::Result<Value, TypeError> {
+        Ok(Value::BasicValue(BasicValue::Int16(v as i16)))
+    }
+
+    fn serialize_i16(self, v: i16) -> result::Result<Value, TypeError> {
+        Ok(Value::BasicValue(BasicValue::Int16(v)))
+    }
+
+    fn serialize_i32(self, v: i32) -> result::Result<Value, TypeError> {
+        Ok(Value::BasicValue(BasicValue::Int32(v)))
+    }
+
+    fn serialize_i64(self, v: i64) -> result::Result<Value, TypeError> {
+        Ok(Value::BasicValue(BasicValue::Int64(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> result::Result<Value, TypeError> {
+        Ok(Value::BasicValue(BasicValue::Byte(v)))
+    }
+
+    fn serialize_u16(self, v: u16) -> result::Result<Value, TypeError> {
+        Ok(Value::BasicValue(BasicValue::UInt16(v)))
+    }
+
+    fn serialize_u32(self, v: u32) -> result::Result<Value, TypeError> {
+        Ok(Value::BasicValue(BasicValue::UInt32(v)))
+    }
+
+    fn serialize_u64(self, v: u64) -> result::Result<Value, TypeError> {
+        Ok(Value::BasicValue(BasicValue::UInt64(v)))
+    }
+
+    fn serialize_f32(self, v: f32) -> result::Result<Value, TypeError> {
+        Ok(Value::BasicValue(BasicValue::Double(v as f64)))
+    }
+
+    fn serialize_f64(self, v: f64) -> result::Result<Value, TypeError> {
+        Ok(Value::BasicValue(BasicValue::Double(v)))
+    }
+
+    fn serialize_char(self, v: char) -> result::Result<Value, TypeError> {
+        Ok(Value::BasicValue(BasicValue::String(v.to_string().into())))
+    }
+
+    fn serialize_str(self, v: &str) -> result::Result<Value, TypeError> {
+        Ok(Value::BasicValue(BasicValue::String(v.to_owned().into())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> result::Result<Value, TypeError> {
+        Ok(Value::ContainerValue(ContainerValue::Array(v.iter()
+            .map(|&b| Value::BasicValue(BasicValue::Byte(b)))
+            .collect())))
+    }
+
+    fn serialize_none(self) -> result::Result<Value, TypeError> {
+        Err(unsupported("a missing optional value"))
+    }
+
+    // `Some` is transparent, so optional struct fields work with
+    // `skip_serializing_if = "Option::is_none"`.
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> result::Result<Value, TypeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> result::Result<Value, TypeError> {
+        Err(unsupported("the unit type"))
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> result::Result<Value, TypeError> {
+        Err(unsupported(&format!("the unit struct `{}`", name)))
+    }
+
+    fn serialize_unit_variant(self,
+                              _name: &'static str,
+                              _variant_index: u32,
+                              variant: &'static str)
+                              -> result::Result<Value, TypeError> {
+        tagged_variant(variant, Value::BasicValue(BasicValue::String("".into())))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self,
+                                                       name: &'static str,
+                                                       value: &T)
+                                                       -> result::Result<Value, TypeError> {
+        let value = value.serialize(self)?;
+        if name == VARIANT_NEWTYPE {
+            // Serde gives no types, so an empty container can't go in one.
+            value.into_variant()
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self,
+                                                        _name: &'static str,
+                                                        _variant_index: u32,
+                                                        variant: &'static str,
+                                                        value: &T)
+                                                        -> result::Result<Value, TypeError> {
+        let value = value.serialize(self).map_err(|err| err.at(PathSegment::Variant))?;
+        tagged_variant(variant, value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> result::Result<SerializeArray, TypeError> {
+        Ok(SerializeArray { elems: Vec::with_capacity(len.unwrap_or(0)) })
+    }
+
+    fn serialize_tuple(self, len: usize) -> result::Result<SerializeStruct, TypeError> {
+        Ok(SerializeStruct { fields: Vec::with_capacity(len) })
+    }
+
+    fn serialize_tuple_struct(self,
+                              _name: &'static str,
+                              len: usize)
+                              -> result::Result<SerializeStruct, TypeError> {
+        self.serialize_tuple(len)
+    }
+
+    fn serialize_tuple_variant(self,
+                               _name: &'static str,
+                               _variant_index: u32,
+                               variant: &'static str,
+                               len: usize)
+                               -> result::Result<SerializeStructVariant, TypeError> {
+        Ok(SerializeStructVariant {
+            variant: variant,
+            fields: SerializeStruct { fields: Vec::with_capacity(len) },
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> result::Result<SerializeDict, TypeError> {
+        Ok(SerializeDict {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self,
+                        _name: &'static str,
+                        len: usize)
+                        -> result::Result<SerializeStruct, TypeError> {
+        self.serialize_tuple(len)
+    }
+
+    fn serialize_struct_variant(self,
+                                name: &'static str,
+                                variant_index: u32,
+                                variant: &'static str,
+                                len: usize)
+                                -> result::Result<SerializeStructVariant, TypeError> {
+        self.serialize_tuple_variant(name, variant_index, variant, len)
+    }
+}
+
+fn tagged_variant(variant: &'static str, value: Value) -> result::Result<Value, TypeError> {
+    let value = value.into_variant().map_err(|err| err.at(PathSegment::Variant))?;
+    Ok(Value::ContainerValue(ContainerValue::Struct(vec![
+        Value::BasicValue(BasicValue::String(variant.into())),
+        value,
+    ])))
+}
+
+pub struct SerializeArray {
+    elems: Vec<Value>,
+}
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = Value;
+    type Error = TypeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self,
+                                                value: &T)
+                                                -> result::Result<(), TypeError> {
+        let i = self.elems.len();
+        self.elems.push(to_value(value).map_err(|err| err.at(PathSegment::Element(i)))?);
+        Ok(())
+    }
+
+    fn end(self) -> result::Result<Value, TypeError> {
+        Ok(Value::ContainerValue(ContainerValue::Array(self.elems)))
+    }
+}
+
+pub struct SerializeStruct {
+    fields: Vec<Value>,
+}
+
+impl SerializeStruct {
+    fn field<T: Serialize + ?Sized>(&mut self, value: &T) -> result::Result<(), TypeError> {
+        let i = self.fields.len();
+        self.fields.push(to_value(value).map_err(|err| err.at(PathSegment::Field(i)))?);
+        Ok(())
+    }
+
+    fn finish(self) -> result::Result<Value, TypeError> {
+        if self.fields.is_empty() {
+            return Err(TypeError::new(TypeErrorKind::EmptyStruct));
+        }
+        Ok(Value::ContainerValue(ContainerValue::Struct(self.fields)))
+    }
+}
+
+impl ser::SerializeTuple for SerializeStruct {
+    type Ok = Value;
+    type Error = TypeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self,
+                                                value: &T)
+                                                -> result::Result<(), TypeError> {
+        self.field(value)
+    }
+
+    fn end(self) -> result::Result<Value, TypeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeStruct {
+    type Ok = Value;
+    type Error = TypeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self,
+                                              value: &T)
+                                              -> result::Result<(), TypeError> {
+        self.field(value)
+    }
+
+    fn end(self) -> result::Result<Value, TypeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for SerializeStruct {
+    type Ok = Value;
+    type Error = TypeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self,
+                                              _key: &'static str,
+                                              value: &T)
+                                              -> result::Result<(), TypeError> {
+        self.field(value)
+    }
+
+    fn end(self) -> result::Result<Value, TypeError> {
+        self.finish()
+    }
+}
+
+pub struct SerializeStructVariant {
+    variant: &'static str,
+    fields: SerializeStruct,
+}
+
+impl ser::SerializeTupleVariant for SerializeStructVariant {
+    type Ok = Value;
+    type Error = TypeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self,
+                                              value: &T)
+                                              -> result::Result<(), TypeError> {
+        self.fields.field(value).map_err(|err| err.at(PathSegment::Variant))
+    }
+
+    fn end(self) -> result::Result<Value, TypeError> {
+        let value = self.fields.finish().map_err(|err| err.at(PathSegment::Variant))?;
+        tagged_variant(self.variant, value)
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeStructVariant {
+    type Ok = Value;
+    type Error = TypeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self,
+                                              _key: &'static str,
+                                              value: &T)
+                                              -> result::Result<(), TypeError> {
+        self.fields.field(value).map_err(|err| err.at(PathSegment::Variant))
+    }
+
+    fn end(self) -> result::Result<Value, TypeError> {
+        let value = self.fields.finish().map_err(|err| err.at(PathSegment::Variant))?;
+        tagged_variant(self.variant, value)
+    }
+}
+
+pub struct SerializeDict {
+    entries: Vec<(BasicValue, Value)>,
+    key: Option<BasicValue>,
+}
+
+impl ser::SerializeMap for SerializeDict {
+    type Ok = Value;
+    type Error = TypeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> result::Result<(), TypeError> {
+        let i = self.entries.len();
+        match to_value(key).map_err(|err| err.at(PathSegment::Key(i)))? {
+            Value::BasicValue(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            key => {
+                Err(TypeError::new(TypeErrorKind::Invalid(format!("Dict keys must be basic \
+                                                                   values, not {}",
+                                                                  key.kind_name())))
+                    .at(PathSegment::Key(i)))
+            }
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self,
+                                              value: &T)
+                                              -> result::Result<(), TypeError> {
+        let i = self.entries.len();
+        let value = to_value(value).map_err(|err| err.at(PathSegment::Value(i)))?;
+        let key = self.key.take().expect("serialize_value called before serialize_key");
+        self.entries.push((key, value));
+        Ok(())
+    }
+
+    fn end(self) -> result::Result<Value, TypeError> {
+        Ok(Value::ContainerValue(ContainerValue::Dict(self.entries)))
+    }
+}
@@ -0,0 +1,115 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::result;
+
+use bus::error::{DBusError, StandardError};
+use bus::message::{Message, MessageType, NO_REPLY_EXPECTED};
+use bus::names::ObjectPath;
+use bus::wire::Value;
+
+/// What a method handler returns: the body of the reply, or the error to
+/// reply with.
+pub type MethodResult = result::Result<Vec<Value>, DBusError>;
+
+// Shared so that a connection can run one without holding on to the server,
+// leaving the handler free to use the connection.
+pub type Handler = Rc<RefCell<Box<FnMut(&Message) -> MethodResult>>>;
+
+/// The objects a connection exports, each a handler for the method calls
+/// made on its path.
+///
+/// Handlers see the whole call, and dispatch on its interface and member
+/// themselves.
+#[derive(Default)]
+pub struct ObjectServer {
+    objects: BTreeMap<ObjectPath, Handler>,
+}
+
+impl ObjectServer {
+    pub fn new() -> Self {
+        ObjectServer::default()
+    }
+
+    /// Exports an object at `path`, replacing any already there.
+    pub fn export<F>(&mut self, path: ObjectPath, handler: F)
+        where F: FnMut(&Message) -> MethodResult + 'static
+    {
+        self.objects.insert(path, Rc::new(RefCell::new(Box::new(handler))));
+    }
+
+    /// Stops exporting the object at `path`, returning whether there was one.
+    pub fn unexport(&mut self, path: &str) -> bool {
+        match ObjectPath::new(path) {
+            Ok(path) => self.objects.remove(&path).is_some(),
+            Err(_) => false,
+        }
+    }
+
+    pub fn is_exported(&self, path: &str) -> bool {
+        match ObjectPath::new(path) {
+            Ok(path) => self.objects.contains_key(&path),
+            Err(_) => false,
+        }
+    }
+
+    /// The paths of the exported objects, in order.
+    pub fn paths(&self) -> Vec<ObjectPath> {
+        self.objects.keys().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Runs the handler for a method call, returning the reply to send.
+    /// Calls on paths with no object get an `UnknownObject` error.
+    ///
+    /// There is no reply if `call` isn't a method call, or its caller set
+    /// `NO_REPLY_EXPECTED`; the handler still runs in the latter case.
+    pub fn handle(&mut self, call: &Message) -> Option<Message> {
+        if call.message_type != MessageType::MethodCall {
+            return None;
+        }
+        let handler = handler_for(self, call);
+        run_handler(handler, call)
+    }
+}
+
+// The handler of the object a method call is made on, if there is one.
+pub fn handler_for(server: &ObjectServer, call: &Message) -> Option<Handler> {
+    let path = call.fields.path.as_ref().map_or("", |path| &path[..]);
+    ObjectPath::new(path).ok().and_then(|path| server.objects.get(&path).cloned())
+}
+
+// Runs `handler` for a method call, returning the reply to send; with no
+// handler, the reply is an `UnknownObject` error.
+pub fn run_handler(handler: Option<Handler>, call: &Message) -> Option<Message> {
+    let result = match handler {
+        Some(handler) => (*handler.borrow_mut())(call),
+        None => {
+            let path = call.fields.path.as_ref().map_or("", |path| &path[..]);
+            Err(DBusError::standard(StandardError::UnknownObject,
+                                    format!("No object is exported at {:?}.", path)))
+        }
+    };
+    if call.has_flag(NO_REPLY_EXPECTED) {
+        return None;
+    }
+    Some(match result {
+        Ok(body) => {
+            let mut reply = Message::method_return(call);
+            match reply.set_body(&body) {
+                Ok(()) => reply,
+                Err(err) => {
+                    DBusError::standard(StandardError::Failed, err.to_string()).to_reply(call)
+                }
+            }
+        }
+        Err(err) => err.to_reply(call),
+    })
+}
@@ -0,0 +1,823 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The GVariant text format, as printed by `g_variant_print` and parsed by
+//! `g_variant_parse`, such as `{'Name': <'foo'>, 'Count': <uint32 3>}`.
+//!
+//! Without a type to parse against, integers are `i`, other numbers `d`,
+//! quoted strings `s`, and containers take the types of their first
+//! elements. A keyword such as `uint32`, or `@` followed by a type string,
+//! forces a type. The printer adds these annotations wherever the parser
+//! couldn't otherwise tell the type, so what it prints parses back to the
+//! same value.
+
+use std::char;
+use std::convert::TryFrom;
+use std::error;
+use std::fmt::{self, Display, Formatter, Write};
+use std::io::{Error, ErrorKind};
+use std::result;
+use std::str::FromStr;
+
+use bus::names::is_valid_object_path;
+use bus::types::{self, BasicType, ContainerType, Type, MAX_TOTAL_DEPTH};
+use bus::wire::{BasicValue, ContainerValue, TypeError, Value};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TextError {
+    /// The byte offset in the text at which parsing failed.
+    pub offset: usize,
+    pub reason: String,
+}
+
+impl Display for TextError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Invalid GVariant text at offset {}: {}.", self.offset, self.reason)
+    }
+}
+
+impl error::Error for TextError {
+    fn description(&self) -> &str {
+        "invalid GVariant text"
+    }
+}
+
+impl From<TextError> for Error {
+    fn from(err: TextError) -> Self {
+        Error::new(ErrorKind::InvalidData, err)
+    }
+}
+
+/// Prints `value` in the GVariant text format, inferring its type.
+pub fn print_value(value: &Value) -> result::Result<String, TypeError> {
+    print_typed_value(value, &value.signature()?)
+}
+
+/// Like `print_value`, for a value of a known type. Empty containers need
+/// this, since their types can't be inferred.
+pub fn print_typed_value(value: &Value, ty: &Type) -> result::Result<String, TypeError> {
+    value.check_type(ty)?;
+    let mut printer = Printer { output: String::new() };
+    printer.value(value, ty, false);
+    Ok(printer.output)
+}
+
+/// Parses a value from the GVariant text format, inferring its type.
+pub fn parse_value(text: &str) -> result::Result<Value, TextError> {
+    parse(text, None)
+}
+
+/// Parses a value of type `ty` from the GVariant text format. Literals take
+/// their types from `ty`, so `3` can be parsed as a `u`, say, without
+/// annotating it.
+pub fn parse_typed_value(text: &str, ty: &Type) -> result::Result<Value, TextError> {
+    parse(text, Some(ty))
+}
+
+fn parse(text: &str, ty: Option<&Type>) -> result::Result<Value, TextError> {
+    let mut parser = Parser {
+        text: text,
+        pos: 0,
+    };
+    let (value, _) = parser.value(ty, 0)?;
+    parser.skip_whitespace();
+    if parser.pos < text.len() {
+        return Err(parser.error(parser.pos, "unexpected text after the value"));
+    }
+    Ok(value)
+}
+
+struct Printer {
+    output: String,
+}
+
+// `typed` says whether the surrounding text already fixes the type of the
+// value, such as for every element of an array but the first, in which case
+// it needs no annotation.
+impl Printer {
+    fn value(&mut self, value: &Value, ty: &Type, typed: bool) {
+        let container_ty = match (value, ty) {
+            (&Value::BasicValue(ref basic), _) => return self.basic_value(basic, typed),
+            (&Value::ContainerValue(_), &Type::ContainerType(ref container_ty)) => container_ty,
+            _ => unreachable!(),
+        };
+        match (value, &**container_ty) {
+            (&Value::ContainerValue(ContainerValue::Array(ref elems)),
+             &ContainerType::Array(ref elem_ty)) => {
+                if elems.is_empty() && !typed {
+                    self.annotate(ty);
+                }
+                self.output.push('[');
+                for (i, elem) in elems.iter().enumerate() {
+                    if i > 0 {
+                        self.output.push_str(", ");
+                    }
+                    self.value(elem, elem_ty, typed || i > 0);
+                }
+                self.output.push(']');
+            }
+            (&Value::ContainerValue(ContainerValue::Dict(ref entries)),
+             &ContainerType::Dict(_, ref value_ty)) => {
+                if entries.is_empty() && !typed {
+                    self.annotate(ty);
+                }
+                self.output.push('{');
+                for (i, &(ref key, ref value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.output.push_str(", ");
+                    }
+                    self.basic_value(key, typed || i > 0);
+                    self.output.push_str(": ");
+                    self.value(value, value_ty, typed || i > 0);
+                }
+                self.output.push('}');
+            }
+            (&Value::ContainerValue(ContainerValue::Struct(ref fields)),
+             &ContainerType::Struct(ref field_tys)) => {
+                self.output.push('(');
+                for (i, (field, field_ty)) in fields.iter().zip(field_tys).enumerate() {
+                    if i > 0 {
+                        self.output.push_str(", ");
+                    }
+                    self.value(field, field_ty, typed);
+                }
+                // As in Python, a tuple of one needs a trailing comma.
+                if fields.len() == 1 {
+                    self.output.push(',');
+                }
+                self.output.push(')');
+            }
+            (&Value::ContainerValue(ContainerValue::Variant(ref inner_ty, ref inner)),
+             &ContainerType::Variant) => {
+                self.output.push('<');
+                self.value(inner, inner_ty, false);
+                self.output.push('>');
+            }
+            #[cfg(feature = "maybe")]
+            (&Value::ContainerValue(ContainerValue::Maybe(ref inner)),
+             &ContainerType::Maybe(ref inner_ty)) => {
+                match *inner {
+                    Some(ref inner) => {
+                        self.output.push_str("just ");
+                        self.value(inner, inner_ty, typed);
+                    }
+                    None => {
+                        if !typed {
+                            self.annotate(ty);
+                        }
+                        self.output.push_str("nothing");
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn annotate(&mut self, ty: &Type) {
+        write!(self.output, "@{} ", ty).unwrap();
+    }
+
+    fn basic_value(&mut self, value: &BasicValue, typed: bool) {
+        // Booleans, int32s, doubles and strings are what unannotated
+        // literals default to.
+        let keyword = match *value {
+            BasicValue::Byte(_) => "byte",
+            BasicValue::Int16(_) => "int16",
+            BasicValue::UInt16(_) => "uint16",
+            BasicValue::UInt32(_) => "uint32",
+            BasicValue::Int64(_) => "int64",
+            BasicValue::UInt64(_) => "uint64",
+            BasicValue::ObjectPath(_) => "objectpath",
+            BasicValue::Signature(_) => "signature",
+            BasicValue::UnixFd(_) => "handle",
+            BasicValue::Bool(_) |
+            BasicValue::Int32(_) |
+            BasicValue::Double(_) |
+            BasicValue::String(_) => "",
+        };
+        if !typed && !keyword.is_empty() {
+            self.output.push_str(keyword);
+            self.output.push(' ');
+        }
+
+        let output = &mut self.output;
+        match *value {
+            BasicValue::Byte(n) => write!(output, "0x{:02x}", n),
+            BasicValue::Bool(b) => write!(output, "{}", b),
+            BasicValue::Int16(n) => write!(output, "{}", n),
+            BasicValue::UInt16(n) => write!(output, "{}", n),
+            BasicValue::Int32(n) => write!(output, "{}", n),
+            BasicValue::UInt32(n) => write!(output, "{}", n),
+            BasicValue::Int64(n) => write!(output, "{}", n),
+            BasicValue::UInt64(n) => write!(output, "{}", n),
+            BasicValue::Double(n) => write_double(n, output),
+            BasicValue::String(ref s) => write_string(s, output),
+            BasicValue::ObjectPath(ref path) => {
+                write_string(&String::from_utf8_lossy(path), output)
+            }
+            BasicValue::Signature(ref signature) => write_string(&signature.to_string(), output),
+            BasicValue::UnixFd(n) => write!(output, "{}", n),
+        }
+        .unwrap();
+    }
+}
+
+fn write_double(n: f64, output: &mut String) -> fmt::Result {
+    if n.is_nan() {
+        output.write_str("nan")
+    } else if n.is_infinite() {
+        output.write_str(if n > 0.0 { "inf" } else { "-inf" })
+    } else {
+        // `Debug` always writes a point or an exponent, so the number
+        // doesn't read back as an integer, and enough digits to read back
+        // exactly.
+        write!(output, "{:?}", n)
+    }
+}
+
+fn write_string(s: &str, output: &mut String) -> fmt::Result {
+    let quote = if s.contains('\'') && !s.contains('"') {
+        '"'
+    } else {
+        '\''
+    };
+    output.push(quote);
+    for c in s.chars() {
+        match c {
+            '\\' => output.push_str("\\\\"),
+            '\x07' => output.push_str("\\a"),
+            '\x08' => output.push_str("\\b"),
+            '\x0c' => output.push_str("\\f"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            '\x0b' => output.push_str("\\v"),
+            c if c == quote => {
+                output.push('\\');
+                output.push(c);
+            }
+            c if c.is_control() && (c as u32) <= 0xffff => write!(output, "\\u{:04x}", c as u32)?,
+            c if c.is_control() => write!(output, "\\U{:08x}", c as u32)?,
+            c => output.push(c),
+        }
+    }
+    output.push(quote);
+    Ok(())
+}
+
+// The type keywords that may precede a literal.
+fn keyword_type(word: &str) -> Option<BasicType> {
+    Some(match word {
+        "boolean" => BasicType::Bool,
+        "byte" => BasicType::Byte,
+        "int16" => BasicType::Int16,
+        "uint16" => BasicType::UInt16,
+        "int32" => BasicType::Int32,
+        "uint32" => BasicType::UInt32,
+        "int64" => BasicType::Int64,
+        "uint64" => BasicType::UInt64,
+        "double" => BasicType::Double,
+        "string" => BasicType::String,
+        "objectpath" => BasicType::ObjectPath,
+        "signature" => BasicType::Signature,
+        "handle" => BasicType::UnixFd,
+        _ => return None,
+    })
+}
+
+const INTEGER_TYPES: &'static [BasicType] = &[BasicType::Byte,
+                                              BasicType::Int16,
+                                              BasicType::UInt16,
+                                              BasicType::Int32,
+                                              BasicType::UInt32,
+                                              BasicType::Int64,
+                                              BasicType::UInt64,
+                                              BasicType::UnixFd,
+                                              BasicType::Double];
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+type ParseResult<T> = result::Result<T, TextError>;
+
+impl<'a> Parser<'a> {
+    fn error<S: Into<String>>(&self, offset: usize, reason: S) -> TextError {
+        TextError {
+            offset: offset,
+            reason: reason.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if let Some(c) = c {
+            self.pos += c.len_utf8();
+        }
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> ParseResult<()> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.bump();
+            Ok(())
+        } else {
+            Err(self.error(self.pos, format!("expected `{}`", expected)))
+        }
+    }
+
+    // Consumes `c` if it comes next.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, f: F) -> &'a str {
+        let start = self.pos;
+        while self.peek().map_or(false, &f) {
+            self.bump();
+        }
+        &self.text[start..self.pos]
+    }
+
+    // Parses a value, returning it along with its type, which the caller
+    // may need to parse the rest of a container.
+    fn value(&mut self, expected: Option<&Type>, depth: usize) -> ParseResult<(Value, Type)> {
+        self.skip_whitespace();
+        let start = self.pos;
+        if depth > MAX_TOTAL_DEPTH {
+            return Err(self.error(start, "values are nested too deeply"));
+        }
+        match self.peek() {
+            Some('@') => {
+                self.bump();
+                let ty = self.type_annotation()?;
+                self.check_expected(expected, &ty, start)?;
+                // Annotations can be stacked, so they count towards the
+                // depth like containers do.
+                self.value(Some(&ty), depth + 1)
+            }
+            Some('[') => self.array(expected, depth),
+            Some('{') => self.dict(expected, depth),
+            Some('(') => self.tuple(expected, depth),
+            Some('<') => self.variant(expected, depth),
+            Some('\'') | Some('"') => self.string(expected),
+            Some(c) if c.is_digit(10) || c == '-' || c == '+' || c == '.' => self.number(expected),
+            Some(c) if c.is_alphabetic() => {
+                let word = self.take_while(|c| c.is_alphanumeric() || c == '_');
+                match word {
+                    "true" | "false" => {
+                        self.literal_type(expected, BasicType::Bool, &[], "a boolean", start)?;
+                        Ok(basic(BasicValue::Bool(word == "true")))
+                    }
+                    "inf" | "nan" => self.special_double(word, expected, start),
+                    #[cfg(feature = "maybe")]
+                    "just" => self.just(expected, depth),
+                    #[cfg(feature = "maybe")]
+                    "nothing" => self.nothing(expected, start),
+                    _ => {
+                        match keyword_type(word) {
+                            Some(basic_ty) => {
+                                let ty = Type::BasicType(basic_ty);
+                                self.check_expected(expected, &ty, start)?;
+                                self.value(Some(&ty), depth + 1)
+                            }
+                            None => Err(self.error(start, format!("unknown word `{}`", word))),
+                        }
+                    }
+                }
+            }
+            Some(_) => Err(self.error(start, "expected a value")),
+            None => Err(self.error(start, "expected a value, found the end of the text")),
+        }
+    }
+
+    fn mismatch(&self, expected: &Type, what: &str, offset: usize) -> TextError {
+        self.error(offset,
+                   format!("expected a value of type `{}`, found {}", expected, what))
+    }
+
+    fn check_expected(&self, expected: Option<&Type>, ty: &Type, offset: usize) -> ParseResult<()> {
+        match expected {
+            Some(expected) if expected != ty => {
+                Err(self.error(offset,
+                               format!("expected a value of type `{}`, found one of type `{}`",
+                                       expected,
+                                       ty)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // The type of a literal of a kind that is `default` unless the context
+    // expects one of `others`.
+    fn literal_type(&self,
+                    expected: Option<&Type>,
+                    default: BasicType,
+                    others: &[BasicType],
+                    what: &str,
+                    offset: usize)
+                    -> ParseResult<BasicType> {
+        match expected {
+            None => Ok(default),
+            Some(&Type::BasicType(ref ty)) if *ty == default || others.contains(ty) => {
+                Ok(ty.clone())
+            }
+            Some(ty) => Err(self.mismatch(ty, what, offset)),
+        }
+    }
+
+    // The container type the context expects, if any.
+    fn expected_container<'t>(&self,
+                              expected: Option<&'t Type>,
+                              what: &str,
+                              offset: usize)
+                              -> ParseResult<Option<&'t ContainerType>> {
+        match expected {
+            None => Ok(None),
+            Some(&Type::ContainerType(ref container_ty)) => Ok(Some(container_ty)),
+            Some(ty) => Err(self.mismatch(ty, what, offset)),
+        }
+    }
+
+    fn type_annotation(&mut self) -> ParseResult<Type> {
+        let start = self.pos;
+        // Read up to the end of one complete type.
+        let mut depth = 0usize;
+        while let Some(c) = self.peek() {
+            match c {
+                'a' | 'm' => {
+                    self.bump();
+                    continue;
+                }
+                '(' | '{' => depth += 1,
+                ')' | '}' => depth = depth.saturating_sub(1),
+                c if c.is_ascii_alphabetic() => {}
+                _ => break,
+            }
+            self.bump();
+            if depth == 0 {
+                break;
+            }
+        }
+        let code = &self.text[start..self.pos];
+        types::validate_gvariant_type(code.as_bytes())
+            .map_err(|_| self.error(start, format!("`{}` is not a valid type", code)))
+    }
+
+    fn array(&mut self, expected: Option<&Type>, depth: usize) -> ParseResult<(Value, Type)> {
+        let start = self.pos;
+        let mut elem_ty = match self.expected_container(expected, "an array", start)? {
+            None => None,
+            Some(&ContainerType::Array(ref elem_ty)) => Some(elem_ty.clone()),
+            Some(_) => return Err(self.mismatch(expected.unwrap(), "an array", start)),
+        };
+        self.bump();
+        let mut elems = Vec::new();
+        while !self.eat(']') {
+            if !elems.is_empty() {
+                self.expect(',')?;
+            }
+            let (elem, ty) = self.value(elem_ty.as_ref(), depth + 1)?;
+            elems.push(elem);
+            elem_ty = Some(ty);
+        }
+        match elem_ty {
+            Some(elem_ty) => {
+                Ok((Value::ContainerValue(ContainerValue::Array(elems)),
+                    Type::ContainerType(Box::new(ContainerType::Array(elem_ty)))))
+            }
+            None => {
+                Err(self.error(start,
+                               "can't infer the type of an empty array; annotate it, as in \
+                                `@as []`"))
+            }
+        }
+    }
+
+    fn dict(&mut self, expected: Option<&Type>, depth: usize) -> ParseResult<(Value, Type)> {
+        let start = self.pos;
+        let mut entry_tys = match self.expected_container(expected, "a dict", start)? {
+            None => None,
+            Some(&ContainerType::Dict(ref key_ty, ref value_ty)) => {
+                Some((Type::BasicType(key_ty.clone()), value_ty.clone()))
+            }
+            Some(_) => return Err(self.mismatch(expected.unwrap(), "a dict", start)),
+        };
+        self.bump();
+        let mut entries = Vec::new();
+        while !self.eat('}') {
+            if !entries.is_empty() {
+                self.expect(',')?;
+            }
+            self.skip_whitespace();
+            let key_start = self.pos;
+            let (key, key_ty) = self.value(entry_tys.as_ref().map(|tys| &tys.0), depth + 1)?;
+            let key = match key {
+                Value::BasicValue(key) => key,
+                Value::ContainerValue(_) => {
+                    return Err(self.error(key_start, "dict keys must have basic types"))
+                }
+            };
+            self.expect(':')?;
+            let (value, value_ty) = self.value(entry_tys.as_ref().map(|tys| &tys.1), depth + 1)?;
+            entries.push((key, value));
+            entry_tys = Some((key_ty, value_ty));
+        }
+        match entry_tys {
+            Some((Type::BasicType(key_ty), value_ty)) => {
+                Ok((Value::ContainerValue(ContainerValue::Dict(entries)),
+                    Type::ContainerType(Box::new(ContainerType::Dict(key_ty, value_ty)))))
+            }
+            _ => {
+                Err(self.error(start,
+                               "can't infer the type of an empty dict; annotate it, as in \
+                                `@a{sv} {}`"))
+            }
+        }
+    }
+
+    fn tuple(&mut self, expected: Option<&Type>, depth: usize) -> ParseResult<(Value, Type)> {
+        let start = self.pos;
+        let field_tys = match self.expected_container(expected, "a tuple", start)? {
+            None => None,
+            Some(&ContainerType::Struct(ref field_tys)) => Some(field_tys),
+            Some(_) => return Err(self.mismatch(expected.unwrap(), "a tuple", start)),
+        };
+        self.bump();
+        let mut fields = Vec::new();
+        let mut tys = Vec::new();
+        loop {
+            if self.eat(')') {
+                break;
+            }
+            let field_ty = match field_tys {
+                Some(field_tys) => {
+                    match field_tys.get(fields.len()) {
+                        Some(field_ty) => Some(field_ty),
+                        None => {
+                            return Err(self.error(self.pos,
+                                                  format!("expected a tuple of {} values",
+                                                          field_tys.len())))
+                        }
+                    }
+                }
+                None => None,
+            };
+            let (field, ty) = self.value(field_ty, depth + 1)?;
+            fields.push(field);
+            tys.push(ty);
+            if !self.eat(',') {
+                self.expect(')')?;
+                break;
+            }
+        }
+        if fields.is_empty() {
+            return Err(self.error(start, "empty tuples have no D-Bus type"));
+        }
+        if let Some(field_tys) = field_tys {
+            if fields.len() != field_tys.len() {
+                return Err(self.error(start,
+                                      format!("expected a tuple of {} values, found {}",
+                                              field_tys.len(),
+                                              fields.len())));
+            }
+        }
+        Ok((Value::ContainerValue(ContainerValue::Struct(fields)),
+            Type::ContainerType(Box::new(ContainerType::Struct(tys)))))
+    }
+
+    fn variant(&mut self, expected: Option<&Type>, depth: usize) -> ParseResult<(Value, Type)> {
+        let start = self.pos;
+        let ty = Type::ContainerType(Box::new(ContainerType::Variant));
+        self.check_expected(expected, &ty, start)?;
+        self.bump();
+        let (inner, inner_ty) = self.value(None, depth + 1)?;
+        self.expect('>')?;
+        Ok((Value::ContainerValue(ContainerValue::Variant(inner_ty, Box::new(inner))), ty))
+    }
+
+    #[cfg(feature = "maybe")]
+    fn just(&mut self, expected: Option<&Type>, depth: usize) -> ParseResult<(Value, Type)> {
+        let start = self.pos;
+        let inner_ty = match self.expected_container(expected, "a maybe", start)? {
+            None => None,
+            Some(&ContainerType::Maybe(ref inner_ty)) => Some(inner_ty),
+            Some(_) => return Err(self.mismatch(expected.unwrap(), "a maybe", start)),
+        };
+        let (inner, inner_ty) = self.value(inner_ty, depth + 1)?;
+        Ok((Value::ContainerValue(ContainerValue::Maybe(Some(Box::new(inner)))),
+            Type::ContainerType(Box::new(ContainerType::Maybe(inner_ty)))))
+    }
+
+    #[cfg(feature = "maybe")]
+    fn nothing(&mut self, expected: Option<&Type>, start: usize) -> ParseResult<(Value, Type)> {
+        let ty = match expected {
+            Some(ty) => ty,
+            None => {
+                return Err(self.error(start,
+                                      "can't infer the type of `nothing`; annotate it, as in \
+                                       `@ms nothing`"))
+            }
+        };
+        match self.expected_container(expected, "a maybe", start)? {
+            Some(&ContainerType::Maybe(_)) => {
+                Ok((Value::ContainerValue(ContainerValue::Maybe(None)), ty.clone()))
+            }
+            _ => Err(self.mismatch(ty, "a maybe", start)),
+        }
+    }
+
+    fn string(&mut self, expected: Option<&Type>) -> ParseResult<(Value, Type)> {
+        let start = self.pos;
+        let ty = self.literal_type(expected,
+                          BasicType::String,
+                          &[BasicType::ObjectPath, BasicType::Signature],
+                          "a string",
+                          start)?;
+        let s = self.quoted()?;
+        let value = match ty {
+            BasicType::ObjectPath => {
+                if !is_valid_object_path(&s) {
+                    return Err(self.error(start, format!("{:?} is not a valid object path", s)));
+                }
+                BasicValue::ObjectPath(s.into_bytes().into())
+            }
+            BasicType::Signature => {
+                match types::validate_signature(s.as_bytes()) {
+                    Ok(signature) => BasicValue::Signature(signature),
+                    Err(_) => {
+                        return Err(self.error(start, format!("{:?} is not a valid signature", s)))
+                    }
+                }
+            }
+            _ => BasicValue::String(s.into()),
+        };
+        Ok(basic(value))
+    }
+
+    fn quoted(&mut self) -> ParseResult<String> {
+        let start = self.pos;
+        let quote = self.bump().unwrap();
+        let mut s = String::new();
+        loop {
+            let escape_start = self.pos;
+            match self.bump() {
+                None => return Err(self.error(start, "unterminated string")),
+                Some(c) if c == quote => return Ok(s),
+                Some('\\') => {
+                    let c = match self.bump() {
+                        Some('a') => '\x07',
+                        Some('b') => '\x08',
+                        Some('f') => '\x0c',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('v') => '\x0b',
+                        Some('u') => self.unicode_escape(4, escape_start)?,
+                        Some('U') => self.unicode_escape(8, escape_start)?,
+                        Some(c) if c == '\\' || c == '\'' || c == '"' => c,
+                        _ => return Err(self.error(escape_start, "invalid escape sequence")),
+                    };
+                    s.push(c);
+                }
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn unicode_escape(&mut self, len: usize, escape_start: usize) -> ParseResult<char> {
+        let digits = self.text[self.pos..].get(..len).unwrap_or("");
+        let c = u32::from_str_radix(digits, 16).ok().and_then(char::from_u32);
+        match c {
+            Some(c) if digits.chars().all(|c| c.is_digit(16)) => {
+                self.pos += len;
+                Ok(c)
+            }
+            _ => Err(self.error(escape_start, "invalid escape sequence")),
+        }
+    }
+
+    fn special_double(&self,
+                      word: &str,
+                      expected: Option<&Type>,
+                      start: usize)
+                      -> ParseResult<(Value, Type)> {
+        self.literal_type(expected, BasicType::Double, &[], "a floating-point number", start)?;
+        let n = match word {
+            "inf" => ::std::f64::INFINITY,
+            "-inf" => ::std::f64::NEG_INFINITY,
+            "+inf" => ::std::f64::INFINITY,
+            _ => ::std::f64::NAN,
+        };
+        Ok(basic(BasicValue::Double(n)))
+    }
+
+    fn number(&mut self, expected: Option<&Type>) -> ParseResult<(Value, Type)> {
+        let start = self.pos;
+        let negative = match self.peek() {
+            Some('-') => {
+                self.bump();
+                true
+            }
+            Some('+') => {
+                self.bump();
+                false
+            }
+            _ => false,
+        };
+        if self.peek().map_or(false, char::is_alphabetic) {
+            let word = self.take_while(char::is_alphanumeric);
+            if word != "inf" {
+                return Err(self.error(start, "expected a number"));
+            }
+            let text = self.text;
+            return self.special_double(&text[start..self.pos], expected, start);
+        }
+
+        let digits_start = self.pos;
+        let hex = self.text[digits_start..].starts_with("0x") ||
+                  self.text[digits_start..].starts_with("0X");
+        let mut prev = ' ';
+        while let Some(c) = self.peek() {
+            let exponent_sign = !hex && (prev == 'e' || prev == 'E') && (c == '+' || c == '-');
+            if !(c.is_alphanumeric() || c == '.' || exponent_sign) {
+                break;
+            }
+            prev = c;
+            self.bump();
+        }
+        let literal = &self.text[start..self.pos];
+        let digits = &self.text[digits_start..self.pos];
+        let invalid = || self.error(start, format!("`{}` is not a valid number", literal));
+
+        if !hex && digits.contains(|c| c == '.' || c == 'e' || c == 'E') {
+            self.literal_type(expected,
+                              BasicType::Double,
+                              &[],
+                              "a floating-point number",
+                              start)?;
+            let n = f64::from_str(literal).map_err(|_| invalid())?;
+            return Ok(basic(BasicValue::Double(n)));
+        }
+
+        let ty = self.literal_type(expected, BasicType::Int32, INTEGER_TYPES, "an integer", start)?;
+        let magnitude = if hex {
+            u64::from_str_radix(&digits[2..], 16)
+        } else {
+            u64::from_str_radix(digits, 10)
+        };
+        let magnitude = magnitude.map_err(|_| invalid())? as i128;
+        let n = if negative { -magnitude } else { magnitude };
+        let out_of_range = || {
+            self.error(start,
+                       format!("{} is out of range for type `{}`",
+                               literal,
+                               Type::BasicType(ty.clone())))
+        };
+        let value = match ty {
+            BasicType::Byte => BasicValue::Byte(u8::try_from(n).map_err(|_| out_of_range())?),
+            BasicType::Int16 => BasicValue::Int16(i16::try_from(n).map_err(|_| out_of_range())?),
+            BasicType::UInt16 => {
+                BasicValue::UInt16(u16::try_from(n).map_err(|_| out_of_range())?)
+            }
+            BasicType::Int32 => BasicValue::Int32(i32::try_from(n).map_err(|_| out_of_range())?),
+            BasicType::UInt32 => {
+                BasicValue::UInt32(u32::try_from(n).map_err(|_| out_of_range())?)
+            }
+            BasicType::Int64 => BasicValue::Int64(i64::try_from(n).map_err(|_| out_of_range())?),
+            BasicType::UInt64 => {
+                BasicValue::UInt64(u64::try_from(n).map_err(|_| out_of_range())?)
+            }
+            BasicType::UnixFd => {
+                BasicValue::UnixFd(u32::try_from(n).map_err(|_| out_of_range())?)
+            }
+            BasicType::Double => BasicValue::Double(n as f64),
+            _ => unreachable!(),
+        };
+        Ok(basic(value))
+    }
+}
+
+fn basic(value: BasicValue) -> (Value, Type) {
+    let ty = Type::BasicType(value.basic_type());
+    (Value::BasicValue(value), ty)
+}
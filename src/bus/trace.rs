@@ -0,0 +1,342 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use serde::de::{self, Deserialize, DeserializeSeed, IntoDeserializer, Visitor};
+use std::result;
+
+use bus::ser::VARIANT_NEWTYPE;
+use bus::types::{BasicType, ContainerType, Signature, Type, MAX_TOTAL_DEPTH};
+use bus::wire::{PathSegment, TypeError, TypeErrorKind};
+
+/// Computes the D-Bus type `to_value` gives values of `T`, as a single-type
+/// signature, by watching what `T`'s `Deserialize` impl asks for.
+///
+/// Enums are typed as `(sv)`, whatever their variants hold. Types that ask
+/// for a self-describing format, such as untagged enums, can't be traced.
+pub fn signature_of<'de, T: Deserialize<'de>>() -> result::Result<Signature, TypeError> {
+    let mut ty = None;
+    T::deserialize(Tracer::new(&mut ty, 0))?;
+    Ok(Signature::new(vec![Tracer::traced(ty)?]))
+}
+
+fn invalid(reason: &str) -> TypeError {
+    TypeError::new(TypeErrorKind::Invalid(reason.to_owned()))
+}
+
+fn basic(ty: BasicType) -> Type {
+    Type::BasicType(ty)
+}
+
+fn container(ty: ContainerType) -> Type {
+    Type::ContainerType(Box::new(ty))
+}
+
+struct Tracer<'r> {
+    ty: &'r mut Option<Type>,
+    depth: usize,
+}
+
+impl<'r> Tracer<'r> {
+    fn new(ty: &'r mut Option<Type>, depth: usize) -> Self {
+        Tracer {
+            ty: ty,
+            depth: depth,
+        }
+    }
+
+    fn traced(ty: Option<Type>) -> result::Result<Type, TypeError> {
+        ty.ok_or_else(|| invalid("Can't trace the type of a container that was never filled"))
+    }
+
+    fn record(self, ty: Type) {
+        *self.ty = Some(ty);
+    }
+
+    fn nested(&self) -> result::Result<usize, TypeError> {
+        if self.depth >= MAX_TOTAL_DEPTH {
+            Err(invalid("Type is recursive or nested too deeply to trace"))
+        } else {
+            Ok(self.depth + 1)
+        }
+    }
+}
+
+macro_rules! trace_basic {
+    ($($method:ident => $ty:ident, $visit:ident($dummy:expr);)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, TypeError> {
+                self.record(basic(BasicType::$ty));
+                visitor.$visit($dummy)
+            }
+        )*
+    }
+}
+
+impl<'de, 'r> de::Deserializer<'de> for Tracer<'r> {
+    type Error = TypeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> result::Result<V::Value, TypeError> {
+        Err(invalid("Can't trace the type of a self-describing value"))
+    }
+
+    trace_basic! {
+        deserialize_bool => Bool, visit_bool(false);
+        deserialize_i8 => Int16, visit_i8(0);
+        deserialize_i16 => Int16, visit_i16(0);
+        deserialize_i32 => Int32, visit_i32(0);
+        deserialize_i64 => Int64, visit_i64(0);
+        deserialize_u8 => Byte, visit_u8(0);
+        deserialize_u16 => UInt16, visit_u16(0);
+        deserialize_u32 => UInt32, visit_u32(0);
+        deserialize_u64 => UInt64, visit_u64(0);
+        deserialize_f32 => Double, visit_f32(0.0);
+        deserialize_f64 => Double, visit_f64(0.0);
+        deserialize_char => String, visit_char('\0');
+        deserialize_str => String, visit_borrowed_str("");
+        deserialize_string => String, visit_borrowed_str("");
+        deserialize_identifier => String, visit_borrowed_str("");
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, TypeError> {
+        self.record(container(ContainerType::Array(basic(BasicType::Byte))));
+        visitor.visit_borrowed_bytes(&[])
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self,
+                                             visitor: V)
+                                             -> result::Result<V::Value, TypeError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self,
+                                           visitor: V)
+                                           -> result::Result<V::Value, TypeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, _visitor: V) -> result::Result<V::Value, TypeError> {
+        Err(invalid("D-Bus has no representation for the unit type"))
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self,
+                                                _name: &'static str,
+                                                visitor: V)
+                                                -> result::Result<V::Value, TypeError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self,
+                                                   name: &'static str,
+                                                   visitor: V)
+                                                   -> result::Result<V::Value, TypeError> {
+        if name != VARIANT_NEWTYPE {
+            return visitor.visit_newtype_struct(self);
+        }
+        let depth = self.nested()?;
+        self.record(container(ContainerType::Variant));
+        // The contents are still traced, to build a value, but their type
+        // doesn't appear in the signature.
+        let mut contents = None;
+        visitor.visit_newtype_struct(Tracer::new(&mut contents, depth))
+            .map_err(|err| err.at(PathSegment::Variant))
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, TypeError> {
+        let mut elem = None;
+        let value = visitor.visit_seq(SeqTracer::new(vec![&mut elem], self.nested()?))?;
+        let elem = Tracer::traced(elem).map_err(|err| err.at(PathSegment::Element(0)))?;
+        self.record(container(ContainerType::Array(elem)));
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self,
+                                          len: usize,
+                                          visitor: V)
+                                          -> result::Result<V::Value, TypeError> {
+        if len == 0 {
+            return Err(TypeError::new(TypeErrorKind::EmptyStruct));
+        }
+        let mut fields = vec![None; len];
+        let value = {
+            let slots = fields.iter_mut().collect();
+            visitor.visit_seq(SeqTracer::new(slots, self.nested()?))?
+        };
+        let mut field_tys = Vec::with_capacity(len);
+        for (i, field) in fields.into_iter().enumerate() {
+            field_tys.push(Tracer::traced(field).map_err(|err| err.at(PathSegment::Field(i)))?);
+        }
+        self.record(container(ContainerType::Struct(field_tys)));
+        Ok(value)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self,
+                                                 _name: &'static str,
+                                                 len: usize,
+                                                 visitor: V)
+                                                 -> result::Result<V::Value, TypeError> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> result::Result<V::Value, TypeError> {
+        let depth = self.nested()?;
+        let mut key = None;
+        let mut value_ty = None;
+        let value = visitor.visit_map(MapTracer {
+                key: Some(&mut key),
+                value: Some(&mut value_ty),
+                depth: depth,
+            })?;
+        let key = match Tracer::traced(key).map_err(|err| err.at(PathSegment::Key(0)))? {
+            Type::BasicType(key) => key,
+            Type::ContainerType(_) => {
+                return Err(invalid("Dict keys must be basic values").at(PathSegment::Key(0)))
+            }
+        };
+        let value_ty = Tracer::traced(value_ty).map_err(|err| err.at(PathSegment::Value(0)))?;
+        self.record(container(ContainerType::Dict(key, value_ty)));
+        Ok(value)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self,
+                                           _name: &'static str,
+                                           fields: &'static [&'static str],
+                                           visitor: V)
+                                           -> result::Result<V::Value, TypeError> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self,
+                                         _name: &'static str,
+                                         variants: &'static [&'static str],
+                                         visitor: V)
+                                         -> result::Result<V::Value, TypeError> {
+        let variant = match variants.first() {
+            Some(&variant) => variant,
+            None => return Err(invalid("Can't trace the type of an enum with no variants")),
+        };
+        // Only the first variant needs tracing, to give the visitor
+        // something to build; the contents' type doesn't show in `(sv)`.
+        let depth = self.nested()?;
+        let value = visitor.visit_enum(EnumTracer {
+                variant: variant,
+                depth: depth,
+            })?;
+        self.record(container(ContainerType::Struct(vec![basic(BasicType::String),
+                                                         container(ContainerType::Variant)])));
+        Ok(value)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self,
+                                                visitor: V)
+                                                -> result::Result<V::Value, TypeError> {
+        self.deserialize_any(visitor)
+    }
+}
+
+// Feeds one traced element to each slot, then ends the sequence.
+struct SeqTracer<'r> {
+    slots: ::std::vec::IntoIter<&'r mut Option<Type>>,
+    depth: usize,
+}
+
+impl<'r> SeqTracer<'r> {
+    fn new(slots: Vec<&'r mut Option<Type>>, depth: usize) -> Self {
+        SeqTracer {
+            slots: slots.into_iter(),
+            depth: depth,
+        }
+    }
+}
+
+impl<'de, 'r> de::SeqAccess<'de> for SeqTracer<'r> {
+    type Error = TypeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> result::Result<Option<T::Value>, TypeError>
+        where T: DeserializeSeed<'de>
+    {
+        match self.slots.next() {
+            Some(slot) => seed.deserialize(Tracer::new(slot, self.depth)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+// Feeds a single traced entry, then ends the map.
+struct MapTracer<'r> {
+    key: Option<&'r mut Option<Type>>,
+    value: Option<&'r mut Option<Type>>,
+    depth: usize,
+}
+
+impl<'de, 'r> de::MapAccess<'de> for MapTracer<'r> {
+    type Error = TypeError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> result::Result<Option<K::Value>, TypeError>
+        where K: DeserializeSeed<'de>
+    {
+        match self.key.take() {
+            Some(slot) => seed.deserialize(Tracer::new(slot, self.depth)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> result::Result<V::Value, TypeError>
+        where V: DeserializeSeed<'de>
+    {
+        let slot = self.value.take().expect("next_value_seed called twice");
+        seed.deserialize(Tracer::new(slot, self.depth))
+    }
+}
+
+struct EnumTracer {
+    variant: &'static str,
+    depth: usize,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumTracer {
+    type Error = TypeError;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> result::Result<(V::Value, Self), TypeError>
+        where V: DeserializeSeed<'de>
+    {
+        let variant: de::value::StrDeserializer<TypeError> = self.variant.into_deserializer();
+        Ok((seed.deserialize(variant)?, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for EnumTracer {
+    type Error = TypeError;
+
+    fn unit_variant(self) -> result::Result<(), TypeError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> result::Result<T::Value, TypeError>
+        where T: DeserializeSeed<'de>
+    {
+        let mut contents = None;
+        seed.deserialize(Tracer::new(&mut contents, self.depth))
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> result::Result<V::Value, TypeError>
+        where V: Visitor<'de>
+    {
+        let mut contents = None;
+        de::Deserializer::deserialize_tuple(Tracer::new(&mut contents, self.depth), len, visitor)
+    }
+
+    fn struct_variant<V>(self,
+                         fields: &'static [&'static str],
+                         visitor: V)
+                         -> result::Result<V::Value, TypeError>
+        where V: Visitor<'de>
+    {
+        let mut contents = None;
+        de::Deserializer::deserialize_struct(Tracer::new(&mut contents, self.depth),
+                                             "",
+                                             fields,
+                                             visitor)
+    }
+}
@@ -0,0 +1,328 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use futures::{Async, AsyncSink, Poll, Sink, StartSend, Stream};
+use libc;
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Result};
+use std::mem;
+use std::net::Shutdown;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::ptr;
+use std::sync::Arc;
+use tokio_uds::UnixStream;
+
+use bus::fd::OwnedFd;
+use bus::message::{self, Limits, Message};
+
+const READ_CHUNK_LEN: usize = 8 * 1024;
+// Past this much unsent data, `start_send` pushes back on the caller.
+const WRITE_HIGH_WATER_MARK: usize = 64 * 1024;
+// The most descriptors libdbus will put in one message.
+const MAX_FDS_PER_MESSAGE: usize = 253;
+
+/// Moves messages over a Unix socket, passing the descriptors attached to
+/// them as `SCM_RIGHTS` ancillary data.
+pub struct Transport {
+    stream: UnixStream,
+    limits: Limits,
+    // Received messages keep their bodies in this buffer, so it's only
+    // written to while no message holds on to it.
+    read_buf: Arc<Vec<u8>>,
+    // The start of the data in `read_buf` that hasn't been decoded yet.
+    read_start: usize,
+    // Descriptors that haven't yet been claimed by a message, in the order
+    // they arrived, each tagged with the range of `read_buf` that was read
+    // along with it.
+    read_fds: VecDeque<(usize, usize, OwnedFd)>,
+    eof: bool,
+    write_buf: Vec<u8>,
+    // Descriptors waiting to be sent, each batch tagged with the offset in
+    // `write_buf` of the message it belongs to.
+    write_fds: VecDeque<(usize, Vec<OwnedFd>)>,
+}
+
+impl Transport {
+    pub fn new(stream: UnixStream, limits: Limits) -> Self {
+        Transport {
+            stream: stream,
+            limits: limits,
+            read_buf: Arc::new(Vec::new()),
+            read_start: 0,
+            read_fds: VecDeque::new(),
+            eof: false,
+            write_buf: Vec::new(),
+            write_fds: VecDeque::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> UnixStream {
+        self.stream
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<()> {
+        self.stream.shutdown(how)
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    fn decode(&mut self) -> Result<Option<Message>> {
+        let decoded =
+            message::decode_shared_message(&self.read_buf, self.read_start, &self.limits)?;
+        let (mut msg, end) = match decoded {
+            Some(decoded) => decoded,
+            None => return Ok(None),
+        };
+
+        // Descriptors are sent with the first byte of their message, so this
+        // message's are the first ones not yet claimed, and were read along
+        // with that byte or before it.
+        let start = self.read_start;
+        let wanted = msg.fields.unix_fds.unwrap_or(0) as usize;
+        let available = self.read_fds.iter().take_while(|&&(from, _, _)| from <= start).count();
+        if available < wanted {
+            return Err(Error::new(ErrorKind::InvalidData,
+                                  "D-Bus message refers to more file descriptors than were \
+                                   received"));
+        }
+        for (_, _, fd) in self.read_fds.drain(..wanted) {
+            msg.attach_fd(fd);
+        }
+
+        self.read_start = end;
+        // Descriptors left from a read that no undecoded message starts in
+        // were sent without being declared. They're closed as they're
+        // dropped.
+        while self.read_fds.front().map_or(false, |&(_, to, _)| to <= end) {
+            self.read_fds.pop_front();
+        }
+        Ok(Some(msg))
+    }
+
+    // Drops the decoded data from the read buffer, moving what's left to a
+    // new buffer if messages still hold the current one.
+    fn read_buf_mut(&mut self) -> &mut Vec<u8> {
+        let start = mem::replace(&mut self.read_start, 0);
+        if Arc::get_mut(&mut self.read_buf).is_some() {
+            Arc::get_mut(&mut self.read_buf).unwrap().drain(..start);
+        } else {
+            self.read_buf = Arc::new(self.read_buf[start..].to_vec());
+        }
+        for &mut (ref mut from, ref mut to, _) in &mut self.read_fds {
+            *from = from.saturating_sub(start);
+            *to -= start;
+        }
+        Arc::get_mut(&mut self.read_buf).unwrap()
+    }
+
+    fn fill_read_buf(&mut self) -> Poll<(), Error> {
+        loop {
+            if let Async::NotReady = self.stream.poll_read() {
+                return Ok(Async::NotReady);
+            }
+
+            let raw_fd = self.stream.as_raw_fd();
+            let read_buf = self.read_buf_mut();
+            let start = read_buf.len();
+            read_buf.resize(start + READ_CHUNK_LEN, 0);
+            let mut fds = Vec::new();
+            let result = recv_with_fds(raw_fd, &mut read_buf[start..], &mut fds);
+            let end = start + *result.as_ref().unwrap_or(&0);
+            read_buf.truncate(end);
+            // A read can take in the descriptors of several messages, so
+            // they're queued and claimed as each message is decoded.
+            self.read_fds.extend(fds.into_iter().map(|fd| (start, end, fd)));
+            match result {
+                Ok(n) => {
+                    if n == 0 {
+                        self.eof = true;
+                    }
+                    return Ok(Async::Ready(()));
+                }
+                Err(err) => {
+                    match err.kind() {
+                        ErrorKind::WouldBlock => {
+                            self.stream.need_read();
+                            return Ok(Async::NotReady);
+                        }
+                        ErrorKind::Interrupted => {}
+                        _ => return Err(err),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Stream for Transport {
+    type Item = Message;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Message>, Error> {
+        loop {
+            if let Some(msg) = self.decode()? {
+                return Ok(Async::Ready(Some(msg)));
+            }
+            if self.eof {
+                if self.read_start == self.read_buf.len() {
+                    return Ok(Async::Ready(None));
+                }
+                return Err(Error::new(ErrorKind::UnexpectedEof,
+                                      "D-Bus connection closed in the middle of a message"));
+            }
+            try_ready!(self.fill_read_buf());
+        }
+    }
+}
+
+impl Sink for Transport {
+    type SinkItem = Message;
+    type SinkError = Error;
+
+    fn start_send(&mut self, mut msg: Message) -> StartSend<Message, Error> {
+        if self.write_buf.len() >= WRITE_HIGH_WATER_MARK {
+            self.poll_complete()?;
+            if self.write_buf.len() >= WRITE_HIGH_WATER_MARK {
+                return Ok(AsyncSink::NotReady(msg));
+            }
+        }
+
+        let offset = self.write_buf.len();
+        message::encode_message(&msg, &mut self.write_buf)?;
+        let fds = msg.take_fds();
+        if !fds.is_empty() {
+            self.write_fds.push_back((offset, fds));
+        }
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Error> {
+        while !self.write_buf.is_empty() {
+            if let Async::NotReady = self.stream.poll_write() {
+                return Ok(Async::NotReady);
+            }
+
+            // Descriptors go out with the first byte of their message, so
+            // stop short of the next message that carries any.
+            let (fds, len) = {
+                let mut batches = self.write_fds.iter();
+                match batches.next() {
+                    Some(&(0, ref fds)) => {
+                        let len = batches.next()
+                            .map_or(self.write_buf.len(), |&(next, _)| next);
+                        (fds.iter().map(AsRawFd::as_raw_fd).collect(), len)
+                    }
+                    Some(&(next, _)) => (Vec::new(), next),
+                    None => (Vec::new(), self.write_buf.len()),
+                }
+            };
+
+            match send_with_fds(self.stream.as_raw_fd(), &self.write_buf[..len], &fds) {
+                Ok(n) => {
+                    // The kernel has its own references to the descriptors
+                    // now, so ours can be closed.
+                    if !fds.is_empty() {
+                        self.write_fds.pop_front();
+                    }
+                    self.write_buf.drain(..n);
+                    for batch in &mut self.write_fds {
+                        batch.0 -= n;
+                    }
+                }
+                Err(err) => {
+                    match err.kind() {
+                        ErrorKind::WouldBlock => {
+                            self.stream.need_write();
+                            return Ok(Async::NotReady);
+                        }
+                        ErrorKind::Interrupted => {}
+                        _ => return Err(err),
+                    }
+                }
+            }
+        }
+        Ok(Async::Ready(()))
+    }
+}
+
+fn cmsg_space(num_fds: usize) -> usize {
+    unsafe { libc::CMSG_SPACE((num_fds * mem::size_of::<RawFd>()) as u32) as usize }
+}
+
+fn recv_with_fds(socket: RawFd, buf: &mut [u8], fds: &mut Vec<OwnedFd>) -> Result<usize> {
+    // u64s keep the control buffer aligned for `cmsghdr`.
+    let mut control = vec![0u64; (cmsg_space(MAX_FDS_PER_MESSAGE) + 7) / 8];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = (control.len() * 8) as _;
+
+    let n = unsafe { libc::recvmsg(socket, &mut msg, libc::MSG_CMSG_CLOEXEC) };
+    if n < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                let data_len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                for i in 0..data_len / mem::size_of::<RawFd>() {
+                    fds.push(OwnedFd::from_raw_fd(ptr::read_unaligned(data.offset(i as isize))));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(Error::new(ErrorKind::InvalidData,
+                              "too many file descriptors received at once"));
+    }
+    Ok(n as usize)
+}
+
+fn send_with_fds(socket: RawFd, buf: &[u8], fds: &[RawFd]) -> Result<usize> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+
+    let mut control = vec![0u64; (cmsg_space(fds.len()) + 7) / 8];
+    if !fds.is_empty() {
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = cmsg_space(fds.len()) as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN((fds.len() * mem::size_of::<RawFd>()) as u32) as _;
+            ptr::copy_nonoverlapping(fds.as_ptr(),
+                                     libc::CMSG_DATA(cmsg) as *mut RawFd,
+                                     fds.len());
+        }
+    }
+
+    let n = unsafe { libc::sendmsg(socket, &msg, libc::MSG_NOSIGNAL) };
+    if n < 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(n as usize)
+    }
+}
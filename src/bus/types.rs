@@ -3,9 +3,15 @@
 // obtain one at http://mozilla.org/MPL/2.0/.
 
 use nom::{IResult};
+use std::error;
+use std::fmt::{self, Display, Formatter};
 use std::io::{Error, ErrorKind, Result};
+use std::ops::Deref;
+use std::result;
+use std::str::{self, FromStr};
 
-pub type Signature = Vec<Type>;
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Signature(Vec<Type>);
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Type {
@@ -36,6 +42,444 @@ pub enum ContainerType {
     Dict(BasicType, Type),
     Struct(Vec<Type>),
     Variant,
+    /// GVariant's `m` type: a value of the inner type, or nothing. The D-Bus
+    /// wire format can't carry it.
+    #[cfg(feature = "maybe")]
+    Maybe(Type),
+}
+
+impl Signature {
+    pub fn new(tys: Vec<Type>) -> Self {
+        Signature(tys)
+    }
+
+    pub fn into_types(self) -> Vec<Type> {
+        self.0
+    }
+}
+
+impl Deref for Signature {
+    type Target = [Type];
+
+    fn deref(&self) -> &[Type] {
+        &self.0
+    }
+}
+
+impl From<Vec<Type>> for Signature {
+    fn from(tys: Vec<Type>) -> Self {
+        Signature(tys)
+    }
+}
+
+impl IntoIterator for Signature {
+    type Item = Type;
+    type IntoIter = ::std::vec::IntoIter<Type>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Signature {
+    type Item = &'a Type;
+    type IntoIter = ::std::slice::Iter<'a, Type>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut buf = Vec::new();
+        encode_signature(self, &mut buf);
+        // Type codes are all ASCII.
+        f.write_str(str::from_utf8(&buf).unwrap())
+    }
+}
+
+impl FromStr for Signature {
+    type Err = SignatureError;
+
+    fn from_str(s: &str) -> result::Result<Self, SignatureError> {
+        validate_signature(s.as_bytes())
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut buf = Vec::new();
+        encode_type(self, &mut buf);
+        f.write_str(str::from_utf8(&buf).unwrap())
+    }
+}
+
+impl FromStr for Type {
+    type Err = SignatureError;
+
+    fn from_str(s: &str) -> result::Result<Self, SignatureError> {
+        validate_single_type(s.as_bytes())
+    }
+}
+
+/// The longest signature the D-Bus specification allows, in bytes.
+pub const MAX_SIGNATURE_LEN: usize = 255;
+/// The deepest arrays may be nested in a single complete type.
+pub const MAX_ARRAY_DEPTH: usize = 32;
+/// The deepest structs (and dict entries) may be nested in a single complete
+/// type.
+pub const MAX_STRUCT_DEPTH: usize = 32;
+/// The deepest containers of any kind may be nested, counting variants in
+/// values as well as arrays and structs.
+pub const MAX_TOTAL_DEPTH: usize = 64;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SignatureError {
+    TooLong { len: usize },
+    UnexpectedEnd,
+    InvalidTypeCode { offset: usize, code: u8 },
+    UnexpectedClose { offset: usize, code: u8 },
+    EmptyStruct { offset: usize },
+    DictEntryOutsideArray { offset: usize },
+    DictKeyNotBasic { offset: usize },
+    DictEntryArity { offset: usize },
+    ArrayDepthExceeded { offset: usize },
+    StructDepthExceeded { offset: usize },
+    TotalDepthExceeded { offset: usize },
+    NotSingleType { offset: usize },
+}
+
+impl Display for SignatureError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            SignatureError::TooLong { len } => {
+                write!(f,
+                       "D-Bus signature is {} bytes long, exceeding the maximum of {}.",
+                       len,
+                       MAX_SIGNATURE_LEN)
+            }
+            SignatureError::UnexpectedEnd => {
+                write!(f, "D-Bus signature ends in the middle of a type.")
+            }
+            SignatureError::InvalidTypeCode { offset, code } => {
+                write!(f,
+                       "Invalid type code {:?} at offset {} in D-Bus signature.",
+                       code as char,
+                       offset)
+            }
+            SignatureError::UnexpectedClose { offset, code } => {
+                write!(f,
+                       "Unbalanced {:?} at offset {} in D-Bus signature.",
+                       code as char,
+                       offset)
+            }
+            SignatureError::EmptyStruct { offset } => {
+                write!(f, "Empty struct at offset {} in D-Bus signature.", offset)
+            }
+            SignatureError::DictEntryOutsideArray { offset } => {
+                write!(f,
+                       "Dict entry at offset {} in D-Bus signature is not the element type \
+                        of an array.",
+                       offset)
+            }
+            SignatureError::DictKeyNotBasic { offset } => {
+                write!(f,
+                       "Dict entry key at offset {} in D-Bus signature is not a basic type.",
+                       offset)
+            }
+            SignatureError::DictEntryArity { offset } => {
+                write!(f,
+                       "Dict entry at offset {} in D-Bus signature does not contain exactly \
+                        two types.",
+                       offset)
+            }
+            SignatureError::ArrayDepthExceeded { offset } => {
+                write!(f,
+                       "Array at offset {} in D-Bus signature exceeds the maximum array \
+                        nesting depth of {}.",
+                       offset,
+                       MAX_ARRAY_DEPTH)
+            }
+            SignatureError::StructDepthExceeded { offset } => {
+                write!(f,
+                       "Struct at offset {} in D-Bus signature exceeds the maximum struct \
+                        nesting depth of {}.",
+                       offset,
+                       MAX_STRUCT_DEPTH)
+            }
+            SignatureError::TotalDepthExceeded { offset } => {
+                write!(f,
+                       "Container at offset {} exceeds the maximum total nesting depth of {}.",
+                       offset,
+                       MAX_TOTAL_DEPTH)
+            }
+            SignatureError::NotSingleType { offset } => {
+                write!(f,
+                       "Expected a single complete type, but found another type at offset {}.",
+                       offset)
+            }
+        }
+    }
+}
+
+impl error::Error for SignatureError {
+    fn description(&self) -> &str {
+        match *self {
+            SignatureError::TooLong { .. } => "D-Bus signature too long",
+            SignatureError::UnexpectedEnd => "truncated D-Bus signature",
+            SignatureError::InvalidTypeCode { .. } => "invalid D-Bus type code",
+            SignatureError::UnexpectedClose { .. } => "unbalanced D-Bus signature",
+            SignatureError::EmptyStruct { .. } => "empty struct in D-Bus signature",
+            SignatureError::DictEntryOutsideArray { .. } => "D-Bus dict entry outside array",
+            SignatureError::DictKeyNotBasic { .. } => "D-Bus dict key not a basic type",
+            SignatureError::DictEntryArity { .. } => "malformed D-Bus dict entry",
+            SignatureError::ArrayDepthExceeded { .. } => "D-Bus array nesting too deep",
+            SignatureError::StructDepthExceeded { .. } => "D-Bus struct nesting too deep",
+            SignatureError::TotalDepthExceeded { .. } => "D-Bus container nesting too deep",
+            SignatureError::NotSingleType { .. } => "not a single complete D-Bus type",
+        }
+    }
+}
+
+impl From<SignatureError> for Error {
+    fn from(err: SignatureError) -> Self {
+        Error::new(ErrorKind::InvalidData, err)
+    }
+}
+
+/// Tracks how deeply containers are nested while walking a signature or a
+/// value, so that hostile input can't recurse without bound.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Depth {
+    array: usize,
+    structure: usize,
+    variant: usize,
+}
+
+impl Depth {
+    pub fn new() -> Self {
+        Depth::default()
+    }
+
+    pub fn enter_array(self, offset: usize) -> result::Result<Self, SignatureError> {
+        if self.array == MAX_ARRAY_DEPTH {
+            return Err(SignatureError::ArrayDepthExceeded { offset: offset });
+        }
+        Depth { array: self.array + 1, ..self }.check_total(offset)
+    }
+
+    pub fn enter_struct(self, offset: usize) -> result::Result<Self, SignatureError> {
+        if self.structure == MAX_STRUCT_DEPTH {
+            return Err(SignatureError::StructDepthExceeded { offset: offset });
+        }
+        Depth { structure: self.structure + 1, ..self }.check_total(offset)
+    }
+
+    pub fn enter_variant(self, offset: usize) -> result::Result<Self, SignatureError> {
+        Depth { variant: self.variant + 1, ..self }.check_total(offset)
+    }
+
+    fn check_total(self, offset: usize) -> result::Result<Self, SignatureError> {
+        if self.array + self.structure + self.variant > MAX_TOTAL_DEPTH {
+            Err(SignatureError::TotalDepthExceeded { offset: offset })
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+/// Parses a complete signature, enforcing every rule of the D-Bus
+/// specification: the length limit, the nesting limits, and the placement of
+/// dict entries. Unlike `decode_signature`, the whole input must be consumed
+/// and the empty signature is accepted.
+///
+/// GVariant's maybe type is never accepted here, even with the `maybe`
+/// feature; see `validate_gvariant_signature`.
+pub fn validate_signature(input: &[u8]) -> result::Result<Signature, SignatureError> {
+    strict_signature(input, false)
+}
+
+/// Parses exactly one complete type, such as the signature carried by a
+/// variant, with the same checks as `validate_signature`.
+pub fn validate_single_type(input: &[u8]) -> result::Result<Type, SignatureError> {
+    strict_single_type(input, false)
+}
+
+/// Like `validate_signature`, but for GVariant type strings, which may also
+/// hold maybe types when the `maybe` feature is enabled.
+pub fn validate_gvariant_signature(input: &[u8]) -> result::Result<Signature, SignatureError> {
+    strict_signature(input, true)
+}
+
+/// Like `validate_single_type`, but for GVariant type strings, which may also
+/// hold maybe types when the `maybe` feature is enabled.
+pub fn validate_gvariant_type(input: &[u8]) -> result::Result<Type, SignatureError> {
+    strict_single_type(input, true)
+}
+
+fn strict_signature(input: &[u8], gvariant: bool) -> result::Result<Signature, SignatureError> {
+    if input.len() > MAX_SIGNATURE_LEN {
+        return Err(SignatureError::TooLong { len: input.len() });
+    }
+
+    let mut parser = StrictParser::new(input, gvariant);
+    let mut tys = Vec::new();
+    while parser.offset < input.len() {
+        tys.push(parser.complete_type(Depth::new())?);
+    }
+    Ok(Signature(tys))
+}
+
+fn strict_single_type(input: &[u8], gvariant: bool) -> result::Result<Type, SignatureError> {
+    if input.len() > MAX_SIGNATURE_LEN {
+        return Err(SignatureError::TooLong { len: input.len() });
+    }
+
+    let mut parser = StrictParser::new(input, gvariant);
+    let ty = parser.complete_type(Depth::new())?;
+    if parser.offset < input.len() {
+        return Err(SignatureError::NotSingleType { offset: parser.offset });
+    }
+    Ok(ty)
+}
+
+pub fn basic_type_from_code(code: u8) -> Option<BasicType> {
+    match code {
+        b'y' => Some(BasicType::Byte),
+        b'b' => Some(BasicType::Bool),
+        b'n' => Some(BasicType::Int16),
+        b'q' => Some(BasicType::UInt16),
+        b'i' => Some(BasicType::Int32),
+        b'u' => Some(BasicType::UInt32),
+        b'x' => Some(BasicType::Int64),
+        b't' => Some(BasicType::UInt64),
+        b'd' => Some(BasicType::Double),
+        b's' => Some(BasicType::String),
+        b'o' => Some(BasicType::ObjectPath),
+        b'g' => Some(BasicType::Signature),
+        b'h' => Some(BasicType::UnixFd),
+        _ => None,
+    }
+}
+
+struct StrictParser<'a> {
+    input: &'a [u8],
+    offset: usize,
+    // Whether `m` is a type code. Cargo features are unified across a build,
+    // so the `maybe` feature alone mustn't make D-Bus signatures accept it.
+    maybe: bool,
+}
+
+impl<'a> StrictParser<'a> {
+    fn new(input: &'a [u8], gvariant: bool) -> Self {
+        StrictParser {
+            input: input,
+            offset: 0,
+            maybe: gvariant && cfg!(feature = "maybe"),
+        }
+    }
+
+    fn next(&mut self) -> result::Result<u8, SignatureError> {
+        match self.input.get(self.offset) {
+            Some(&code) => {
+                self.offset += 1;
+                Ok(code)
+            }
+            None => Err(SignatureError::UnexpectedEnd),
+        }
+    }
+
+    fn peek(&self) -> result::Result<u8, SignatureError> {
+        self.input.get(self.offset).cloned().ok_or(SignatureError::UnexpectedEnd)
+    }
+
+    // Recursion here is bounded by the depth limits, which are checked before
+    // descending into any container.
+    fn complete_type(&mut self, depth: Depth) -> result::Result<Type, SignatureError> {
+        let offset = self.offset;
+        let code = self.next()?;
+        if let Some(basic_ty) = basic_type_from_code(code) {
+            return Ok(Type::BasicType(basic_ty));
+        }
+
+        let container_ty = match code {
+            b'a' => {
+                let depth = depth.enter_array(offset)?;
+                if self.peek()? == b'{' {
+                    self.dict_entry(depth)?
+                } else {
+                    ContainerType::Array(self.complete_type(depth)?)
+                }
+            }
+            b'(' => {
+                let depth = depth.enter_struct(offset)?;
+                if self.peek()? == b')' {
+                    return Err(SignatureError::EmptyStruct { offset: offset });
+                }
+                let mut inner_tys = Vec::new();
+                while self.peek()? != b')' {
+                    inner_tys.push(self.complete_type(depth)?);
+                }
+                self.offset += 1;
+                ContainerType::Struct(inner_tys)
+            }
+            b'v' => ContainerType::Variant,
+            #[cfg(feature = "maybe")]
+            b'm' if self.maybe => {
+                let depth = depth.enter_array(offset)?;
+                ContainerType::Maybe(self.complete_type(depth)?)
+            }
+            b'{' => return Err(SignatureError::DictEntryOutsideArray { offset: offset }),
+            b')' | b'}' => {
+                return Err(SignatureError::UnexpectedClose {
+                    offset: offset,
+                    code: code,
+                })
+            }
+            _ => {
+                return Err(SignatureError::InvalidTypeCode {
+                    offset: offset,
+                    code: code,
+                })
+            }
+        };
+        Ok(Type::ContainerType(Box::new(container_ty)))
+    }
+
+    fn dict_entry(&mut self, depth: Depth) -> result::Result<ContainerType, SignatureError> {
+        let offset = self.offset;
+        self.offset += 1;
+        let depth = depth.enter_struct(offset)?;
+
+        let key_offset = self.offset;
+        let key_code = self.next()?;
+        let key_ty = match basic_type_from_code(key_code) {
+            Some(key_ty) => key_ty,
+            None if key_code == b'}' => {
+                return Err(SignatureError::DictEntryArity { offset: offset })
+            }
+            None if key_code == b'a' || key_code == b'(' || key_code == b'v' ||
+                    key_code == b'{' || self.maybe && key_code == b'm' => {
+                return Err(SignatureError::DictKeyNotBasic { offset: key_offset })
+            }
+            None => {
+                return Err(SignatureError::InvalidTypeCode {
+                    offset: key_offset,
+                    code: key_code,
+                })
+            }
+        };
+
+        if self.peek()? == b'}' {
+            return Err(SignatureError::DictEntryArity { offset: offset });
+        }
+        let value_ty = self.complete_type(depth)?;
+        if self.next()? != b'}' {
+            return Err(SignatureError::DictEntryArity { offset: offset });
+        }
+        Ok(ContainerType::Dict(key_ty, value_ty))
+    }
 }
 
 pub fn decode_signature(input: &[u8]) -> Result<Option<(Signature, &[u8])>> {
@@ -54,7 +498,7 @@ pub fn encode_signature(signature: &Signature, output: &mut Vec<u8>) {
     encode_types(signature, output);
 }
 
-fn encode_types(tys: &Vec<Type>, output: &mut Vec<u8>) {
+fn encode_types(tys: &[Type], output: &mut Vec<u8>) {
     for ty in tys {
         encode_type(ty, output);
     }
@@ -115,11 +559,16 @@ fn encode_container_type(ty: &ContainerType, output: &mut Vec<u8>) {
             output.push(b')');
         }
         ContainerType::Variant => output.push(b'v'),
+        #[cfg(feature = "maybe")]
+        ContainerType::Maybe(ref inner_ty) => {
+            output.push(b'm');
+            encode_type(inner_ty, output);
+        }
     }
 }
 
 named!(parse_signature(&[u8]) -> Signature,
-    many1!(parse_type)
+    map!(many1!(parse_type), Signature)
 );
 
 named!(parse_type(&[u8]) -> Type,
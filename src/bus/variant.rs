@@ -0,0 +1,240 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::btree_map::{self, BTreeMap};
+use std::iter::FromIterator;
+use std::result;
+
+use bus::convert::{DBusType, FromDBus, ToDBus};
+use bus::literal::ConstSignature;
+use bus::types::Type;
+use bus::wire::{BasicValue, ContainerValue, PathSegment, TypeError, TypeErrorKind, Value};
+
+/// A D-Bus variant: a value of any type, tagged with its type on the wire.
+///
+/// The type is kept alongside the value, since an empty array or dict
+/// doesn't say what it would hold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variant {
+    ty: Type,
+    value: Value,
+}
+
+impl Variant {
+    /// A variant holding `value`, with the D-Bus type of `T`.
+    pub fn new<T: ToDBus>(value: T) -> Self {
+        Variant {
+            ty: T::dbus_type(),
+            value: value.to_dbus(),
+        }
+    }
+
+    /// A variant holding `value` as type `ty`, which it must match.
+    pub fn with_type(ty: Type, value: Value) -> result::Result<Self, TypeError> {
+        value.check_type(&ty)?;
+        Ok(Variant {
+            ty: ty,
+            value: value,
+        })
+    }
+
+    /// A variant holding `value`, whose type is inferred as
+    /// `Value::signature` does.
+    pub fn infer(value: Value) -> result::Result<Self, TypeError> {
+        let ty = value.signature()?;
+        Ok(Variant {
+            ty: ty,
+            value: value,
+        })
+    }
+
+    /// The type of the contents.
+    pub fn ty(&self) -> &Type {
+        &self.ty
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn into_inner(self) -> Value {
+        self.value
+    }
+
+    /// Converts a copy of the contents to `T`.
+    pub fn get<T: FromDBus>(&self) -> result::Result<T, TypeError> {
+        self.clone().take()
+    }
+
+    /// Converts the contents to `T`.
+    pub fn take<T: FromDBus>(self) -> result::Result<T, TypeError> {
+        T::from_dbus(self.value).map_err(|err| err.at(PathSegment::Variant))
+    }
+}
+
+impl DBusType for Variant {
+    const SIGNATURE: ConstSignature = ConstSignature::new("v");
+}
+
+impl ToDBus for Variant {
+    fn to_dbus(&self) -> Value {
+        Value::ContainerValue(ContainerValue::Variant(self.ty.clone(),
+                                                      Box::new(self.value.clone())))
+    }
+}
+
+impl FromDBus for Variant {
+    fn from_dbus(value: Value) -> result::Result<Self, TypeError> {
+        match value {
+            Value::ContainerValue(ContainerValue::Variant(ty, inner)) => {
+                Ok(Variant {
+                    ty: ty,
+                    value: *inner,
+                })
+            }
+            value => {
+                Err(TypeError::new(TypeErrorKind::Mismatch {
+                    expected: Self::dbus_type(),
+                    found: value.kind_name(),
+                }))
+            }
+        }
+    }
+}
+
+/// An `a{sv}` dict, as used for D-Bus properties and option arguments.
+///
+/// Entries are kept sorted by name, so a map is marshalled the same way
+/// however it was built.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PropertyMap {
+    entries: BTreeMap<String, Variant>,
+}
+
+impl PropertyMap {
+    pub fn new() -> Self {
+        PropertyMap::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// Sets the property `name` to `value`, returning the previous value.
+    pub fn insert<K, T>(&mut self, name: K, value: T) -> Option<Variant>
+        where K: Into<String>,
+              T: ToDBus
+    {
+        self.entries.insert(name.into(), Variant::new(value))
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Variant> {
+        self.entries.remove(name)
+    }
+
+    /// The property `name`, without converting it.
+    pub fn get_variant(&self, name: &str) -> Option<&Variant> {
+        self.entries.get(name)
+    }
+
+    /// Converts the property `name` to `T`, if present. Errors are located
+    /// at the property's entry in the marshalled dict.
+    pub fn get_opt<T: FromDBus>(&self, name: &str) -> result::Result<Option<T>, TypeError> {
+        match self.entries.get(name) {
+            Some(variant) => {
+                let i = self.entries.keys().take_while(|key| key.as_str() < name).count();
+                variant.get().map(Some).map_err(|err| err.at(PathSegment::Value(i)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Converts the property `name` to `T`; a missing property is an error.
+    pub fn get<T: FromDBus>(&self, name: &str) -> result::Result<T, TypeError> {
+        match self.get_opt(name)? {
+            Some(value) => Ok(value),
+            None => {
+                Err(TypeError::new(TypeErrorKind::Invalid(format!("Missing property `{}`",
+                                                                  name))))
+            }
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> btree_map::Iter<'a, String, Variant> {
+        self.entries.iter()
+    }
+}
+
+impl DBusType for PropertyMap {
+    const SIGNATURE: ConstSignature = ConstSignature::dict(String::SIGNATURE, Variant::SIGNATURE);
+}
+
+impl ToDBus for PropertyMap {
+    fn to_dbus(&self) -> Value {
+        Value::ContainerValue(ContainerValue::Dict(self.entries
+            .iter()
+            .map(|(name, variant)| (BasicValue::String(name.clone().into()), variant.to_dbus()))
+            .collect()))
+    }
+}
+
+impl FromDBus for PropertyMap {
+    fn from_dbus(value: Value) -> result::Result<Self, TypeError> {
+        let entries = match value {
+            Value::ContainerValue(ContainerValue::Dict(entries)) => entries,
+            value => {
+                return Err(TypeError::new(TypeErrorKind::Mismatch {
+                    expected: Self::dbus_type(),
+                    found: value.kind_name(),
+                }))
+            }
+        };
+        let mut map = PropertyMap::new();
+        for (i, (key, value)) in entries.into_iter().enumerate() {
+            let name = String::from_dbus(Value::BasicValue(key))
+                .map_err(|err| err.at(PathSegment::Key(i)))?;
+            if map.entries.contains_key(&name) {
+                return Err(TypeError::new(TypeErrorKind::Invalid(format!("Duplicate property \
+                                                                          `{}`",
+                                                                         name)))
+                    .at(PathSegment::Key(i)));
+            }
+            let variant = Variant::from_dbus(value).map_err(|err| err.at(PathSegment::Value(i)))?;
+            map.entries.insert(name, variant);
+        }
+        Ok(map)
+    }
+}
+
+impl FromIterator<(String, Variant)> for PropertyMap {
+    fn from_iter<I: IntoIterator<Item = (String, Variant)>>(iter: I) -> Self {
+        PropertyMap { entries: iter.into_iter().collect() }
+    }
+}
+
+impl IntoIterator for PropertyMap {
+    type Item = (String, Variant);
+    type IntoIter = btree_map::IntoIter<String, Variant>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a PropertyMap {
+    type Item = (&'a String, &'a Variant);
+    type IntoIter = btree_map::Iter<'a, String, Variant>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
@@ -3,9 +3,12 @@
 // obtain one at http://mozilla.org/MPL/2.0/.
 
 use std::borrow::Cow;
-use std::os::unix::io::RawFd;
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{Error, ErrorKind};
+use std::result;
 
-use bus::types::Signature;
+use bus::types::{BasicType, ContainerType, Signature, Type};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Value {
@@ -27,13 +30,366 @@ pub enum BasicValue {
     String(Cow<'static, str>),
     ObjectPath(Cow<'static, [u8]>),
     Signature(Signature),
-    UnixFd(RawFd),
+    /// An index into the descriptors attached to the message.
+    UnixFd(u32),
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ContainerValue {
     Array(Vec<Value>),
     Struct(Vec<Value>),
-    Variant(Box<Value>),
+    /// A value of any type, along with that type, which is what goes on the
+    /// wire ahead of it.
+    Variant(Type, Box<Value>),
     Dict(Vec<(BasicValue, Value)>),
+    /// A GVariant maybe value. Like an empty array, `None` has no type of
+    /// its own and must be checked against a known one.
+    #[cfg(feature = "maybe")]
+    Maybe(Option<Box<Value>>),
+}
+
+/// One step on the way from a message body down to a nested value.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PathSegment {
+    Arg(usize),
+    Element(usize),
+    Field(usize),
+    Key(usize),
+    Value(usize),
+    Variant,
+    #[cfg(feature = "maybe")]
+    Maybe,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TypeErrorKind {
+    Mismatch { expected: Type, found: &'static str },
+    MixedArray { expected: Type, found: Type },
+    Arity { expected: usize, found: usize },
+    EmptyStruct,
+    EmptyContainer,
+    /// The value has the right type but isn't acceptable, such as a string
+    /// that isn't a valid interface name.
+    Invalid(String),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TypeError {
+    pub path: Vec<PathSegment>,
+    pub kind: TypeErrorKind,
+}
+
+impl TypeError {
+    pub fn new(kind: TypeErrorKind) -> Self {
+        TypeError {
+            path: Vec::new(),
+            kind: kind,
+        }
+    }
+
+    /// Records that the error occurred inside `segment` of the value being
+    /// checked. Segments are added innermost first.
+    pub fn at(mut self, segment: PathSegment) -> Self {
+        self.path.insert(0, segment);
+        self
+    }
+}
+
+impl Display for PathSegment {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            PathSegment::Arg(i) => write!(f, "argument {}", i),
+            PathSegment::Element(i) => write!(f, "element {}", i),
+            PathSegment::Field(i) => write!(f, "field {}", i),
+            PathSegment::Key(i) => write!(f, "key of entry {}", i),
+            PathSegment::Value(i) => write!(f, "value of entry {}", i),
+            PathSegment::Variant => write!(f, "variant contents"),
+            #[cfg(feature = "maybe")]
+            PathSegment::Maybe => write!(f, "maybe contents"),
+        }
+    }
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.kind {
+            TypeErrorKind::Mismatch { ref expected, found } => {
+                write!(f, "Expected a value of type `{}`, found {}", expected, found)?
+            }
+            TypeErrorKind::MixedArray { ref expected, ref found } => {
+                write!(f,
+                       "Container elements must share one type; expected `{}`, found `{}`",
+                       expected,
+                       found)?
+            }
+            TypeErrorKind::Arity { expected, found } => {
+                write!(f, "Expected {} values, found {}", expected, found)?
+            }
+            TypeErrorKind::EmptyStruct => write!(f, "Structs must have at least one field")?,
+            TypeErrorKind::EmptyContainer => {
+                write!(f, "Can't infer the element type of an empty container")?
+            }
+            TypeErrorKind::Invalid(ref reason) => write!(f, "{}", reason)?,
+        }
+        for (i, segment) in self.path.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " at " } else { ", " }, segment)?;
+        }
+        write!(f, ".")
+    }
+}
+
+impl error::Error for TypeError {
+    fn description(&self) -> &str {
+        "D-Bus value does not match its type"
+    }
+}
+
+impl From<TypeError> for Error {
+    fn from(err: TypeError) -> Self {
+        Error::new(ErrorKind::InvalidData, err)
+    }
+}
+
+impl BasicValue {
+    pub fn basic_type(&self) -> BasicType {
+        match *self {
+            BasicValue::Byte(_) => BasicType::Byte,
+            BasicValue::Bool(_) => BasicType::Bool,
+            BasicValue::Int16(_) => BasicType::Int16,
+            BasicValue::UInt16(_) => BasicType::UInt16,
+            BasicValue::Int32(_) => BasicType::Int32,
+            BasicValue::UInt32(_) => BasicType::UInt32,
+            BasicValue::Int64(_) => BasicType::Int64,
+            BasicValue::UInt64(_) => BasicType::UInt64,
+            BasicValue::Double(_) => BasicType::Double,
+            BasicValue::String(_) => BasicType::String,
+            BasicValue::ObjectPath(_) => BasicType::ObjectPath,
+            BasicValue::Signature(_) => BasicType::Signature,
+            BasicValue::UnixFd(_) => BasicType::UnixFd,
+        }
+    }
+
+    fn kind_name(&self) -> &'static str {
+        match *self {
+            BasicValue::Byte(_) => "a byte",
+            BasicValue::Bool(_) => "a boolean",
+            BasicValue::Int16(_) => "an int16",
+            BasicValue::UInt16(_) => "a uint16",
+            BasicValue::Int32(_) => "an int32",
+            BasicValue::UInt32(_) => "a uint32",
+            BasicValue::Int64(_) => "an int64",
+            BasicValue::UInt64(_) => "a uint64",
+            BasicValue::Double(_) => "a double",
+            BasicValue::String(_) => "a string",
+            BasicValue::ObjectPath(_) => "an object path",
+            BasicValue::Signature(_) => "a signature",
+            BasicValue::UnixFd(_) => "a Unix file descriptor",
+        }
+    }
+}
+
+impl Value {
+    /// Infers the type of this value. Arrays and dicts take the type of their
+    /// first element, and every other element must agree with it, so empty
+    /// containers can't be inferred; use `check_type` against a known type
+    /// for those instead.
+    pub fn signature(&self) -> result::Result<Type, TypeError> {
+        match *self {
+            Value::BasicValue(ref basic) => Ok(Type::BasicType(basic.basic_type())),
+            Value::ContainerValue(ref container) => {
+                let container_ty = match *container {
+                    ContainerValue::Array(ref elems) => {
+                        let elem_ty = match elems.first() {
+                            Some(first) => {
+                                first.signature().map_err(|err| err.at(PathSegment::Element(0)))?
+                            }
+                            None => return Err(TypeError::new(TypeErrorKind::EmptyContainer)),
+                        };
+                        for (i, elem) in elems.iter().enumerate().skip(1) {
+                            elem.check_inferred(&elem_ty)
+                                .map_err(|err| err.at(PathSegment::Element(i)))?;
+                        }
+                        ContainerType::Array(elem_ty)
+                    }
+                    ContainerValue::Struct(ref fields) => {
+                        if fields.is_empty() {
+                            return Err(TypeError::new(TypeErrorKind::EmptyStruct));
+                        }
+                        let mut field_tys = Vec::with_capacity(fields.len());
+                        for (i, field) in fields.iter().enumerate() {
+                            field_tys.push(field.signature()
+                                .map_err(|err| err.at(PathSegment::Field(i)))?);
+                        }
+                        ContainerType::Struct(field_tys)
+                    }
+                    ContainerValue::Variant(ref inner_ty, ref inner) => {
+                        inner.check_type(inner_ty).map_err(|err| err.at(PathSegment::Variant))?;
+                        ContainerType::Variant
+                    }
+                    ContainerValue::Dict(ref entries) => {
+                        let (key_ty, value_ty) = match entries.first() {
+                            Some(&(ref key, ref value)) => {
+                                (key.basic_type(),
+                                 value.signature().map_err(|err| err.at(PathSegment::Value(0)))?)
+                            }
+                            None => return Err(TypeError::new(TypeErrorKind::EmptyContainer)),
+                        };
+                        for (i, &(ref key, ref value)) in entries.iter().enumerate().skip(1) {
+                            if key.basic_type() != key_ty {
+                                return Err(TypeError::new(TypeErrorKind::MixedArray {
+                                        expected: Type::BasicType(key_ty),
+                                        found: Type::BasicType(key.basic_type()),
+                                    })
+                                    .at(PathSegment::Key(i)));
+                            }
+                            value.check_inferred(&value_ty)
+                                .map_err(|err| err.at(PathSegment::Value(i)))?;
+                        }
+                        ContainerType::Dict(key_ty, value_ty)
+                    }
+                    #[cfg(feature = "maybe")]
+                    ContainerValue::Maybe(Some(ref inner)) => {
+                        ContainerType::Maybe(inner.signature()
+                            .map_err(|err| err.at(PathSegment::Maybe))?)
+                    }
+                    #[cfg(feature = "maybe")]
+                    ContainerValue::Maybe(None) => {
+                        return Err(TypeError::new(TypeErrorKind::EmptyContainer))
+                    }
+                };
+                Ok(Type::ContainerType(Box::new(container_ty)))
+            }
+        }
+    }
+
+    /// Checks that this value can be marshalled as `ty`.
+    pub fn check_type(&self, ty: &Type) -> result::Result<(), TypeError> {
+        let mismatch = || {
+            TypeError::new(TypeErrorKind::Mismatch {
+                expected: ty.clone(),
+                found: self.kind_name(),
+            })
+        };
+
+        match (self, ty) {
+            (&Value::BasicValue(ref basic), &Type::BasicType(ref basic_ty)) => {
+                if basic.basic_type() == *basic_ty {
+                    Ok(())
+                } else {
+                    Err(mismatch())
+                }
+            }
+            (&Value::ContainerValue(ref container), &Type::ContainerType(ref container_ty)) => {
+                match (container, &**container_ty) {
+                    (&ContainerValue::Array(ref elems), &ContainerType::Array(ref elem_ty)) => {
+                        for (i, elem) in elems.iter().enumerate() {
+                            elem.check_type(elem_ty)
+                                .map_err(|err| err.at(PathSegment::Element(i)))?;
+                        }
+                        Ok(())
+                    }
+                    (&ContainerValue::Struct(ref fields),
+                     &ContainerType::Struct(ref field_tys)) => {
+                        if fields.len() != field_tys.len() {
+                            return Err(TypeError::new(TypeErrorKind::Arity {
+                                expected: field_tys.len(),
+                                found: fields.len(),
+                            }));
+                        }
+                        for (i, (field, field_ty)) in fields.iter().zip(field_tys).enumerate() {
+                            field.check_type(field_ty)
+                                .map_err(|err| err.at(PathSegment::Field(i)))?;
+                        }
+                        Ok(())
+                    }
+                    (&ContainerValue::Variant(ref inner_ty, ref inner),
+                     &ContainerType::Variant) => {
+                        inner.check_type(inner_ty).map_err(|err| err.at(PathSegment::Variant))
+                    }
+                    (&ContainerValue::Dict(ref entries),
+                     &ContainerType::Dict(ref key_ty, ref value_ty)) => {
+                        for (i, &(ref key, ref value)) in entries.iter().enumerate() {
+                            if key.basic_type() != *key_ty {
+                                return Err(TypeError::new(TypeErrorKind::Mismatch {
+                                        expected: Type::BasicType(key_ty.clone()),
+                                        found: key.kind_name(),
+                                    })
+                                    .at(PathSegment::Key(i)));
+                            }
+                            value.check_type(value_ty)
+                                .map_err(|err| err.at(PathSegment::Value(i)))?;
+                        }
+                        Ok(())
+                    }
+                    #[cfg(feature = "maybe")]
+                    (&ContainerValue::Maybe(ref inner), &ContainerType::Maybe(ref inner_ty)) => {
+                        match *inner {
+                            Some(ref inner) => {
+                                inner.check_type(inner_ty)
+                                    .map_err(|err| err.at(PathSegment::Maybe))
+                            }
+                            None => Ok(()),
+                        }
+                    }
+                    _ => Err(mismatch()),
+                }
+            }
+            _ => Err(mismatch()),
+        }
+    }
+
+    /// Wraps this value in a variant, inferring the type it holds as
+    /// `signature` does.
+    pub fn into_variant(self) -> result::Result<Value, TypeError> {
+        let ty = self.signature()?;
+        Ok(Value::ContainerValue(ContainerValue::Variant(ty, Box::new(self))))
+    }
+
+    // Like `check_type`, but reports a disagreement with a sibling's inferred
+    // type rather than a declared one.
+    fn check_inferred(&self, ty: &Type) -> result::Result<(), TypeError> {
+        self.check_type(ty).map_err(|err| match err.kind {
+            TypeErrorKind::Mismatch { .. } if err.path.is_empty() => {
+                match self.signature() {
+                    Ok(found) => {
+                        TypeError::new(TypeErrorKind::MixedArray {
+                            expected: ty.clone(),
+                            found: found,
+                        })
+                    }
+                    Err(err) => err,
+                }
+            }
+            _ => err,
+        })
+    }
+
+    /// A short description of this kind of value, such as "an int32", for
+    /// error messages.
+    pub fn kind_name(&self) -> &'static str {
+        match *self {
+            Value::BasicValue(ref basic) => basic.kind_name(),
+            Value::ContainerValue(ContainerValue::Array(_)) => "an array",
+            Value::ContainerValue(ContainerValue::Struct(_)) => "a struct",
+            Value::ContainerValue(ContainerValue::Variant(..)) => "a variant",
+            Value::ContainerValue(ContainerValue::Dict(_)) => "a dict",
+            #[cfg(feature = "maybe")]
+            Value::ContainerValue(ContainerValue::Maybe(_)) => "a maybe",
+        }
+    }
+}
+
+/// Checks that a list of values, such as a message body, matches `signature`
+/// one-to-one.
+pub fn check_signature(values: &[Value], signature: &Signature) -> result::Result<(), TypeError> {
+    if values.len() != signature.len() {
+        return Err(TypeError::new(TypeErrorKind::Arity {
+            expected: signature.len(),
+            found: values.len(),
+        }));
+    }
+    for (i, (value, ty)) in values.iter().zip(signature).enumerate() {
+        value.check_type(ty).map_err(|err| err.at(PathSegment::Arg(i)))?;
+    }
+    Ok(())
 }
@@ -4,10 +4,15 @@
 
 #![feature(conservative_impl_trait)]
 
+#[macro_use]
 extern crate futures;
 extern crate libc;
 #[macro_use]
 extern crate nom;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde_json")]
+extern crate serde_json;
 extern crate tokio_core;
 extern crate tokio_uds;
 
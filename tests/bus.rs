@@ -1,11 +1,11 @@
 extern crate tokio_dbus;
 
-use tokio_dbus::{BasicType, ContainerType, Type};
+use tokio_dbus::{BasicType, ContainerType, Signature, Type};
 
 #[test]
 fn test() {
     let header_sig_enc = b"yyyyuua(yv)";
-    let header_sig_ast = vec!(Type::BasicType(BasicType::Byte),
+    let header_sig_ast = Signature::from(vec!(Type::BasicType(BasicType::Byte),
              Type::BasicType(BasicType::Byte),
              Type::BasicType(BasicType::Byte),
              Type::BasicType(BasicType::Byte),
@@ -16,7 +16,7 @@ fn test() {
                      Type::ContainerType(Box::new(
                          ContainerType::Struct(
                              vec!(Type::BasicType(BasicType::Byte),
-                                  Type::ContainerType(Box::new(ContainerType::Variant))))))))));
+                                  Type::ContainerType(Box::new(ContainerType::Variant)))))))))));
 
     let mut buf: Vec<u8> = vec![];
     tokio_dbus::encode_signature(&header_sig_ast, &mut buf);
@@ -0,0 +1,526 @@
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_dbus;
+extern crate tokio_uds;
+
+use futures::{future, Future, Sink, Stream};
+use tokio_core::reactor::Core;
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+use tokio_dbus::{Bus, BusName, DBusError, FromDBus, InterfaceName, MatchRule, MemberName,
+                 Message, MessageType, ObjectPath, PropertyMap, StandardError, Supervisor, ToDBus,
+                 Variant, NAME_DO_NOT_QUEUE, NO_REPLY_EXPECTED};
+use tokio_uds::UnixStream;
+
+// Plays the bus daemon's part of `Hello`: receives the call, sends a signal
+// ahead of the reply, then answers with `reply`.
+fn daemon<F>(daemon: Bus, reply: F) -> Box<Future<Item = Bus, Error = std::io::Error>>
+    where F: FnOnce(&Message) -> Message + 'static
+{
+    Box::new(daemon.into_future()
+        .map_err(|(err, _)| err)
+        .and_then(move |(call, daemon)| {
+            let call = call.unwrap();
+            assert_eq!(call.message_type, MessageType::MethodCall);
+            assert_eq!(call.fields.member, Some("Hello".to_owned()));
+            assert_eq!(call.fields.destination, Some("org.freedesktop.DBus".to_owned()));
+            let signal = Message::signal("/org/freedesktop/DBus",
+                                         "org.freedesktop.DBus",
+                                         "NameAcquired");
+            let reply = reply(&call);
+            daemon.send(signal).and_then(|daemon| daemon.send(reply))
+        }))
+}
+
+#[test]
+fn test_hello() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (client, server) = (Bus::new(a), Bus::new(b));
+    assert!(client.unique_name().is_none());
+
+    let server = daemon(server, |call| {
+        let mut reply = Message::method_return(call);
+        reply.set_body(&[":1.42".to_dbus()]).unwrap();
+        reply
+    });
+    let (client, _server) = l.run(client.hello().join(server)).unwrap();
+    assert_eq!(client.unique_name().unwrap().as_str(), ":1.42");
+
+    // The signal that came before the reply is still delivered.
+    let (signal, _) = l.run(client.into_future().map_err(|(err, _)| err)).unwrap();
+    assert_eq!(signal.unwrap().fields.member, Some("NameAcquired".to_owned()));
+}
+
+#[test]
+fn test_hello_refused() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (client, server) = (Bus::new(a), Bus::new(b));
+
+    let server = daemon(server, |call| {
+        let mut reply = Message::error(call, "org.freedesktop.DBus.Error.LimitsExceeded");
+        reply.set_body(&["Too many connections".to_dbus()]).unwrap();
+        reply
+    });
+    let err = DBusError::from(l.run(client.hello().join(server)).err().unwrap());
+    assert_eq!(err.standard_kind(), Some(StandardError::LimitsExceeded));
+    assert_eq!(err.message(), Some("Too many connections"));
+}
+
+#[test]
+fn test_hello_disconnected() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    drop(b);
+    assert!(l.run(Bus::new(a).hello()).is_err());
+}
+
+#[test]
+fn test_calls() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (mut client, server) = (Bus::new(a), Bus::new(b));
+
+    let calls = (0..3u32)
+        .map(|i| {
+            client.call("org.example.Service",
+                        "/org/example/Object",
+                        "org.example.Interface",
+                        "Double",
+                        &[Variant::new(i)])
+        })
+        .collect::<Vec<_>>();
+
+    // Drive the client, keeping whatever isn't a reply.
+    let (others, others_out) = futures::sync::oneshot::channel();
+    handle.spawn(client.collect().then(|result| {
+        let _ = others.send(result.unwrap());
+        Ok(())
+    }));
+
+    // Answer in reverse order, after an unrelated signal, failing the first.
+    let mut server = server;
+    let received = l.run(server.by_ref().take(3).collect()).unwrap();
+    let mut replies = vec![Message::signal("/org/example/Object",
+                                           "org.example.Interface",
+                                           "Changed")];
+    for call in received.iter().rev() {
+        assert_eq!(call.fields.member, Some("Double".to_owned()));
+        let arg = u32::from_dbus(call.body().unwrap().remove(0)).unwrap();
+        let mut reply = if arg == 0 {
+            Message::error(call, "org.example.Error.Zero")
+        } else {
+            Message::method_return(call)
+        };
+        let body = if arg == 0 { "zero".to_dbus() } else { (arg * 2).to_dbus() };
+        reply.set_body(&[body]).unwrap();
+        replies.push(reply);
+    }
+    let replies = futures::stream::iter_ok::<_, std::io::Error>(replies);
+    let server = l.run(server.send_all(replies)).unwrap().0;
+
+    let mut calls = calls.into_iter();
+    let err = l.run(calls.next().unwrap()).err().unwrap();
+    assert_eq!(err.name().as_str(), "org.example.Error.Zero");
+    assert_eq!(err.message(), Some("zero"));
+    for i in 1..3u32 {
+        let body = l.run(calls.next().unwrap()).unwrap();
+        assert_eq!(body, vec![(i * 2).to_dbus()]);
+    }
+
+    drop(server);
+    let others = l.run(others_out).unwrap();
+    assert_eq!(others.len(), 1);
+    assert_eq!(others[0].fields.member, Some("Changed".to_owned()));
+}
+
+#[test]
+fn test_call_with_empty_array() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (mut client, server) = (Bus::new(a), Bus::new(b));
+
+    let args = [Variant::new(Vec::<String>::new()), Variant::new(PropertyMap::new())];
+    let _call = client.call("org.example.App", "/", "org.example.I", "M", &args);
+    handle.spawn(client.for_each(|_| Ok(())).map_err(|_| ()));
+    let (call, _) = l.run(server.into_future().map_err(|(err, _)| err)).unwrap();
+    let call = call.unwrap();
+    assert_eq!(call.signature().to_string(), "asa{sv}");
+    assert_eq!(call.body().unwrap(),
+               vec![Vec::<String>::new().to_dbus(), PropertyMap::new().to_dbus()]);
+}
+
+// Receives the next call to the bus, returning its member and argument.
+fn bus_call(l: &mut Core, server: &mut Bus) -> (Message, String, String) {
+    let call = l.run(server.by_ref().take(1).collect()).unwrap().remove(0);
+    assert_eq!(call.fields.destination, Some("org.freedesktop.DBus".to_owned()));
+    let member = call.fields.member.clone().unwrap();
+    let arg = String::from_dbus(call.body().unwrap().remove(0)).unwrap();
+    (call, member, arg)
+}
+
+#[test]
+fn test_subscribe() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (mut client, mut server) = (Bus::new(a), Bus::new(b));
+
+    let rule = |member| {
+        MatchRule::new()
+            .interface(InterfaceName::new("org.example.Interface").unwrap())
+            .member(MemberName::new(member).unwrap())
+    };
+    let changed = "type='signal',interface='org.example.Interface',member='Changed'";
+    let removed = "type='signal',interface='org.example.Interface',member='Removed'";
+    let mut first = client.subscribe(rule("Changed"));
+    let mut second = client.subscribe(rule("Changed"));
+    let mut third = client.subscribe(rule("Removed"));
+
+    let (others, others_out) = futures::sync::oneshot::channel();
+    handle.spawn(client.collect().then(|result| {
+        let _ = others.send(result.unwrap());
+        Ok(())
+    }));
+
+    // Identical rules are only added once.
+    let mut replies = Vec::new();
+    let mut added = Vec::new();
+    for _ in 0..2 {
+        let (call, member, arg) = bus_call(&mut l, &mut server);
+        assert_eq!(member, "AddMatch");
+        added.push(arg);
+        replies.push(Message::method_return(&call));
+    }
+    assert_eq!(added, vec![changed.to_owned(), removed.to_owned()]);
+    for member in &["Changed", "Removed", "Unrelated"] {
+        replies.push(Message::signal("/org/example/Object", "org.example.Interface", *member));
+    }
+    let replies = futures::stream::iter_ok::<_, std::io::Error>(replies);
+    let mut server = l.run(server.send_all(replies)).unwrap().0;
+
+    let member = |msgs: Vec<Message>| msgs[0].fields.member.clone().unwrap();
+    assert_eq!(member(l.run(first.by_ref().take(1).collect()).unwrap()), "Changed");
+    assert_eq!(member(l.run(second.by_ref().take(1).collect()).unwrap()), "Changed");
+    assert_eq!(member(l.run(third.by_ref().take(1).collect()).unwrap()), "Removed");
+
+    // A shared rule is removed with its last subscriber.
+    drop(first);
+    drop(second);
+    let (_, member, arg) = bus_call(&mut l, &mut server);
+    assert_eq!((member.as_str(), arg.as_str()), ("RemoveMatch", changed));
+    drop(third);
+    let (_, member, arg) = bus_call(&mut l, &mut server);
+    assert_eq!((member.as_str(), arg.as_str()), ("RemoveMatch", removed));
+
+    drop(server);
+    let others = l.run(others_out).unwrap();
+    assert_eq!(others.len(), 1);
+    assert_eq!(others[0].fields.member, Some("Unrelated".to_owned()));
+}
+
+#[test]
+fn test_subscribe_well_known_sender() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let server = daemon(Bus::new(b), |call| {
+        let mut reply = Message::method_return(call);
+        reply.set_body(&[":1.42".to_dbus()]).unwrap();
+        reply
+    });
+    let (mut client, mut server) = l.run(Bus::new(a).hello().join(server)).unwrap();
+
+    let rule = MatchRule::new().sender(BusName::new("org.example.Service").unwrap());
+    let signals = client.subscribe(rule);
+    handle.spawn(client.for_each(|_| Ok(())).map_err(|_| ()));
+    let (call, member, _) = bus_call(&mut l, &mut server);
+    assert_eq!(member, "AddMatch");
+
+    // The bus routes the service's signals with its unique name.
+    let mut signal = Message::signal("/", "org.example.Interface", "Changed");
+    signal.fields.sender = Some(":1.7".to_owned());
+    let replies = vec![Message::method_return(&call), signal];
+    let replies = futures::stream::iter_ok::<_, std::io::Error>(replies);
+    let _server = l.run(server.send_all(replies)).unwrap().0;
+    let (signal, _) = l.run(signals.into_future().map_err(|(err, _)| err)).unwrap();
+    assert_eq!(signal.unwrap().fields.sender, Some(":1.7".to_owned()));
+}
+
+#[test]
+fn test_call_timeout() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (mut client, mut server) = (Bus::new(a), Bus::new(b));
+    client.set_handle(&handle);
+
+    let call = |client: &mut Bus| client.call("org.example.App", "/", "org.example.I", "M", &[]);
+    let slow = call(&mut client).timeout(Some(Duration::from_millis(20)));
+    let dropped = call(&mut client);
+    let (others, others_out) = futures::sync::oneshot::channel();
+    handle.spawn(client.collect().then(|result| {
+        let _ = others.send(result.unwrap());
+        Ok(())
+    }));
+
+    let received = l.run(server.by_ref().take(2).collect()).unwrap();
+    let err = l.run(slow).err().unwrap();
+    assert_eq!(err.standard_kind(), Some(StandardError::NoReply));
+    drop(dropped);
+
+    // Replies that come too late are thrown away.
+    let mut replies = received.iter().map(Message::method_return).collect::<Vec<_>>();
+    replies.push(Message::signal("/", "org.example.I", "Changed"));
+    let replies = futures::stream::iter_ok::<_, std::io::Error>(replies);
+    let server = l.run(server.send_all(replies)).unwrap().0;
+    drop(server);
+    let others = l.run(others_out).unwrap();
+    assert_eq!(others.len(), 1);
+    assert_eq!(others[0].message_type, MessageType::Signal);
+}
+
+#[test]
+fn test_call_message_no_reply() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (mut client, server) = (Bus::new(a), Bus::new(b));
+    client.set_handle(&handle);
+
+    // A call that asks for no reply doesn't wait for one.
+    let mut msg = Message::method_call("/", "M");
+    msg.fields.interface = Some("org.example.I".to_owned());
+    msg.set_flag(NO_REPLY_EXPECTED, true);
+    let call = client.call_message(msg).timeout(Some(Duration::from_millis(20)));
+    assert_eq!(l.run(call).unwrap(), vec![]);
+
+    handle.spawn(client.for_each(|_| Ok(())).map_err(|_| ()));
+    let (received, _) = l.run(server.into_future().map_err(|(err, _)| err)).unwrap();
+    let received = received.unwrap();
+    assert_eq!(received.fields.member, Some("M".to_owned()));
+    assert!(received.has_flag(NO_REPLY_EXPECTED));
+}
+
+#[test]
+fn test_abandoned_calls_forgotten() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (mut client, mut server) = (Bus::new(a), Bus::new(b));
+
+    // Only the last 1024 calls given up on are remembered.
+    let calls = (0..1025)
+        .map(|_| client.call("org.example.App", "/", "org.example.I", "M", &[]))
+        .collect::<Vec<_>>();
+    drop(calls);
+    let (others, others_out) = futures::sync::oneshot::channel();
+    handle.spawn(client.collect().then(|result| {
+        let _ = others.send(result.unwrap());
+        Ok(())
+    }));
+
+    let received = l.run(server.by_ref().take(1025).collect()).unwrap();
+    let replies = received.iter().map(Message::method_return).collect::<Vec<_>>();
+    let replies = futures::stream::iter_ok::<_, std::io::Error>(replies);
+    let server = l.run(server.send_all(replies)).unwrap().0;
+    drop(server);
+    let others = l.run(others_out).unwrap();
+    assert_eq!(others.len(), 1);
+    assert_eq!(others[0].fields.reply_serial, Some(received[0].serial));
+}
+
+#[test]
+fn test_shared() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (client, mut server) = (Bus::new(a), Bus::new(b));
+    let (bus, driver) = client.into_shared();
+    let (done, done_out) = futures::sync::oneshot::channel();
+    handle.spawn(driver.then(|result| {
+        let _ = done.send(result.is_ok());
+        Ok(())
+    }));
+
+    // One part of the program subscribes while another calls and sends.
+    let signals = bus.clone().subscribe(MatchRule::new());
+    let other = bus.clone();
+    let call = other.call("org.example.App", "/", "org.example.I", "Ping", &[]);
+    let serial = other.send(Message::signal("/", "org.example.I", "Sent"));
+    drop(other);
+
+    let received = l.run(server.by_ref().take(3).collect()).unwrap();
+    let members = received.iter().map(|msg| msg.fields.member.clone().unwrap()).collect::<Vec<_>>();
+    assert_eq!(members, vec!["AddMatch", "Ping", "Sent"]);
+    assert_eq!(received[2].serial, serial);
+
+    let mut reply = Message::method_return(&received[1]);
+    reply.set_body(&["pong".to_dbus()]).unwrap();
+    let replies = vec![Message::method_return(&received[0]),
+                       reply,
+                       Message::signal("/", "org.example.I", "Changed")];
+    let replies = futures::stream::iter_ok::<_, std::io::Error>(replies);
+    let mut server = l.run(server.send_all(replies)).unwrap().0;
+    assert_eq!(l.run(call).unwrap(), vec!["pong".to_dbus()]);
+    let (signal, signals) = l.run(signals.into_future().map_err(|(err, _)| err)).unwrap();
+    assert_eq!(signal.unwrap().fields.member, Some("Changed".to_owned()));
+
+    // The driver finishes once nothing is left to do.
+    drop(signals);
+    drop(bus);
+    let received = l.run(server.by_ref().take(1).collect()).unwrap();
+    assert_eq!(received[0].fields.member, Some("RemoveMatch".to_owned()));
+    assert!(l.run(done_out).unwrap());
+}
+
+#[test]
+fn test_disconnected() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (client, mut server) = (Bus::new(a), Bus::new(b));
+    let bus = client.spawn(&handle);
+    let closed = bus.closed();
+    let call = bus.call("org.example.App", "/", "org.example.I", "M", &[]);
+    let signals = bus.subscribe(MatchRule::new());
+
+    l.run(server.by_ref().take(2).collect()).unwrap();
+    drop(server);
+    let err = l.run(call).err().unwrap();
+    assert_eq!(err.standard_kind(), Some(StandardError::Disconnected));
+    assert!(l.run(signals.collect()).unwrap().is_empty());
+    l.run(closed).unwrap();
+    assert!(!bus.is_connected());
+
+    // Later calls fail at once.
+    let err = l.run(bus.call("org.example.App", "/", "org.example.I", "M", &[])).err().unwrap();
+    assert_eq!(err.standard_kind(), Some(StandardError::Disconnected));
+    assert!(l.run(bus.subscribe(MatchRule::new()).collect()).unwrap().is_empty());
+}
+
+#[test]
+fn test_local_disconnected() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (mut client, server) = (Bus::new(a), Bus::new(b));
+    let closed = client.closed();
+    let signal = Message::signal("/org/freedesktop/DBus/Local",
+                                 "org.freedesktop.DBus.Local",
+                                 "Disconnected");
+    let _server = l.run(server.send(signal)).unwrap();
+    assert!(l.run(client.by_ref().collect()).unwrap().is_empty());
+    assert!(!client.is_connected());
+    l.run(closed).unwrap();
+}
+
+#[test]
+fn test_close() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (mut client, server) = (Bus::new(a), Bus::new(b));
+    for member in &["A", "B"] {
+        client.call_no_reply("org.example.App", "/", "org.example.I", *member, &[]).unwrap();
+    }
+    let call = client.call("org.example.App", "/", "org.example.I", "C", &[]);
+
+    // Everything queued is written before the connection goes.
+    l.run(client.close()).unwrap();
+    let received = l.run(server.collect()).unwrap();
+    assert_eq!(received.len(), 3);
+    assert_eq!(received[2].fields.member, Some("C".to_owned()));
+    assert!(l.run(call).is_err());
+
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (client, server) = (Bus::new(a), Bus::new(b));
+    let bus = client.spawn(&handle);
+    bus.send(Message::signal("/", "org.example.I", "Bye"));
+    l.run(bus.close()).unwrap();
+    let received = l.run(server.collect()).unwrap();
+    assert_eq!(received.len(), 1);
+    assert!(!bus.is_connected());
+}
+
+// Answers `Hello` with `name`.
+fn hello_reply(name: &'static str) -> impl Fn(&Message) -> Message {
+    move |call| {
+        let mut reply = Message::method_return(call);
+        reply.set_body(&[name.to_dbus()]).unwrap();
+        reply
+    }
+}
+
+#[test]
+fn test_supervisor() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (c, d) = UnixStream::pair(&handle).unwrap();
+    let server = daemon(Bus::new(b), hello_reply(":1.1"));
+    let (client, mut server) = l.run(Bus::new(a).hello().join(server)).unwrap();
+
+    // The first attempt to reconnect fails.
+    let mut attempts = vec![Some(c), None];
+    let (bus, mut supervisor) = Supervisor::new(client, &handle, move || {
+        future::result(match attempts.pop().unwrap() {
+            Some(stream) => Ok(Bus::new(stream)),
+            None => Err(Error::new(ErrorKind::ConnectionRefused, "no bus")),
+        })
+    });
+    supervisor.set_backoff(Duration::from_millis(10), Duration::from_millis(20));
+    handle.spawn(supervisor.map_err(|_| ()));
+
+    bus.export(ObjectPath::new("/org/example/Obj").unwrap(),
+               |_| Ok(vec!["pong".to_dbus()]));
+    let app = BusName::new("org.example.App").unwrap();
+    let request = bus.request_name(app, NAME_DO_NOT_QUEUE);
+    let rule = MatchRule::new().member(MemberName::new("Changed").unwrap());
+    let signals = bus.subscribe(rule);
+    let reconnects = bus.reconnects();
+    let received = l.run(server.by_ref().take(2).collect()).unwrap();
+    let mut granted = Message::method_return(&received[0]);
+    granted.set_body(&[1u32.to_dbus()]).unwrap();
+    let replies = vec![granted, Message::method_return(&received[1])];
+    let replies = futures::stream::iter_ok::<_, Error>(replies);
+    let server = l.run(server.send_all(replies)).unwrap().0;
+    assert_eq!(l.run(request).unwrap(), vec![1u32.to_dbus()]);
+
+    // The bus restarts.
+    drop(server);
+    let mut server = l.run(daemon(Bus::new(d), hello_reply(":1.2"))).unwrap();
+    let received = l.run(server.by_ref().take(2).collect()).unwrap();
+    assert_eq!(received[0].fields.member, Some("AddMatch".to_owned()));
+    assert_eq!(received[0].body().unwrap(),
+               vec!["type='signal',member='Changed'".to_dbus()]);
+    assert_eq!(received[1].fields.member, Some("RequestName".to_owned()));
+    assert_eq!(received[1].body().unwrap(),
+               vec!["org.example.App".to_dbus(), NAME_DO_NOT_QUEUE.to_dbus()]);
+    assert_eq!(bus.unique_name().unwrap().as_str(), ":1.2");
+    assert!(bus.is_connected());
+
+    // The new name is told of apart from signals, whose streams carry on.
+    let (event, reconnects) = l.run(reconnects.into_future().map_err(|(err, _)| err)).unwrap();
+    let event = event.unwrap();
+    assert_eq!(event.old_name.unwrap().as_str(), ":1.1");
+    assert_eq!(event.new_name.unwrap().as_str(), ":1.2");
+    let messages = vec![Message::signal("/", "org.example.I", "Changed"),
+                        Message::method_call("/org/example/Obj", "Ping")];
+    let messages = futures::stream::iter_ok::<_, Error>(messages);
+    let mut server = l.run(server.send_all(messages)).unwrap().0;
+    let (signal, _signals) = l.run(signals.into_future().map_err(|(err, _)| err)).unwrap();
+    assert_eq!(signal.unwrap().fields.member, Some("Changed".to_owned()));
+    let reply = l.run(server.by_ref().take(1).collect()).unwrap();
+    assert_eq!(reply[0].body().unwrap(), vec!["pong".to_dbus()]);
+
+    l.run(bus.close()).unwrap();
+    assert!(l.run(server.collect()).unwrap().is_empty());
+    assert!(l.run(reconnects.collect()).unwrap().is_empty());
+}
@@ -0,0 +1,93 @@
+extern crate tokio_dbus;
+
+use std::collections::{BTreeMap, HashMap};
+use tokio_dbus::{BasicValue, BusName, DBusType, FromDBus, InterfaceName, MemberName, ObjectPath,
+                 PathSegment, Signature, ToDBus, TypeErrorKind, Value};
+
+fn round_trip<T: ToDBus + FromDBus>(value: &T) -> T {
+    let dbus_value = value.to_dbus();
+    dbus_value.check_type(&T::dbus_type()).unwrap();
+    T::from_dbus(dbus_value).unwrap()
+}
+
+#[test]
+fn test_signatures() {
+    assert_eq!(u8::signature().to_string(), "y");
+    assert_eq!(f64::signature().to_string(), "d");
+    assert_eq!(<&str>::signature().to_string(), "s");
+    assert_eq!(ObjectPath::signature().to_string(), "o");
+    assert_eq!(InterfaceName::signature().to_string(), "s");
+    assert_eq!(Vec::<Vec<i64>>::signature().to_string(), "aax");
+    assert_eq!(HashMap::<String, Vec<u8>>::signature().to_string(), "a{say}");
+    assert_eq!(BTreeMap::<u32, (bool, String)>::signature().to_string(), "a{u(bs)}");
+    assert_eq!(<(u8, u16, u32, u64, i16, i32, i64, f64, bool, String, ObjectPath, Signature)>::
+                   signature()
+                   .to_string(),
+               "(yqutnixdbsog)");
+}
+
+#[test]
+fn test_round_trip() {
+    assert_eq!(round_trip(&-7i16), -7);
+    assert_eq!(round_trip(&u64::max_value()), u64::max_value());
+    assert_eq!(round_trip(&true), true);
+    assert_eq!(round_trip(&"hello".to_owned()), "hello");
+    assert_eq!(round_trip(&vec![vec![1u8, 2], vec![]]), vec![vec![1u8, 2], vec![]]);
+
+    let mut map = HashMap::new();
+    map.insert("a".to_owned(), vec![1u32]);
+    map.insert("b".to_owned(), vec![]);
+    assert_eq!(round_trip(&map), map);
+
+    let mut tree = BTreeMap::new();
+    tree.insert(ObjectPath::new("/org/example").unwrap(), (1u8, 2.5f64));
+    assert_eq!(round_trip(&tree), tree);
+
+    let tuple = (1u8, 2i16, 3u16, 4i32, 5u32, 6i64, 7u64, 8.0f64, true, "x".to_owned(),
+                 BusName::new(":1.42").unwrap(), MemberName::new("Hello").unwrap());
+    assert_eq!(round_trip(&tuple), tuple);
+}
+
+#[test]
+fn test_errors() {
+    let err = u32::from_dbus(Value::BasicValue(BasicValue::Int32(1))).unwrap_err();
+    assert_eq!(err.to_string(), "Expected a value of type `u`, found an int32.");
+
+    let err = Vec::<(u8, String)>::from_dbus(vec![("a", 1u8)].to_dbus()).unwrap_err();
+    assert_eq!(err.path, vec![PathSegment::Element(0), PathSegment::Field(0)]);
+    assert_eq!(err.to_string(),
+               "Expected a value of type `y`, found a string at element 0, field 0.");
+
+    let err = InterfaceName::from_dbus("NoDots".to_dbus()).unwrap_err();
+    assert_eq!(err.kind,
+               TypeErrorKind::Invalid("\"NoDots\" is not a valid D-Bus interface name"
+                   .to_owned()));
+}
+
+#[test]
+fn test_names() {
+    assert!(ObjectPath::new("/").is_ok());
+    assert!(ObjectPath::new("/org/freedesktop/DBus").is_ok());
+    assert!(ObjectPath::new("").is_err());
+    assert!(ObjectPath::new("/org/").is_err());
+    assert!(ObjectPath::new("/org//DBus").is_err());
+    assert!(ObjectPath::new("/org/free-desktop").is_err());
+
+    assert!(InterfaceName::new("org.freedesktop.DBus").is_ok());
+    assert!(InterfaceName::new("org").is_err());
+    assert!(InterfaceName::new("org.1freedesktop").is_err());
+    assert!(InterfaceName::new("org..DBus").is_err());
+    assert!(InterfaceName::new(format!("a.{}", "b".repeat(254))).is_err());
+
+    assert!(MemberName::new("GetAll").is_ok());
+    assert!(MemberName::new("Get.All").is_err());
+    assert!(MemberName::new("2Get").is_err());
+
+    assert!(BusName::new(":1.42").is_ok());
+    assert!(BusName::new("org.example-app.Service").is_ok());
+    assert!(BusName::new("org.1example").is_err());
+    assert!(BusName::new(":1").is_err());
+
+    let err = MemberName::new("").unwrap_err();
+    assert_eq!(err.to_string(), "\"\" is not a valid D-Bus member name.");
+}
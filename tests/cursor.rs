@@ -0,0 +1,100 @@
+extern crate tokio_dbus;
+
+use std::collections::BTreeMap;
+use tokio_dbus::{BasicValueRef, Endianness, MAX_ARRAY_LEN, Signature, ToDBus, Type, Value,
+                 ValueCursor, ValueRef, encode_values};
+
+fn encode(values: &[Value], signature: &Signature) -> Vec<u8> {
+    let mut buf = Vec::new();
+    encode_values(values, signature, Endianness::Little, &mut buf).unwrap();
+    buf
+}
+
+fn cursor<'a>(bytes: &'a [u8], signature: &Signature) -> ValueCursor<'a, 'a> {
+    ValueCursor::new(bytes, signature, Endianness::Little, MAX_ARRAY_LEN)
+}
+
+#[test]
+fn test_walk() {
+    let signature: Signature = "sa(sut)a{sv}".parse().unwrap();
+    let entries = (0..1000u32).map(|i| (i.to_string(), i, 0u64)).collect::<Vec<_>>();
+    let mut props = BTreeMap::new();
+    props.insert("A".to_owned(), tokio_dbus::Variant::new(1u8));
+    props.insert("B".to_owned(), tokio_dbus::Variant::new("b"));
+    let bytes = encode(&["head".to_dbus(), entries.to_dbus(), props.to_dbus()], &signature);
+
+    let mut body = cursor(&bytes, &signature);
+    assert_eq!(body.peek_type(), Some(&"s".parse::<Type>().unwrap()));
+    assert_eq!(body.next_value().unwrap(),
+               Some(ValueRef::BasicValue(BasicValueRef::String("head"))));
+
+    // Pull the middle field of each entry, skipping the rest.
+    let mut sum = 0;
+    {
+        let mut array = body.enter().unwrap().unwrap();
+        while let Some(mut entry) = array.enter().unwrap() {
+            assert!(entry.skip().unwrap());
+            sum += entry.read::<u32>().unwrap().unwrap();
+        }
+        assert!(array.next_value().unwrap().is_none());
+    }
+    assert_eq!(sum, 999 * 1000 / 2);
+
+    {
+        let mut dict = body.enter().unwrap().unwrap();
+        let mut entry = dict.enter().unwrap().unwrap();
+        assert_eq!(entry.read::<String>().unwrap(), Some("A".to_owned()));
+        let mut variant = entry.enter().unwrap().unwrap();
+        assert_eq!(variant.read::<u8>().unwrap(), Some(1));
+        assert!(variant.read::<u8>().unwrap().is_none());
+        // The remaining entry is skipped when the cursors are dropped.
+    }
+    assert!(body.peek_type().is_none());
+    body.finish().unwrap();
+}
+
+#[test]
+fn test_abandoned() {
+    let signature: Signature = "aasu".parse().unwrap();
+    let bytes = encode(&[vec![vec!["x", "y"], vec![]].to_dbus(), 7u32.to_dbus()], &signature);
+    let mut body = cursor(&bytes, &signature);
+    {
+        let mut outer = body.enter().unwrap().unwrap();
+        let mut inner = outer.enter().unwrap().unwrap();
+        assert_eq!(inner.read::<String>().unwrap(), Some("x".to_owned()));
+    }
+    assert_eq!(body.read::<u32>().unwrap(), Some(7));
+    assert!(body.enter().unwrap().is_none());
+
+    let mut body = cursor(&bytes, &signature);
+    body.skip().unwrap();
+    assert!(body.enter().is_err());
+    assert_eq!(body.read::<u32>().unwrap(), Some(7));
+}
+
+#[test]
+fn test_errors() {
+    let signature: Signature = "asu".parse().unwrap();
+    let mut bytes = encode(&[vec!["x"].to_dbus(), 1u32.to_dbus()], &signature);
+
+    // Trailing bytes are only caught by `finish`.
+    bytes.extend_from_slice(&[0; 4]);
+    let mut body = cursor(&bytes, &signature);
+    body.skip().unwrap();
+    body.skip().unwrap();
+    assert!(body.finish().is_err());
+
+    // A bad string in a skipped part of an array fails the parent cursor.
+    bytes.truncate(bytes.len() - 4);
+    bytes[9] = 0xff;
+    let mut body = cursor(&bytes, &signature);
+    drop(body.enter().unwrap().unwrap());
+    assert!(body.skip().is_err());
+    assert!(body.skip().is_err());
+
+    // A value of the wrong type is still stepped over.
+    let bytes = encode(&[vec!["x"].to_dbus(), 1u32.to_dbus()], &signature);
+    let mut body = cursor(&bytes, &signature);
+    assert!(body.read::<u32>().is_err());
+    assert_eq!(body.read::<u32>().unwrap(), Some(1));
+}
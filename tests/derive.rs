@@ -0,0 +1,230 @@
+#[macro_use]
+extern crate tokio_dbus;
+#[macro_use]
+extern crate tokio_dbus_derive;
+
+use tokio_dbus::{BasicValue, ContainerValue, DBusType, FromDBus, ObjectPath, PathSegment, ToDBus,
+                 TypeErrorKind, Value};
+
+#[derive(Debug, DBusType, FromDBus, PartialEq, ToDBus)]
+struct Point {
+    x: i32,
+    y: i32,
+    #[dbus(skip)]
+    cached_norm: Option<f64>,
+}
+
+#[derive(Debug, DBusType, FromDBus, PartialEq, ToDBus)]
+struct Pair<T>(T, String);
+
+#[derive(Debug, DBusType, FromDBus, PartialEq, ToDBus)]
+#[dbus(dict)]
+struct Device {
+    #[dbus(rename = "Path")]
+    path: ObjectPath,
+    #[dbus(rename = "Points")]
+    points: Vec<Point>,
+    #[dbus(rename = "Label", skip_if_none)]
+    label: Option<String>,
+    #[dbus(skip)]
+    dirty: bool,
+}
+
+fn device(label: Option<&str>) -> Device {
+    Device {
+        path: ObjectPath::new("/dev/0").unwrap(),
+        points: vec![Point {
+                         x: 1,
+                         y: -1,
+                         cached_norm: None,
+                     }],
+        label: label.map(ToOwned::to_owned),
+        dirty: false,
+    }
+}
+
+fn variant(value: Value) -> Value {
+    value.into_variant().unwrap()
+}
+
+#[test]
+fn test_struct() {
+    assert_eq!(Point::signature().to_string(), "(ii)");
+    assert_eq!(Pair::<Vec<u8>>::signature().to_string(), "(ays)");
+
+    let point = Point {
+        x: 3,
+        y: 4,
+        cached_norm: Some(5.0),
+    };
+    assert_eq!(point.to_dbus(),
+               Value::ContainerValue(ContainerValue::Struct(vec![
+                   Value::BasicValue(BasicValue::Int32(3)),
+                   Value::BasicValue(BasicValue::Int32(4)),
+               ])));
+    assert_eq!(Point::from_dbus(point.to_dbus()).unwrap(),
+               Point {
+                   x: 3,
+                   y: 4,
+                   cached_norm: None,
+               });
+
+    let pair = Pair(7u16, "seven".to_owned());
+    assert_eq!(Pair::from_dbus(pair.to_dbus()).unwrap(), pair);
+}
+
+#[test]
+fn test_dict() {
+    assert_eq!(Device::signature().to_string(), "a{sv}");
+
+    let value = device(None).to_dbus();
+    value.check_type(&Device::dbus_type()).unwrap();
+    match value {
+        Value::ContainerValue(ContainerValue::Dict(ref entries)) => {
+            let keys = entries.iter().map(|entry| entry.0.clone()).collect::<Vec<_>>();
+            assert_eq!(keys,
+                       vec![BasicValue::String("Path".into()),
+                            BasicValue::String("Points".into())]);
+        }
+        _ => panic!("expected a dict"),
+    }
+    assert_eq!(Device::from_dbus(value).unwrap(), device(None));
+
+    let labelled = device(Some("eth0"));
+    assert_eq!(Device::from_dbus(labelled.to_dbus()).unwrap(), labelled);
+}
+
+#[test]
+fn test_errors() {
+    let value = Value::ContainerValue(ContainerValue::Dict(vec![
+        (BasicValue::String("Path".into()),
+         variant(Value::BasicValue(BasicValue::String("/dev/0".into())))),
+    ]));
+    let err = Device::from_dbus(value).unwrap_err();
+    assert_eq!(err.path, vec![PathSegment::Value(0), PathSegment::Variant]);
+
+    let value = Value::ContainerValue(ContainerValue::Dict(vec![
+        (BasicValue::String("Path".into()),
+         variant(ObjectPath::new("/").unwrap().to_dbus())),
+    ]));
+    let err = Device::from_dbus(value).unwrap_err();
+    assert_eq!(err.kind, TypeErrorKind::Invalid("Missing property `Points`".to_owned()));
+
+    let err = Point::from_dbus((1i32, 2u32).to_dbus()).unwrap_err();
+    assert_eq!(err.to_string(), "Expected a value of type `i`, found a uint32 at field 1.");
+}
+
+#[derive(Clone, Copy, Debug, DBusType, Eq, FromDBus, Hash, PartialEq, ToDBus)]
+enum Level {
+    Low,
+    High = 10,
+    Higher,
+    #[dbus(value = 100)]
+    Max,
+}
+
+#[derive(Debug, DBusType, FromDBus, PartialEq, ToDBus)]
+#[dbus(repr = "s")]
+enum Mode {
+    #[dbus(value = "read-only")]
+    ReadOnly,
+    ReadWrite,
+}
+
+#[derive(Debug, DBusType, FromDBus, PartialEq, ToDBus)]
+enum Event {
+    Reset,
+    Moved(Point),
+    Resized(u32, u32),
+    Renamed { from: String, to: String },
+}
+
+#[derive(Debug, DBusType, FromDBus, PartialEq, ToDBus)]
+#[dbus(repr = "u")]
+enum Code {
+    Ok(u8),
+    #[dbus(value = 7)]
+    Err(String),
+}
+
+#[test]
+fn test_c_like_enum() {
+    assert_eq!(Level::signature().to_string(), "u");
+    assert_eq!(Mode::signature().to_string(), "s");
+    assert_eq!(std::collections::HashMap::<Level, bool>::signature().to_string(), "a{ub}");
+
+    assert_eq!(Level::Low.to_dbus(), Value::BasicValue(BasicValue::UInt32(0)));
+    assert_eq!(Level::Higher.to_dbus(), Value::BasicValue(BasicValue::UInt32(11)));
+    assert_eq!(Level::Max.to_dbus(), Value::BasicValue(BasicValue::UInt32(100)));
+    for &level in &[Level::Low, Level::High, Level::Higher, Level::Max] {
+        assert_eq!(Level::from_dbus(level.to_dbus()).unwrap(), level);
+    }
+
+    assert_eq!(Mode::ReadOnly.to_dbus(), "read-only".to_dbus());
+    assert_eq!(Mode::from_dbus("ReadWrite".to_dbus()).unwrap(), Mode::ReadWrite);
+
+    let err = Level::from_dbus(5u32.to_dbus()).unwrap_err();
+    assert_eq!(err.to_string(), "Unknown discriminant 5 for enum `Level`.");
+    let err = Mode::from_dbus("ReadOnly".to_dbus()).unwrap_err();
+    assert_eq!(err.to_string(), "Unknown discriminant \"ReadOnly\" for enum `Mode`.");
+}
+
+#[test]
+fn test_tagged_enum() {
+    assert_eq!(Event::signature().to_string(), "(sv)");
+    assert_eq!(Code::signature().to_string(), "(uv)");
+
+    let events = vec![Event::Reset,
+                      Event::Moved(Point {
+                          x: 1,
+                          y: 2,
+                          cached_norm: None,
+                      }),
+                      Event::Resized(640, 480),
+                      Event::Renamed {
+                          from: "a".to_owned(),
+                          to: "b".to_owned(),
+                      }];
+    for event in events {
+        let value = event.to_dbus();
+        value.check_type(&Event::dbus_type()).unwrap();
+        assert_eq!(Event::from_dbus(value).unwrap(), event);
+    }
+    assert_eq!(Event::Resized(1, 2).to_dbus(),
+               Value::ContainerValue(ContainerValue::Struct(vec![
+                   "Resized".to_dbus(),
+                   variant((1u32, 2u32).to_dbus()),
+               ])));
+
+    assert_eq!(Code::Err("bad".to_owned()).to_dbus(),
+               Value::ContainerValue(ContainerValue::Struct(vec![7u32.to_dbus(),
+                                                                 variant("bad".to_dbus())])));
+    assert_eq!(Code::from_dbus(Code::Ok(3).to_dbus()).unwrap(), Code::Ok(3));
+
+    let value = Value::ContainerValue(ContainerValue::Struct(vec![1u32.to_dbus(),
+                                                                  variant("x".to_dbus())]));
+    let err = Code::from_dbus(value).unwrap_err();
+    assert_eq!(err.to_string(), "Unknown discriminant 1 for enum `Code`.");
+
+    let value = Value::ContainerValue(ContainerValue::Struct(vec!["Resized".to_dbus(),
+                                                                  variant((1u32, "2")
+                                                                      .to_dbus())]));
+    let err = Event::from_dbus(value).unwrap_err();
+    assert_eq!(err.path,
+               vec![PathSegment::Field(1), PathSegment::Variant, PathSegment::Field(1)]);
+}
+
+assert_signature!(Point, "(ii)");
+assert_signature!(Pair<u8>, "(ys)");
+assert_signature!(Device, "a{sv}");
+assert_signature!(Level, "u");
+assert_signature!(Mode, "s");
+assert_signature!(Event, "(sv)");
+assert_signature!(Code, "(uv)");
+
+#[test]
+fn test_const_signatures() {
+    assert_eq!(Point::SIGNATURE.to_type(), Point::dbus_type());
+    assert_eq!(<Vec<Pair<Level>>>::SIGNATURE.as_str(), "a(us)");
+    assert_eq!(Event::SIGNATURE.to_type(), Event::dbus_type());
+}
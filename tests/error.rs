@@ -0,0 +1,60 @@
+extern crate tokio_dbus;
+
+use std::io;
+use tokio_dbus::{AuthError, DBusError, ErrorName, Message, StandardError, ToDBus};
+
+#[test]
+fn test_standard_names() {
+    assert_eq!(StandardError::ServiceUnknown.name(),
+               "org.freedesktop.DBus.Error.ServiceUnknown");
+    assert_eq!(StandardError::from_name("org.freedesktop.DBus.Error.IOError"),
+               Some(StandardError::IoError));
+    assert_eq!(StandardError::from_name("org.example.Error.Failed"), None);
+
+    let err = DBusError::standard(StandardError::UnknownMethod, "No such method.");
+    assert_eq!(err.standard_kind(), Some(StandardError::UnknownMethod));
+    assert_eq!(err.to_string(),
+               "org.freedesktop.DBus.Error.UnknownMethod: No such method.");
+    let custom = DBusError::new(ErrorName::new("org.example.Error.Busy").unwrap(), None);
+    assert_eq!(custom.standard_kind(), None);
+    assert_eq!(custom.to_string(), "org.example.Error.Busy");
+}
+
+#[test]
+fn test_replies() {
+    let call = Message::method_call("/", "Frob");
+    let err = DBusError::standard(StandardError::AccessDenied, "Not yours.");
+    let reply = err.to_reply(&call);
+    assert_eq!(reply.fields.error_name,
+               Some("org.freedesktop.DBus.Error.AccessDenied".to_owned()));
+    assert_eq!(DBusError::from_reply(&reply), err);
+
+    // A first argument that isn't a string isn't a message.
+    let mut reply = Message::error(&call, "org.example.Error.Code");
+    reply.set_body(&[7u32.to_dbus()]).unwrap();
+    assert_eq!(DBusError::from_reply(&reply).message(), None);
+}
+
+#[test]
+fn test_conversions() {
+    let err = DBusError::standard(StandardError::NoReply, "Too slow.");
+    let io_err: io::Error = err.clone().into();
+    assert_eq!(io_err.kind(), io::ErrorKind::TimedOut);
+    assert_eq!(DBusError::from(io_err), err);
+
+    let err = DBusError::from(io::Error::new(io::ErrorKind::UnexpectedEof, "gone"));
+    assert_eq!(err.standard_kind(), Some(StandardError::Disconnected));
+    assert_eq!(err.message(), Some("gone"));
+
+    let err = DBusError::from(AuthError::Rejected { supported_mechanisms: vec![] });
+    assert_eq!(err.standard_kind(), Some(StandardError::AuthFailed));
+    let auth_err: AuthError = DBusError::standard(StandardError::Failed, "No.").into();
+    assert_eq!(DBusError::from(auth_err).message(), Some("No."));
+
+    fn connect_and_call() -> Result<(), DBusError> {
+        Err(AuthError::Rejected { supported_mechanisms: vec![] })?;
+        Err(io::Error::new(io::ErrorKind::Other, "unreachable"))?;
+        Ok(())
+    }
+    assert!(connect_and_call().is_err());
+}
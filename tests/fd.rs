@@ -0,0 +1,172 @@
+extern crate futures;
+extern crate libc;
+extern crate tokio_core;
+extern crate tokio_dbus;
+extern crate tokio_uds;
+
+use futures::{Future, Sink, Stream};
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use tokio_core::reactor::Core;
+use tokio_dbus::{BasicValue, Bus, Message, OwnedFd, Value, encode_message};
+use tokio_uds::UnixStream;
+
+fn pipe() -> (OwnedFd, OwnedFd) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) }
+}
+
+#[test]
+fn test_pass_fd() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (a, b) = (Bus::new(a), Bus::new(b));
+
+    let (read_end, write_end) = pipe();
+    let mut msg = Message::signal("/", "org.example.Pipes", "Opened");
+    let index = msg.attach_fd(write_end);
+    msg.set_body(&[Value::BasicValue(BasicValue::UnixFd(index))]).unwrap();
+
+    let (_, (received, _)) = l.run(a.send(msg).join(b.into_future().map_err(|(err, _)| err)))
+        .unwrap();
+    let received = received.unwrap();
+
+    let index = match received.body().unwrap()[0] {
+        Value::BasicValue(BasicValue::UnixFd(index)) => index,
+        ref value => panic!("unexpected value: {:?}", value),
+    };
+    let fd = received.fd(index).unwrap();
+    assert_eq!(unsafe { libc::write(fd.as_raw_fd(), b"x".as_ptr() as *const _, 1) }, 1);
+
+    let mut buf = [0u8; 1];
+    assert_eq!(unsafe { libc::read(read_end.as_raw_fd(), buf.as_mut_ptr() as *mut _, 1) },
+               1);
+    assert_eq!(&buf, b"x");
+}
+
+#[test]
+fn test_unclaimed_fd_closed() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (a, b) = (Bus::new(a), Bus::new(b));
+
+    // Send a descriptor along with a message that doesn't declare it.
+    let (read_end, write_end) = pipe();
+    let mut msg = Message::signal("/", "org.example.Pipes", "Opened");
+    msg.attach_fd(write_end);
+    msg.fields.unix_fds = None;
+
+    let (_, (received, _)) = l.run(a.send(msg).join(b.into_future().map_err(|(err, _)| err)))
+        .unwrap();
+    assert!(received.unwrap().fds().is_empty());
+
+    // With every write end closed, reading hits EOF instead of blocking.
+    let mut buf = [0u8; 1];
+    assert_eq!(unsafe { libc::read(read_end.as_raw_fd(), buf.as_mut_ptr() as *mut _, 1) },
+               0);
+}
+
+#[test]
+fn test_fds_read_with_earlier_message() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (a, b) = (Bus::new(a), Bus::new(b));
+
+    // Both messages are sent before either is read, so a single read can
+    // take in the first one along with the second one's descriptor.
+    let first = Message::signal("/", "org.example.Pipes", "Ready");
+    let (read_end, write_end) = pipe();
+    let mut second = Message::signal("/", "org.example.Pipes", "Opened");
+    let index = second.attach_fd(write_end);
+    second.set_body(&[Value::BasicValue(BasicValue::UnixFd(index))]).unwrap();
+    l.run(a.send(first).and_then(|a| a.send(second))).unwrap();
+
+    let received = l.run(b.take(2).collect()).unwrap();
+    assert!(received[0].fds().is_empty());
+    let fd = received[1].fd(index).unwrap();
+    assert_eq!(unsafe { libc::write(fd.as_raw_fd(), b"x".as_ptr() as *const _, 1) }, 1);
+
+    let mut buf = [0u8; 1];
+    assert_eq!(unsafe { libc::read(read_end.as_raw_fd(), buf.as_mut_ptr() as *mut _, 1) },
+               1);
+    assert_eq!(&buf, b"x");
+}
+
+// Sends `buf` over `socket` in one write, with `fd` attached to its first
+// byte.
+fn send_with_fd(socket: &UnixStream, buf: &[u8], fd: Option<&OwnedFd>) {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    let mut control = [0u64; 4];
+    if let Some(fd) = fd {
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        unsafe {
+            msg.msg_controllen = libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) as _;
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+            *(libc::CMSG_DATA(cmsg) as *mut RawFd) = fd.as_raw_fd();
+        }
+    }
+    let n = unsafe { libc::sendmsg(socket.as_raw_fd(), &msg, 0) };
+    assert_eq!(n, buf.len() as isize);
+}
+
+#[test]
+fn test_stray_fd_not_passed_on() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let b = Bus::new(b);
+
+    let mut first = Vec::new();
+    let mut msg = Message::signal("/", "org.example.Pipes", "Ready");
+    msg.serial = 1;
+    encode_message(&msg, &mut first).unwrap();
+    let mut second = Vec::new();
+    msg.serial = 2;
+    encode_message(&msg, &mut second).unwrap();
+    let mut third = Vec::new();
+    let mut msg = Message::signal("/", "org.example.Pipes", "Opened");
+    msg.serial = 3;
+    msg.set_body(&[Value::BasicValue(BasicValue::UnixFd(0))]).unwrap();
+    msg.fields.unix_fds = Some(1);
+    encode_message(&msg, &mut third).unwrap();
+
+    // A stray descriptor comes with the first message and the start of the
+    // second, which is still buffered when the third message's descriptor
+    // arrives.
+    let (stray_read, stray_write) = pipe();
+    let (read_end, write_end) = pipe();
+    first.extend_from_slice(&second[..4]);
+    send_with_fd(&a, &first, Some(&stray_write));
+    drop(stray_write);
+    let (received, b) = l.run(b.into_future().map_err(|(err, _)| err)).unwrap();
+    assert!(received.unwrap().fds().is_empty());
+    let mut rest = second[4..].to_vec();
+    rest.extend_from_slice(&third);
+    send_with_fd(&a, &rest, Some(&write_end));
+    drop(write_end);
+
+    let received = l.run(b.take(2).collect()).unwrap();
+    assert!(received[0].fds().is_empty());
+    let fd = received[1].fd(0).unwrap();
+    assert_eq!(unsafe { libc::write(fd.as_raw_fd(), b"x".as_ptr() as *const _, 1) }, 1);
+
+    let mut buf = [0u8; 1];
+    assert_eq!(unsafe { libc::read(read_end.as_raw_fd(), buf.as_mut_ptr() as *mut _, 1) },
+               1);
+    assert_eq!(&buf, b"x");
+    assert_eq!(unsafe { libc::read(stray_read.as_raw_fd(), buf.as_mut_ptr() as *mut _, 1) },
+               0);
+}
@@ -0,0 +1,107 @@
+extern crate tokio_dbus;
+
+use tokio_dbus::{BasicValue, ContainerValue, Endianness, ToDBus, Type, Value, decode_gvariant,
+                 encode_gvariant, validate_gvariant_type};
+
+fn ty(s: &str) -> Type {
+    validate_gvariant_type(s.as_bytes()).unwrap()
+}
+
+fn encode(value: &Value, ty: &Type) -> Vec<u8> {
+    let mut buf = Vec::new();
+    encode_gvariant(value, ty, Endianness::Little, &mut buf).unwrap();
+    buf
+}
+
+// Checks the serialization of `value`, and that it decodes back to `value`.
+fn check(value: Value, signature: &str, bytes: &[u8]) {
+    let ty = ty(signature);
+    assert_eq!(encode(&value, &ty), bytes);
+    assert_eq!(decode_gvariant(bytes, &ty, Endianness::Little).unwrap(), value);
+}
+
+#[test]
+fn test_spec_examples() {
+    check("hello world".to_dbus(), "s", b"hello world\0");
+    check(vec![true, false, false, true, true].to_dbus(),
+          "ab",
+          &[1, 0, 0, 1, 1]);
+    check(("foo", -1i32).to_dbus(),
+          "(si)",
+          b"foo\0\xff\xff\xff\xff\x04");
+    check(vec!["i", "can", "has", "strings?"].to_dbus(),
+          "as",
+          b"i\0can\0has\0strings?\0\x02\x06\x0a\x13");
+    check(vec![("hi", -2i32), ("bye", -1i32)].to_dbus(),
+          "a(si)",
+          b"hi\0\0\xfe\xff\xff\xff\x03\0\0\0bye\0\xff\xff\xff\xff\x04\x09\x15");
+    check(vec![4i32, 258].to_dbus(), "ai", &[4, 0, 0, 0, 2, 1, 0, 0]);
+}
+
+#[test]
+fn test_containers() {
+    // Fixed-size structs are padded to their alignment.
+    check((1u8, 2u32).to_dbus(), "(yu)", &[1, 0, 0, 0, 2, 0, 0, 0]);
+    check((2u32, 1u8).to_dbus(), "(uy)", &[2, 0, 0, 0, 1, 0, 0, 0]);
+
+    let dict = Value::ContainerValue(ContainerValue::Dict(vec![
+        (BasicValue::String("a".into()), 1u8.to_dbus()),
+    ]));
+    check(dict, "a{sy}", b"a\0\x01\x02\x04");
+
+    let variant = 0x0102u16.to_dbus().into_variant().unwrap();
+    check(variant, "v", b"\x02\x01\0q");
+
+    // Enough data to need two-byte framing offsets.
+    let strings = (0..300).map(|i| i.to_string()).collect::<Vec<_>>();
+    let strings_ty = ty("as");
+    let bytes = encode(&strings.to_dbus(), &strings_ty);
+    assert_eq!(&bytes[bytes.len() - 2..], &[0x42, 0x04]);
+    assert_eq!(decode_gvariant(&bytes, &strings_ty, Endianness::Little).unwrap(),
+               strings.to_dbus());
+
+    let value = (-5i64, 1.5f64, vec![(String::from("x"), 7u16)]).to_dbus();
+    let struct_ty = ty("(xda(sq))");
+    let mut bytes = Vec::new();
+    encode_gvariant(&value, &struct_ty, Endianness::Big, &mut bytes).unwrap();
+    assert_eq!(decode_gvariant(&bytes, &struct_ty, Endianness::Big).unwrap(), value);
+}
+
+#[test]
+fn test_malformed() {
+    let err = |bytes: &[u8], signature: &str| {
+        decode_gvariant(bytes, &ty(signature), Endianness::Little).unwrap_err()
+    };
+    err(b"hello", "s");
+    err(&[1, 2, 3], "u");
+    err(&[2], "b");
+    err(&[1, 0, 0], "ai");
+    err(&[1, 9, 0, 0, 2, 0, 0, 0], "(yu)");
+    err(b"foo\0\xff\xff\xff\xff\x09", "(si)");
+    err(b"i\0\x05", "as");
+    err(b"\x02\x01q", "v");
+
+    let mut buf = Vec::new();
+    assert!(encode_gvariant(&1u32.to_dbus(), &ty("s"), Endianness::Little, &mut buf).is_err());
+}
+
+#[cfg(feature = "maybe")]
+#[test]
+fn test_maybe() {
+    use tokio_dbus::{DBusType, FromDBus, Signature, encode_values, validate_gvariant_signature};
+
+    assert_eq!(Option::<u32>::signature().to_string(), "mu");
+    check(Some("hello world").to_dbus(), "ms", b"hello world\0\0");
+    check(Some(7u32).to_dbus(), "mu", &[7, 0, 0, 0]);
+    check(None::<u32>.to_dbus(), "mu", &[]);
+    check(vec![Some("a"), None].to_dbus(), "ams", b"a\0\0\x03\x03");
+    assert_eq!(Option::<String>::from_dbus(Some("x").to_dbus()).unwrap(),
+               Some("x".to_owned()));
+
+    // The D-Bus wire format has no maybe type.
+    assert!("mu".parse::<Signature>().is_err());
+    let signature = validate_gvariant_signature(b"mu").unwrap();
+    let mut buf = Vec::new();
+    assert!(encode_values(&[Some(1u32).to_dbus()], &signature, Endianness::Little, &mut buf)
+        .is_err());
+}
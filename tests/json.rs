@@ -0,0 +1,114 @@
+#![cfg(feature = "serde_json")]
+
+extern crate serde_json;
+extern crate tokio_dbus;
+
+use std::collections::BTreeMap;
+use serde_json::Value as Json;
+use tokio_dbus::{JsonOptions, ObjectPath, PropertyMap, Signature, ToDBus, Type, Value,
+                 validate_gvariant_type, value_from_json, value_to_json, values_from_json,
+                 values_to_json};
+
+fn ty(s: &str) -> Type {
+    validate_gvariant_type(s.as_bytes()).unwrap()
+}
+
+fn json(s: &str) -> Json {
+    serde_json::from_str(s).unwrap()
+}
+
+// Checks the JSON for `value`, and that it reads back to `value`.
+fn check(value: Value, signature: &str, text: &str) {
+    let ty = ty(signature);
+    assert_eq!(value_to_json(&value, &ty, JsonOptions::new()).unwrap(), json(text));
+    assert_eq!(value_from_json(&json(text), &ty).unwrap(), value);
+}
+
+#[test]
+fn test_basic_types() {
+    check(true.to_dbus(), "b", "true");
+    check(200u8.to_dbus(), "y", "200");
+    check((-3i32).to_dbus(), "i", "-3");
+    check(1.5f64.to_dbus(), "d", "1.5");
+    check("héllo".to_dbus(), "s", "\"héllo\"");
+    check(ObjectPath::new("/org/example").unwrap().to_dbus(), "o", "\"/org/example\"");
+    check("a{sv}".parse::<Signature>().unwrap().to_dbus(), "g", "\"a{sv}\"");
+    check(::std::u64::MAX.to_dbus(), "t", "18446744073709551615");
+
+    let lossless = JsonOptions::new().with_lossless_int64(true);
+    assert_eq!(value_to_json(&::std::i64::MIN.to_dbus(), &ty("x"), lossless).unwrap(),
+               json("\"-9223372036854775808\""));
+    assert_eq!(value_to_json(&5u32.to_dbus(), &ty("u"), lossless).unwrap(), json("5"));
+    assert_eq!(value_from_json(&json("\"-9223372036854775808\""), &ty("x")).unwrap(),
+               ::std::i64::MIN.to_dbus());
+    assert!(value_from_json(&json("\"5\""), &ty("u")).is_err());
+    assert!(value_to_json(&::std::f64::NAN.to_dbus(), &ty("d"), JsonOptions::new()).is_err());
+}
+
+#[test]
+fn test_container_types() {
+    check(vec![0u8, 1, 2, 254, 255].to_dbus(), "ay", "\"AAEC/v8=\"");
+    check(Vec::<u8>::new().to_dbus(), "ay", "\"\"");
+    check(vec![vec![1u16], vec![]].to_dbus(), "aaq", "[[1], []]");
+    check((1i32, "one", vec![true]).to_dbus(), "(isab)", "[1, \"one\", [true]]");
+
+    let mut map = BTreeMap::new();
+    map.insert(1u32, "a".to_owned());
+    map.insert(20u32, "b".to_owned());
+    check(map.to_dbus(), "a{us}", "{\"1\": \"a\", \"20\": \"b\"}");
+
+    let mut props = PropertyMap::new();
+    props.insert("Count", 3u32);
+    props.insert("Items", Vec::<String>::new());
+    props.insert("Name", "foo");
+    check(props.to_dbus(),
+          "a{sv}",
+          r#"{"Count": {"signature": "u", "value": 3},
+              "Items": {"signature": "as", "value": []},
+              "Name": {"signature": "s", "value": "foo"}}"#);
+
+    let signature: Signature = "sau".parse().unwrap();
+    let values = vec!["x".to_dbus(), vec![1u32, 2].to_dbus()];
+    let body = values_to_json(&values, &signature, JsonOptions::new()).unwrap();
+    assert_eq!(body, json("[\"x\", [1, 2]]"));
+    assert_eq!(values_from_json(&body, &signature).unwrap(), values);
+}
+
+#[test]
+fn test_errors() {
+    let err = value_from_json(&json(r#"{"a": [1, "two"]}"#), &ty("a{sai}")).unwrap_err();
+    assert_eq!(err.path, "$.a[1]");
+    assert_eq!(err.expected, ty("i"));
+    assert_eq!(err.to_string(),
+               "Can't convert JSON at `$.a[1]` for D-Bus type `i`: \
+                expected an integer, found a string.");
+
+    assert!(value_from_json(&json(r#"{"x y": 1}"#), &ty("a{sy}")).is_ok());
+    let err = value_from_json(&json(r#"{"x y": 300}"#), &ty("a{sy}")).unwrap_err();
+    assert_eq!(err.path, "$['x y']");
+    assert_eq!(err.reason, "300 is out of range");
+
+    let err = value_from_json(&json(r#"[{"signature": "z", "value": 1}]"#), &ty("av"))
+        .unwrap_err();
+    assert_eq!(err.path, "$[0].signature");
+    assert_eq!(err.expected, ty("g"));
+
+    let err = value_from_json(&json("{\"1.5\": true}"), &ty("a{ib}")).unwrap_err();
+    assert_eq!(err.expected, ty("i"));
+    assert!(value_from_json(&json("[1]"), &ty("(ii)")).is_err());
+    assert!(value_from_json(&json("1.5"), &ty("i")).is_err());
+    assert!(value_from_json(&json("\"AAE\""), &ty("ay")).is_err());
+    assert!(value_from_json(&json("\"/a/\""), &ty("o")).is_err());
+    assert!(value_from_json(&json("{\"value\": 1}"), &ty("v")).is_err());
+    assert!(value_to_json(&1u32.to_dbus(), &ty("s"), JsonOptions::new()).is_err());
+}
+
+#[cfg(feature = "maybe")]
+#[test]
+fn test_maybe() {
+    check(Some(1u32).to_dbus(), "mu", "1");
+    check(None::<u32>.to_dbus(), "mu", "null");
+    check(Some(None::<bool>).to_dbus(), "mmb", "[null]");
+    check(None::<Option<bool>>.to_dbus(), "mmb", "null");
+    check(vec![Some("a"), None].to_dbus(), "ams", "[\"a\", null]");
+}
@@ -0,0 +1,63 @@
+#[macro_use]
+extern crate tokio_dbus;
+
+use std::collections::{BTreeMap, HashMap};
+use tokio_dbus::{ConstSignature, DBusType, ObjectPath, PropertyMap, Signature, Variant};
+
+const POINT: ConstSignature = ConstSignature::structure(&[<i32 as DBusType>::SIGNATURE,
+                                                            <i32 as DBusType>::SIGNATURE]);
+const POINTS: ConstSignature = ConstSignature::array(POINT);
+
+assert_signature!(Vec<(String, u32)>, "a(su)");
+assert_signature!(HashMap<u8, Vec<ObjectPath>>, "a{yao}");
+assert_signature!(PropertyMap, "a{sv}");
+assert_signature!(&'static str, "s");
+
+fn check<T: DBusType>(signature: &str) {
+    assert_eq!(T::SIGNATURE.as_str(), signature);
+    assert_eq!(T::SIGNATURE.to_type(), T::dbus_type());
+}
+
+#[test]
+fn test_signature_macro() {
+    let signature: Signature = signature!("a{sv}(iu)");
+    assert_eq!(signature, "a{sv}(iu)".parse().unwrap());
+    assert_eq!(signature!(""), Signature::default());
+    assert_eq!(POINTS.to_string(), "a(ii)");
+    assert!(POINTS.eq_str("a(ii)"));
+    assert!(!POINTS.eq_str("a(iu)"));
+    assert_eq!(format!("{:?}", POINT), "ConstSignature(\"(ii)\")");
+}
+
+#[test]
+fn test_trait_signatures() {
+    check::<u8>("y");
+    check::<bool>("b");
+    check::<f64>("d");
+    check::<String>("s");
+    check::<Signature>("g");
+    check::<Variant>("v");
+    check::<(u8, (i64, String), Vec<bool>)>("(y(xs)ab)");
+    check::<BTreeMap<String, Vec<u16>>>("a{saq}");
+    check::<PropertyMap>("a{sv}");
+}
+
+#[test]
+#[should_panic(expected = "dict key that is not basic")]
+fn test_invalid_at_run_time() {
+    // In a constant, this fails the build instead.
+    ConstSignature::new("a{vs}");
+}
+
+#[test]
+fn test_validation() {
+    // Invalid in a constant, so checked at run time here.
+    let invalid = ["a", "(", "()", "a{s}", "{sv}", "a{sv", "z", "(i))", "a{(i)s}"];
+    for signature in &invalid {
+        let result = std::panic::catch_unwind(|| ConstSignature::new(signature));
+        assert!(result.is_err(), "{:?} should be invalid", signature);
+    }
+    let nested = "a".repeat(32) + "i";
+    assert_eq!(ConstSignature::new(&nested).as_str(), nested);
+    assert!(std::panic::catch_unwind(|| ConstSignature::new(&("a".repeat(33) + "i"))).is_err());
+}
@@ -0,0 +1,179 @@
+extern crate tokio_dbus;
+
+use std::sync::Arc;
+use tokio_dbus::{BasicValue, BasicValueRef, ContainerValue, ContainerValueRef, LimitError, Limits,
+                 Message, MessageType, Signature, Value, ValueRef, decode_message,
+                 decode_shared_message, encode_message};
+
+fn hello() -> Message {
+    let mut msg = Message::method_call("/org/freedesktop/DBus", "Hello");
+    msg.serial = 1;
+    msg.fields.interface = Some("org.freedesktop.DBus".to_owned());
+    msg.fields.destination = Some("org.freedesktop.DBus".to_owned());
+    msg
+}
+
+#[test]
+fn test_encode() {
+    let mut buf = vec![];
+    encode_message(&hello(), &mut buf).unwrap();
+
+    let mut expected = vec![];
+    expected.extend_from_slice(b"l\x01\x00\x01\x00\x00\x00\x00\x01\x00\x00\x00\x6d\x00\x00\x00");
+    expected.extend_from_slice(b"\x01\x01o\x00\x15\x00\x00\x00/org/freedesktop/DBus\x00\x00\x00");
+    expected.extend_from_slice(b"\x02\x01s\x00\x14\x00\x00\x00org.freedesktop.DBus\x00");
+    expected.extend_from_slice(b"\x00\x00\x00");
+    expected.extend_from_slice(b"\x03\x01s\x00\x05\x00\x00\x00Hello\x00\x00\x00");
+    expected.extend_from_slice(b"\x06\x01s\x00\x14\x00\x00\x00org.freedesktop.DBus\x00");
+    expected.extend_from_slice(b"\x00\x00\x00");
+    if cfg!(target_endian = "little") {
+        assert_eq!(buf, expected);
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    let mut msg = hello();
+    let body = vec![Value::BasicValue(BasicValue::String("name".into())),
+                    Value::ContainerValue(ContainerValue::Array(vec![])),
+                    Value::ContainerValue(ContainerValue::Struct(vec![
+                        Value::BasicValue(BasicValue::Byte(7)),
+                        Value::BasicValue(BasicValue::Double(1.5)),
+                    ]))];
+    msg.set_body_with_signature(&body, "sat(yd)".parse::<Signature>().unwrap()).unwrap();
+
+    let mut buf = vec![];
+    encode_message(&msg, &mut buf).unwrap();
+    buf.extend_from_slice(b"rest");
+
+    let (decoded, remaining) = decode_message(&buf, &Limits::default()).unwrap().unwrap();
+    assert_eq!(remaining, b"rest");
+    assert_eq!(decoded.message_type, MessageType::MethodCall);
+    assert_eq!(decoded.fields, msg.fields);
+    assert_eq!(decoded.body().unwrap(), body);
+
+    assert_eq!(decode_message(&buf[..buf.len() - 5], &Limits::default()).unwrap(),
+               None);
+}
+
+#[test]
+fn test_shared_buffer() {
+    let mut first = hello();
+    first.set_body(&[Value::BasicValue(BasicValue::String("first".into()))]).unwrap();
+    let mut second = hello();
+    second.serial = 2;
+    second.set_body(&[Value::BasicValue(BasicValue::String("second".into()))]).unwrap();
+
+    let mut buf = vec![];
+    encode_message(&first, &mut buf).unwrap();
+    let first_len = buf.len();
+    encode_message(&second, &mut buf).unwrap();
+    let buf = Arc::new(buf);
+    let limits = Limits::default();
+
+    let (decoded_first, end) = decode_shared_message(&buf, 0, &limits).unwrap().unwrap();
+    assert_eq!(end, first_len);
+    assert_eq!(decoded_first, first);
+    // The body is read straight out of the shared buffer.
+    let body = decoded_first.body_bytes();
+    assert!(buf.as_ptr() <= body.as_ptr() && body.as_ptr() < buf[first_len..].as_ptr());
+
+    let (decoded_second, end) = decode_shared_message(&buf, end, &limits).unwrap().unwrap();
+    assert_eq!(end, buf.len());
+    assert_eq!(decoded_second, second);
+    assert_eq!(Arc::strong_count(&buf), 3);
+    drop(decoded_first);
+    drop(decoded_second);
+    assert_eq!(Arc::strong_count(&buf), 1);
+
+    assert_eq!(decode_shared_message(&buf, end, &limits).unwrap(), None);
+}
+
+fn limit_error(buf: &[u8], limits: &Limits) -> LimitError {
+    let err = decode_message(buf, limits).unwrap_err();
+    err.get_ref().and_then(|err| err.downcast_ref::<LimitError>()).unwrap().clone()
+}
+
+#[test]
+fn test_limits() {
+    // Only the fixed header of a message claiming a 256 MiB body.
+    let header = b"l\x01\x00\x01\x00\x00\x00\x10\x01\x00\x00\x00\x00\x00\x00\x00";
+    assert_eq!(limit_error(header, &Limits::default()),
+               LimitError::MessageTooLarge {
+                   size: 256 * 1024 * 1024 + 16,
+                   limit: 128 * 1024 * 1024,
+               });
+
+    let mut msg = Message::method_call("/", "Ping");
+    msg.serial = 1;
+    let bytes = vec![Value::ContainerValue(ContainerValue::Array(vec![
+        Value::BasicValue(BasicValue::Byte(0)); 100]))];
+    msg.set_body(&bytes).unwrap();
+    let mut buf = vec![];
+    encode_message(&msg, &mut buf).unwrap();
+
+    let limits = Limits::new().with_max_message_size(128);
+    match limit_error(&buf[..16], &limits) {
+        LimitError::MessageTooLarge { limit: 128, .. } => {}
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    let limits = Limits::new().with_max_array_len(64);
+    assert_eq!(limit_error(&buf, &limits),
+               LimitError::ArrayTooLarge {
+                   len: 100,
+                   limit: 64,
+               });
+}
+
+#[test]
+fn test_long_signatures() {
+    // 256 bytes of signature, one more than fits.
+    let fields = vec![Value::BasicValue(BasicValue::Byte(0)); 254];
+    let long = Value::ContainerValue(ContainerValue::Struct(fields));
+    let long_ty = long.signature().unwrap();
+    let mut msg = Message::method_call("/", "Ping");
+    msg.serial = 1;
+    assert!(msg.set_body(&[long.clone().into_variant().unwrap()]).is_err());
+    assert!(msg.set_body(&[long]).is_err());
+    assert!(msg.fields.signature.is_none());
+
+    // One byte fewer is fine.
+    let fields = vec![Value::BasicValue(BasicValue::Byte(0)); 253];
+    let longest = Value::ContainerValue(ContainerValue::Struct(fields));
+    msg.set_body(&[longest]).unwrap();
+
+    msg.fields.signature = Some(Signature::new(vec![long_ty]));
+    let mut buf = vec![];
+    assert!(encode_message(&msg, &mut buf).is_err());
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_body_ref() {
+    let mut msg = Message::signal("/", "org.example.Iface", "Changed");
+    msg.set_body_with_signature(&[Value::BasicValue(BasicValue::String("on".into())),
+                                  Value::ContainerValue(ContainerValue::Array(vec![
+                                      Value::BasicValue(BasicValue::Byte(1)),
+                                      Value::BasicValue(BasicValue::Byte(2)),
+                                  ]))],
+                                "say".parse::<Signature>().unwrap())
+        .unwrap();
+
+    let body = msg.body_ref().unwrap();
+    assert_eq!(body,
+               vec![ValueRef::BasicValue(BasicValueRef::String("on")),
+                    ValueRef::ContainerValue(ContainerValueRef::ByteArray(&[1, 2]))]);
+
+    let start = msg.body_bytes().as_ptr() as usize;
+    match body[0] {
+        ValueRef::BasicValue(BasicValueRef::String(s)) => {
+            let offset = s.as_ptr() as usize - start;
+            assert!(offset < msg.body_bytes().len());
+        }
+        _ => unreachable!(),
+    }
+
+    let owned = body.into_iter().map(ValueRef::into_owned).collect::<Vec<_>>();
+    assert_eq!(owned, msg.body().unwrap());
+}
@@ -0,0 +1,108 @@
+extern crate tokio_dbus;
+
+use tokio_dbus::{BusName, InterfaceName, MatchRule, MemberName, Message, MessageType,
+                 ObjectPath, ToDBus, Value};
+
+fn signal(path: &str, member: &str, args: &[Value]) -> Message {
+    let mut msg = Message::signal(path, "org.freedesktop.DBus", member);
+    msg.fields.sender = Some("org.freedesktop.DBus".to_owned());
+    msg.set_body(args).unwrap();
+    msg
+}
+
+#[test]
+fn test_format_and_parse() {
+    let rule = MatchRule::new()
+        .message_type(MessageType::Signal)
+        .sender(BusName::new("org.freedesktop.DBus").unwrap())
+        .interface(InterfaceName::new("org.freedesktop.DBus").unwrap())
+        .member(MemberName::new("NameOwnerChanged").unwrap())
+        .path_namespace(ObjectPath::new("/org").unwrap())
+        .destination(BusName::new(":1.7").unwrap())
+        .arg0_namespace("org.example")
+        .arg(2, "")
+        .arg_path(3, "/a/")
+        .eavesdrop(true);
+    let text = rule.to_string();
+    assert_eq!(text,
+               "type='signal',sender='org.freedesktop.DBus',interface='org.freedesktop.DBus',\
+                member='NameOwnerChanged',path_namespace='/org',destination=':1.7',\
+                arg0namespace='org.example',arg2='',arg3path='/a/',eavesdrop='true'");
+    assert_eq!(text.parse::<MatchRule>().unwrap(), rule);
+
+    // Quotes, and unquoted values with their escapes.
+    let rule = MatchRule::new().arg(0, "'").arg(1, r"a\b,c").arg(5, "x'y'");
+    assert_eq!(rule.to_string(), r"arg0=''\',arg1='a\b,c',arg5='x'\''y'\'");
+    assert_eq!(rule.to_string().parse::<MatchRule>().unwrap(), rule);
+    assert_eq!(r" arg0 =\'x\y , arg1=don\'t ".parse::<MatchRule>().unwrap(),
+               MatchRule::new().arg(0, r"'x\y ").arg(1, "don't "));
+    assert_eq!("member='Na'me".parse::<MatchRule>().unwrap(),
+               MatchRule::new().member(MemberName::new("Name").unwrap()));
+    assert_eq!("".parse::<MatchRule>().unwrap(), MatchRule::new());
+}
+
+#[test]
+fn test_parse_errors() {
+    for text in &["type='bogus'",
+                  "member",
+                  "member='a'b'",
+                  "member='a.b'",
+                  "member='A',member='B'",
+                  "path='/a',path_namespace='/b'",
+                  "arg0='a',arg0namespace='b'",
+                  "arg64='a'",
+                  "arg1x='a'",
+                  "eavesdrop='yes'",
+                  "colour='red'",
+                  "interface='unterminated"] {
+        assert!(text.parse::<MatchRule>().is_err(), "{}", text);
+    }
+    let err = "colour='red'".parse::<MatchRule>().unwrap_err();
+    assert_eq!(err.to_string(), "Invalid D-Bus match rule: unknown key `colour`.");
+}
+
+#[test]
+fn test_matches() {
+    let changed = signal("/org/freedesktop/DBus",
+                         "NameOwnerChanged",
+                         &["org.example.App".to_dbus(), "".to_dbus(), ":1.9".to_dbus()]);
+    let matches = |text: &str| text.parse::<MatchRule>().unwrap().matches(&changed);
+
+    assert!(matches(""));
+    assert!(matches("type='signal',sender='org.freedesktop.DBus',member='NameOwnerChanged'"));
+    assert!(!matches("type='method_call'"));
+    assert!(!matches("destination=':1.1'"));
+    assert!(matches("path='/org/freedesktop/DBus'"));
+    assert!(!matches("path='/org/freedesktop'"));
+    assert!(matches("path_namespace='/org/freedesktop'"));
+    assert!(matches("path_namespace='/'"));
+    assert!(!matches("path_namespace='/org/free'"));
+    assert!(matches("arg0='org.example.App',arg2=':1.9'"));
+    assert!(!matches("arg1='x'"));
+    assert!(!matches("arg3=''"));
+    assert!(matches("arg0namespace='org.example'"));
+    assert!(matches("arg0namespace='org.example.App'"));
+    assert!(!matches("arg0namespace='org.ex'"));
+    assert!(matches("eavesdrop='true'"));
+
+    let moved = signal("/", "Moved", &[ObjectPath::new("/a/b").unwrap().to_dbus(), 1u32.to_dbus()]);
+    let matches = |text: &str| text.parse::<MatchRule>().unwrap().matches(&moved);
+    assert!(matches("arg0path='/a/b'"));
+    assert!(matches("arg0path='/a/'"));
+    assert!(!matches("arg0path='/a'"));
+    assert!(!matches("arg0='/a/b'"));
+    assert!(!matches("arg1='1'"));
+
+    // A bus routes messages with the sender's unique name.
+    let mut routed = signal("/", "Moved", &[]);
+    routed.fields.sender = Some(":1.7".to_owned());
+    let matches = |text: &str| text.parse::<MatchRule>().unwrap().matches_routed(&routed);
+    assert!(matches("sender='org.example.App'"));
+    assert!(matches("sender=':1.7'"));
+    assert!(!matches("sender=':1.8'"));
+    assert!(!matches("sender='org.example.App',member='Changed'"));
+    assert!(!"sender='org.example.App'".parse::<MatchRule>().unwrap().matches(&routed));
+    let matches = |text: &str| text.parse::<MatchRule>().unwrap().matches_routed(&moved);
+    assert!(matches("sender='org.freedesktop.DBus'"));
+    assert!(!matches("sender='org.example.App'"));
+}
@@ -0,0 +1,126 @@
+#![cfg(feature = "serde")]
+
+#[macro_use]
+extern crate serde_derive;
+extern crate tokio_dbus;
+
+use std::collections::HashMap;
+use tokio_dbus::{AsVariant, BasicType, BasicValue, ContainerValue, Endianness, PathSegment, Type,
+                 Value, from_bytes, from_value, signature_of, to_bytes, to_value};
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct Device {
+    name: String,
+    id: u32,
+    tags: Vec<String>,
+    props: HashMap<String, AsVariant<i64>>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+enum State {
+    Idle,
+    Busy(u32),
+    Failed { code: i32, reason: String },
+}
+
+fn device() -> Device {
+    let mut props = HashMap::new();
+    props.insert("weight".to_owned(), AsVariant(-3));
+    Device {
+        name: "eth0".to_owned(),
+        id: 2,
+        tags: vec!["up".to_owned()],
+        props: props,
+    }
+}
+
+#[test]
+fn test_signature_of() {
+    assert_eq!(signature_of::<Device>().unwrap().to_string(), "(suasa{sv})");
+    assert_eq!(signature_of::<Vec<(u8, bool, f64)>>().unwrap().to_string(), "a(ybd)");
+    assert_eq!(signature_of::<Option<String>>().unwrap().to_string(), "s");
+    assert_eq!(signature_of::<State>().unwrap().to_string(), "(sv)");
+    assert_eq!(signature_of::<Vec<State>>().unwrap().to_string(), "a(sv)");
+    assert!(signature_of::<()>().is_err());
+}
+
+#[test]
+fn test_round_trip() {
+    let value = to_value(&device()).unwrap();
+    value.check_type(&signature_of::<Device>().unwrap()[0]).unwrap();
+    assert_eq!(from_value::<Device>(value).unwrap(), device());
+
+    for state in vec![State::Idle,
+                      State::Busy(7),
+                      State::Failed {
+                          code: -1,
+                          reason: "jammed".to_owned(),
+                      }] {
+        let value = to_value(&state).unwrap();
+        value.check_type(&signature_of::<State>().unwrap()[0]).unwrap();
+        assert_eq!(from_value::<State>(value).unwrap(), state);
+    }
+    assert_eq!(to_value(&State::Idle).unwrap(),
+               Value::ContainerValue(ContainerValue::Struct(vec![
+                   Value::BasicValue(BasicValue::String("Idle".into())),
+                   Value::ContainerValue(ContainerValue::Variant(
+                       Type::BasicType(BasicType::String),
+                       Box::new(Value::BasicValue(BasicValue::String("".into()))))),
+               ])));
+    assert_eq!(to_value(&State::Busy(7)).unwrap(),
+               Value::ContainerValue(ContainerValue::Struct(vec![
+                   Value::BasicValue(BasicValue::String("Busy".into())),
+                   Value::ContainerValue(ContainerValue::Variant(
+                       Type::BasicType(BasicType::UInt32),
+                       Box::new(Value::BasicValue(BasicValue::UInt32(7))))),
+               ])));
+}
+
+#[test]
+fn test_bytes() {
+    let signature = signature_of::<Device>().unwrap();
+    let mut buf = Vec::new();
+    to_bytes(&device(), &signature, Endianness::Little, &mut buf).unwrap();
+    assert_eq!(from_bytes::<Device>(&buf, &signature, Endianness::Little).unwrap(),
+               device());
+
+    // A multi-type signature reads and writes the fields of a tuple.
+    let signature = "us".parse().unwrap();
+    let mut buf = Vec::new();
+    to_bytes(&(1u32, "one"), &signature, Endianness::Big, &mut buf).unwrap();
+    assert_eq!(from_bytes::<(u32, String)>(&buf, &signature, Endianness::Big).unwrap(),
+               (1, "one".to_owned()));
+}
+
+#[test]
+fn test_dict_as_struct() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Props {
+        enabled: bool,
+        count: u32,
+    }
+
+    let dict = Value::ContainerValue(ContainerValue::Dict(vec![
+        (BasicValue::String("count".into()),
+         Value::BasicValue(BasicValue::UInt32(3)).into_variant().unwrap()),
+        (BasicValue::String("enabled".into()),
+         Value::BasicValue(BasicValue::Bool(true)).into_variant().unwrap()),
+    ]));
+    assert_eq!(from_value::<Props>(dict).unwrap(),
+               Props {
+                   enabled: true,
+                   count: 3,
+               });
+}
+
+#[test]
+fn test_errors() {
+    let value = to_value(&vec![(1u32, "a")]).unwrap();
+    let err = from_value::<Vec<(u32, u32)>>(value).unwrap_err();
+    assert_eq!(err.path, vec![PathSegment::Element(0), PathSegment::Field(1)]);
+    assert_eq!(err.to_string(),
+               "Expected a value of type `u`, found a string at element 0, field 1.");
+
+    let err = to_value(&vec![None::<u32>]).unwrap_err();
+    assert_eq!(err.path, vec![PathSegment::Element(0)]);
+}
@@ -0,0 +1,143 @@
+extern crate futures;
+extern crate tokio_core;
+extern crate tokio_dbus;
+extern crate tokio_uds;
+
+use futures::{Future, Stream};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio_core::reactor::Core;
+use tokio_dbus::{Bus, DBusError, FromDBus, Message, MessageType, ObjectPath, ObjectServer,
+                 StandardError, ToDBus, Variant, NO_AUTO_START, NO_REPLY_EXPECTED};
+use tokio_uds::UnixStream;
+
+// An object whose `Double` method doubles its argument, counting calls.
+fn doubler(calls: Rc<Cell<u32>>) -> impl FnMut(&Message) -> tokio_dbus::MethodResult {
+    move |call| {
+        calls.set(calls.get() + 1);
+        match call.fields.member.as_ref().map(|member| &member[..]) {
+            Some("Double") => {
+                let arg = u32::from_dbus(call.body()?.remove(0))?;
+                Ok(vec![(arg * 2).to_dbus()])
+            }
+            _ => Err(DBusError::standard(StandardError::UnknownMethod, "No such method.")),
+        }
+    }
+}
+
+#[test]
+fn test_flags() {
+    let mut msg = Message::method_call("/", "M");
+    assert!(!msg.has_flag(NO_REPLY_EXPECTED));
+    msg.set_flag(NO_REPLY_EXPECTED, true);
+    msg.set_flag(NO_AUTO_START, true);
+    assert_eq!(msg.flags, 0x3);
+    msg.set_flag(NO_REPLY_EXPECTED, false);
+    assert!(!msg.has_flag(NO_REPLY_EXPECTED));
+    assert!(msg.has_flag(NO_AUTO_START));
+}
+
+#[test]
+fn test_handle() {
+    let calls = Rc::new(Cell::new(0));
+    let mut server = ObjectServer::new();
+    server.export(ObjectPath::new("/a").unwrap(), doubler(calls.clone()));
+    assert!(server.is_exported("/a"));
+
+    let mut call = Message::method_call("/a", "Double");
+    call.serial = 5;
+    call.set_body(&[21u32.to_dbus()]).unwrap();
+    let reply = server.handle(&call).unwrap();
+    assert_eq!(reply.message_type, MessageType::MethodReturn);
+    assert_eq!(reply.fields.reply_serial, Some(5));
+    assert_eq!(reply.body().unwrap(), vec![42u32.to_dbus()]);
+
+    let reply = server.handle(&Message::method_call("/a", "Halve")).unwrap();
+    assert_eq!(DBusError::from_reply(&reply).standard_kind(),
+               Some(StandardError::UnknownMethod));
+    let reply = server.handle(&Message::method_call("/b", "Double")).unwrap();
+    assert_eq!(DBusError::from_reply(&reply).standard_kind(),
+               Some(StandardError::UnknownObject));
+
+    // The handler runs, but no reply is made.
+    call.set_flag(NO_REPLY_EXPECTED, true);
+    assert!(server.handle(&call).is_none());
+    assert_eq!(calls.get(), 3);
+    assert!(server.handle(&Message::signal("/a", "org.example.I", "S")).is_none());
+
+    assert!(server.unexport("/a"));
+    assert!(!server.unexport("/a"));
+    assert!(server.paths().is_empty());
+}
+
+#[test]
+fn test_served() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (client, mut service) = (Bus::new(a), Bus::new(b));
+
+    let calls = Rc::new(Cell::new(0));
+    service.export(ObjectPath::new("/a").unwrap(), doubler(calls.clone()));
+    let client = client.spawn(&handle);
+    client.call_no_reply("org.example.App", "/a", "org.example.I", "Double", &[Variant::new(1u32)])
+        .unwrap();
+    let call =
+        client.call("org.example.App", "/a", "org.example.I", "Double", &[Variant::new(2u32)]);
+    let _unknown = client.call("org.example.App", "/b", "org.example.I", "Double", &[]);
+
+    // Calls on paths with no object are left to the stream.
+    let (other, service) = l.run(service.into_future().map_err(|(err, _)| err)).unwrap();
+    assert_eq!(other.unwrap().fields.path, Some("/b".to_owned()));
+    assert_eq!(calls.get(), 2);
+
+    // Replies come in order, so one to the first call would have come first.
+    let mut service = service;
+    let reverse = service.call("org.example.Client", "/c", "org.example.I", "Double", &[]);
+    handle.spawn(service.for_each(|_| Ok(())).map_err(|_| ()));
+    assert_eq!(l.run(call).unwrap(), vec![4u32.to_dbus()]);
+
+    // A shared connection has nowhere else to send calls.
+    let err = l.run(reverse).err().unwrap();
+    assert_eq!(err.standard_kind(), Some(StandardError::UnknownObject));
+}
+
+#[test]
+fn test_handler_uses_handle() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (mut client, service) = (Bus::new(a), Bus::new(b).spawn(&handle));
+
+    let signaller = service.clone();
+    service.export(ObjectPath::new("/a").unwrap(), move |_| {
+        signaller.send(Message::signal("/a", "org.example.I", "Called"));
+        Ok(Vec::new())
+    });
+    let call = client.call("org.example.App", "/a", "org.example.I", "M", &[]);
+
+    // The signal is sent ahead of the reply.
+    let (signal, client) = l.run(client.into_future().map_err(|(err, _)| err)).unwrap();
+    assert_eq!(signal.unwrap().fields.member, Some("Called".to_owned()));
+    handle.spawn(client.for_each(|_| Ok(())).map_err(|_| ()));
+    assert!(l.run(call).unwrap().is_empty());
+}
+
+#[test]
+fn test_served_without_handles() {
+    let mut l = Core::new().unwrap();
+    let handle = l.handle();
+    let (a, b) = UnixStream::pair(&handle).unwrap();
+    let (client, service) = (Bus::new(a).spawn(&handle), Bus::new(b).spawn(&handle));
+
+    // The driver keeps answering for the object once the handle is gone.
+    let calls = Rc::new(Cell::new(0));
+    service.export(ObjectPath::new("/a").unwrap(), doubler(calls.clone()));
+    drop(service);
+    l.turn(Some(Duration::from_millis(10)));
+    let call =
+        client.call("org.example.App", "/a", "org.example.I", "Double", &[Variant::new(2u32)]);
+    assert_eq!(l.run(call).unwrap(), vec![4u32.to_dbus()]);
+    assert_eq!(calls.get(), 1);
+}
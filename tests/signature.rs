@@ -0,0 +1,144 @@
+extern crate tokio_dbus;
+
+use std::iter;
+use tokio_dbus::{BasicType, BasicValue, ContainerType, ContainerValue, PathSegment, Signature,
+                 SignatureError, Type, TypeError, TypeErrorKind, Value, check_signature,
+                 validate_gvariant_signature, validate_gvariant_type, validate_signature};
+
+#[test]
+fn test_valid() {
+    assert_eq!(validate_signature(b""), Ok(Signature::default()));
+    assert_eq!(validate_signature(b"a{sv}"),
+               Ok(Signature::from(vec![Type::ContainerType(Box::new(
+                   ContainerType::Dict(BasicType::String,
+                                       Type::ContainerType(Box::new(ContainerType::Variant)))))])));
+
+    let deepest_arrays = iter::repeat(b'a').take(32).chain(iter::once(b'y')).collect::<Vec<u8>>();
+    assert!(validate_signature(&deepest_arrays).is_ok());
+}
+
+#[test]
+fn test_invalid() {
+    let too_long = vec![b'y'; 256];
+    assert_eq!(validate_signature(&too_long),
+               Err(SignatureError::TooLong { len: 256 }));
+
+    assert_eq!(validate_signature(b"a"), Err(SignatureError::UnexpectedEnd));
+    assert_eq!(validate_signature(b"(i"), Err(SignatureError::UnexpectedEnd));
+    assert_eq!(validate_signature(b"i)"),
+               Err(SignatureError::UnexpectedClose { offset: 1, code: b')' }));
+    assert_eq!(validate_signature(b"iz"),
+               Err(SignatureError::InvalidTypeCode { offset: 1, code: b'z' }));
+    assert_eq!(validate_signature(b"()"),
+               Err(SignatureError::EmptyStruct { offset: 0 }));
+    assert_eq!(validate_signature(b"{sv}"),
+               Err(SignatureError::DictEntryOutsideArray { offset: 0 }));
+    assert_eq!(validate_signature(b"a(i{sv})"),
+               Err(SignatureError::DictEntryOutsideArray { offset: 3 }));
+    assert_eq!(validate_signature(b"a{vs}"),
+               Err(SignatureError::DictKeyNotBasic { offset: 2 }));
+    assert_eq!(validate_signature(b"a{s}"),
+               Err(SignatureError::DictEntryArity { offset: 1 }));
+    assert_eq!(validate_signature(b"a{sss}"),
+               Err(SignatureError::DictEntryArity { offset: 1 }));
+}
+
+#[test]
+fn test_depth_limits() {
+    let arrays = iter::repeat(b'a').take(33).chain(iter::once(b'y')).collect::<Vec<u8>>();
+    assert_eq!(validate_signature(&arrays),
+               Err(SignatureError::ArrayDepthExceeded { offset: 32 }));
+
+    let structs = iter::repeat(b'(')
+        .take(33)
+        .chain(iter::once(b'y'))
+        .chain(iter::repeat(b')').take(33))
+        .collect::<Vec<u8>>();
+    assert_eq!(validate_signature(&structs),
+               Err(SignatureError::StructDepthExceeded { offset: 32 }));
+}
+
+#[test]
+fn test_display_from_str() {
+    let sig = "a{sv}(iao)h".parse::<Signature>().unwrap();
+    assert_eq!(sig.len(), 3);
+    assert_eq!(sig.to_string(), "a{sv}(iao)h");
+
+    let ty = "a(sv)".parse::<Type>().unwrap();
+    assert_eq!(ty.to_string(), "a(sv)");
+    assert_eq!("".parse::<Type>(), Err(SignatureError::UnexpectedEnd));
+    assert_eq!("ss".parse::<Type>(),
+               Err(SignatureError::NotSingleType { offset: 1 }));
+}
+
+#[test]
+fn test_gvariant_types() {
+    // D-Bus signatures never hold maybe types, whatever features are on.
+    assert_eq!("mu".parse::<Signature>(),
+               Err(SignatureError::InvalidTypeCode {
+                   offset: 0,
+                   code: b'm',
+               }));
+    assert!("a{sv}".parse::<Signature>().is_ok());
+    assert_eq!(validate_gvariant_signature(b"a{sv}i").unwrap().to_string(), "a{sv}i");
+
+    if cfg!(feature = "maybe") {
+        assert_eq!(validate_gvariant_type(b"amu").unwrap().to_string(), "amu");
+        assert_eq!(validate_gvariant_signature(b"a{mss}"),
+                   Err(SignatureError::DictKeyNotBasic { offset: 2 }));
+    } else {
+        assert!(validate_gvariant_type(b"amu").is_err());
+    }
+}
+
+#[test]
+fn test_value_signature() {
+    let value = Value::ContainerValue(ContainerValue::Dict(vec![
+        (BasicValue::String("a".into()),
+         Value::ContainerValue(ContainerValue::Variant(
+             Type::BasicType(BasicType::UInt32),
+             Box::new(Value::BasicValue(BasicValue::UInt32(1)))))),
+    ]));
+    assert_eq!(value.signature().unwrap().to_string(), "a{sv}");
+
+    // Variants say what they hold, even when it's an empty container.
+    let empty = Value::ContainerValue(ContainerValue::Variant(
+        "as".parse().unwrap(),
+        Box::new(Value::ContainerValue(ContainerValue::Array(vec![])))));
+    assert_eq!(empty.signature().unwrap().to_string(), "v");
+    let wrong = Value::ContainerValue(ContainerValue::Variant(
+        Type::BasicType(BasicType::String),
+        Box::new(Value::BasicValue(BasicValue::UInt32(1)))));
+    assert_eq!(wrong.signature().unwrap_err().path, vec![PathSegment::Variant]);
+
+    let mixed = Value::ContainerValue(ContainerValue::Array(vec![
+        Value::BasicValue(BasicValue::Int32(1)),
+        Value::BasicValue(BasicValue::String("two".into())),
+    ]));
+    assert_eq!(mixed.signature(),
+               Err(TypeError {
+                   path: vec![PathSegment::Element(1)],
+                   kind: TypeErrorKind::MixedArray {
+                       expected: Type::BasicType(BasicType::Int32),
+                       found: Type::BasicType(BasicType::String),
+                   },
+               }));
+}
+
+#[test]
+fn test_check_signature() {
+    let sig = "sa{su}".parse::<Signature>().unwrap();
+    let body = vec![
+        Value::BasicValue(BasicValue::String("name".into())),
+        Value::ContainerValue(ContainerValue::Dict(vec![
+            (BasicValue::String("x".into()), Value::BasicValue(BasicValue::UInt32(1))),
+            (BasicValue::String("y".into()), Value::BasicValue(BasicValue::Int32(2))),
+        ])),
+    ];
+    let err = check_signature(&body, &sig).unwrap_err();
+    assert_eq!(err.path, vec![PathSegment::Arg(1), PathSegment::Value(1)]);
+    assert_eq!(err.to_string(),
+               "Expected a value of type `u`, found an int32 at argument 1, value of entry 1.");
+
+    assert!(check_signature(&body[..1], &sig).is_err());
+}
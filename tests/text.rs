@@ -0,0 +1,141 @@
+extern crate tokio_dbus;
+
+use std::collections::BTreeMap;
+use tokio_dbus::{BasicValue, ContainerValue, ObjectPath, Signature, ToDBus, Type, Value, Variant,
+                 parse_typed_value, parse_value, print_typed_value, print_value};
+
+fn variant(value: Value) -> Value {
+    value.into_variant().unwrap()
+}
+
+// Checks how `value` prints, and that it parses back to itself.
+fn check(value: Value, text: &str) {
+    assert_eq!(print_value(&value).unwrap(), text);
+    assert_eq!(parse_value(text).unwrap(), value);
+}
+
+#[test]
+fn test_basic_types() {
+    check(true.to_dbus(), "true");
+    check(7u8.to_dbus(), "byte 0x07");
+    check((-2i16).to_dbus(), "int16 -2");
+    check(65535u16.to_dbus(), "uint16 65535");
+    check((-3i32).to_dbus(), "-3");
+    check(3u32.to_dbus(), "uint32 3");
+    check(::std::i64::MIN.to_dbus(), "int64 -9223372036854775808");
+    check(::std::u64::MAX.to_dbus(), "uint64 18446744073709551615");
+    check(1.5f64.to_dbus(), "1.5");
+    check(2.0f64.to_dbus(), "2.0");
+    check(::std::f64::NEG_INFINITY.to_dbus(), "-inf");
+    check("it's \"quoted\"\n".to_dbus(), "'it\\'s \"quoted\"\\n'");
+    check("don't".to_dbus(), "\"don't\"");
+    check("\u{1}é".to_dbus(), "'\\u0001é'");
+    check(ObjectPath::new("/org/example").unwrap().to_dbus(),
+          "objectpath '/org/example'");
+    check("a{sv}".parse::<Signature>().unwrap().to_dbus(), "signature 'a{sv}'");
+    check(Value::BasicValue(BasicValue::UnixFd(2)), "handle 2");
+
+    match parse_value("nan").unwrap() {
+        Value::BasicValue(BasicValue::Double(n)) => assert!(n.is_nan()),
+        value => panic!("expected a double, found {:?}", value),
+    }
+}
+
+#[test]
+fn test_container_types() {
+    check(vec![1u32, 2, 3].to_dbus(), "[uint32 1, 2, 3]");
+    check(vec![vec![0u8, 255]].to_dbus(), "[[byte 0x00, 0xff]]");
+    check((1i32, "one", 1.0f64).to_dbus(), "(1, 'one', 1.0)");
+    check((7u16,).to_dbus(), "(uint16 7,)");
+    check(variant(variant(5i64.to_dbus())), "<<int64 5>>");
+    check(Variant::new(Vec::<String>::new()).to_dbus(), "<@as []>");
+
+    let mut map = BTreeMap::new();
+    map.insert(1u32, vec!["a".to_owned()]);
+    map.insert(2u32, vec![]);
+    check(map.to_dbus(), "{uint32 1: ['a'], 2: []}");
+
+    let props = Value::ContainerValue(ContainerValue::Dict(vec![
+        (BasicValue::String("Name".into()), variant("foo".to_dbus())),
+        (BasicValue::String("Count".into()), variant(3u32.to_dbus())),
+    ]));
+    check(props, "{'Name': <'foo'>, 'Count': <uint32 3>}");
+
+    // Empty containers need their types, and print with annotations.
+    let empty = Vec::<(String, u8)>::new().to_dbus();
+    let ty: Type = "a(sy)".parse().unwrap();
+    assert!(print_value(&empty).is_err());
+    assert_eq!(print_typed_value(&empty, &ty).unwrap(), "@a(sy) []");
+    assert_eq!(parse_value("@a(sy) []").unwrap(), empty);
+    let empty_dict = BTreeMap::<String, bool>::new().to_dbus();
+    assert_eq!(print_typed_value(&empty_dict, &"a{sb}".parse().unwrap()).unwrap(),
+               "@a{sb} {}");
+    assert_eq!(parse_value(" @a{sb}{ } ").unwrap(), empty_dict);
+}
+
+#[test]
+fn test_typed_parsing() {
+    let ty: Type = "a{sq}".parse().unwrap();
+    let mut map = BTreeMap::new();
+    map.insert("x".to_owned(), 3u16);
+    assert_eq!(parse_typed_value("{'x': 3}", &ty).unwrap(), map.to_dbus());
+
+    assert_eq!(parse_typed_value("0x10", &"y".parse().unwrap()).unwrap(),
+               16u8.to_dbus());
+    assert_eq!(parse_typed_value("2", &"d".parse().unwrap()).unwrap(),
+               2.0f64.to_dbus());
+    assert_eq!(parse_typed_value("'/a'", &"o".parse().unwrap()).unwrap(),
+               ObjectPath::new("/a").unwrap().to_dbus());
+    assert_eq!(parse_value("@(ux) (1, 2)").unwrap(), (1u32, 2i64).to_dbus());
+    assert_eq!(parse_value("[int64 1, 2e0]").unwrap_err().offset, 10);
+}
+
+#[test]
+fn test_errors() {
+    let err = parse_value("[]").unwrap_err();
+    assert_eq!(err.offset, 0);
+    assert_eq!(err.to_string(),
+               "Invalid GVariant text at offset 0: can't infer the type of an empty array; \
+                annotate it, as in `@as []`.");
+
+    let err = parse_value("{'a': 1, 'b': 'two'}").unwrap_err();
+    assert_eq!(err.offset, 14);
+    assert_eq!(err.reason, "expected a value of type `i`, found a string");
+
+    assert_eq!(parse_value("byte 256").unwrap_err().offset, 5);
+    assert_eq!(parse_value("objectpath 'x'").unwrap_err().offset, 11);
+    assert_eq!(parse_value("(1, 2").unwrap_err().offset, 5);
+    assert_eq!(parse_value("'abc").unwrap_err().offset, 0);
+    assert_eq!(parse_value("1 2").unwrap_err().offset, 2);
+    assert_eq!(parse_value("@az []").unwrap_err().offset, 1);
+    assert_eq!(parse_value("()").unwrap_err().offset, 0);
+    assert_eq!(parse_value("{[1]: 2}").unwrap_err().offset, 1);
+    assert_eq!(parse_typed_value("(1, 2)", &"(i)".parse().unwrap()).unwrap_err().offset, 4);
+}
+
+#[test]
+fn test_stacked_annotations() {
+    assert_eq!(parse_value("int32 @i 1").unwrap(), parse_value("1").unwrap());
+
+    let text = "int32 ".repeat(20000) + "1";
+    assert_eq!(parse_value(&text).unwrap_err().reason, "values are nested too deeply");
+    let text = "@i ".repeat(20000) + "1";
+    assert_eq!(parse_value(&text).unwrap_err().reason, "values are nested too deeply");
+}
+
+#[cfg(feature = "maybe")]
+#[test]
+fn test_maybe() {
+    use tokio_dbus::validate_gvariant_type;
+
+    check(Some(1u32).to_dbus(), "just uint32 1");
+    check(vec![Some("a"), None].to_dbus(), "[just 'a', nothing]");
+    let nested = Some(None::<bool>).to_dbus();
+    let ty = |s: &str| validate_gvariant_type(s.as_bytes()).unwrap();
+    assert_eq!(print_typed_value(&nested, &ty("mmb")).unwrap(),
+               "just @mb nothing");
+    assert_eq!(parse_value("just @mb nothing").unwrap(), nested);
+    assert!(parse_value("nothing").is_err());
+    assert_eq!(parse_typed_value("nothing", &ty("mi")).unwrap(),
+               None::<i32>.to_dbus());
+}
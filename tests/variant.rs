@@ -0,0 +1,98 @@
+extern crate tokio_dbus;
+
+use tokio_dbus::{BasicType, BasicValue, ContainerValue, DBusType, Endianness, FromDBus,
+                 ObjectPath, PathSegment, PropertyMap, ToDBus, Type, TypeErrorKind, Value,
+                 Variant, MAX_ARRAY_LEN, decode_values, encode_values};
+
+fn properties() -> PropertyMap {
+    let mut props = PropertyMap::new();
+    props.insert("Name", "eth0");
+    props.insert("Mtu", 1500u32);
+    props.insert("Path", ObjectPath::new("/dev/0").unwrap());
+    props.insert("Addresses", vec!["10.0.0.1".to_owned()]);
+    props
+}
+
+#[test]
+fn test_variant() {
+    assert_eq!(Variant::signature().to_string(), "v");
+
+    let variant = Variant::new(7u16);
+    assert_eq!(variant.get::<u16>().unwrap(), 7);
+    assert_eq!(variant.to_dbus(),
+               Value::ContainerValue(ContainerValue::Variant(
+                   Type::BasicType(BasicType::UInt16),
+                   Box::new(Value::BasicValue(BasicValue::UInt16(7))))));
+    assert_eq!(Variant::from_dbus(variant.to_dbus()).unwrap(), variant);
+
+    let err = variant.get::<String>().unwrap_err();
+    assert_eq!(err.to_string(),
+               "Expected a value of type `s`, found a uint16 at variant contents.");
+    assert!(Variant::from_dbus(7u16.to_dbus()).is_err());
+
+    let nested = Variant::new(vec![Variant::new(true), Variant::new("x")]);
+    assert_eq!(nested.get::<Vec<Variant>>().unwrap()[1].get::<String>().unwrap(), "x");
+}
+
+#[test]
+fn test_property_map() {
+    assert_eq!(PropertyMap::signature().to_string(), "a{sv}");
+
+    let props = properties();
+    assert_eq!(props.len(), 4);
+    assert_eq!(props.get::<String>("Name").unwrap(), "eth0");
+    assert_eq!(props.get::<u32>("Mtu").unwrap(), 1500);
+    assert_eq!(props.get::<ObjectPath>("Path").unwrap().as_str(), "/dev/0");
+    assert_eq!(props.get_opt::<bool>("Up").unwrap(), None);
+
+    let value = props.to_dbus();
+    value.check_type(&PropertyMap::dbus_type()).unwrap();
+    assert_eq!(PropertyMap::from_dbus(value).unwrap(), props);
+
+    let names = props.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["Addresses", "Mtu", "Name", "Path"]);
+
+    // An empty array keeps the type it was inserted with.
+    let mut props = PropertyMap::new();
+    props.insert("Items", Vec::<String>::new());
+    let signature = PropertyMap::signature();
+    let mut buf = Vec::new();
+    encode_values(&[props.to_dbus()], &signature, Endianness::Little, &mut buf).unwrap();
+    let values = decode_values(&buf, &signature, Endianness::Little, MAX_ARRAY_LEN).unwrap();
+    let decoded = PropertyMap::from_dbus(values.into_iter().next().unwrap()).unwrap();
+    assert_eq!(decoded.get_variant("Items").unwrap().ty().to_string(), "as");
+    assert_eq!(decoded, props);
+}
+
+#[test]
+fn test_property_errors() {
+    let props = properties();
+
+    let err = props.get::<bool>("Up").unwrap_err();
+    assert_eq!(err.kind, TypeErrorKind::Invalid("Missing property `Up`".to_owned()));
+    assert_eq!(err.to_string(), "Missing property `Up`.");
+
+    // Errors point at the entry's place in the marshalled, sorted dict.
+    let err = props.get::<i32>("Mtu").unwrap_err();
+    assert_eq!(err.path, vec![PathSegment::Value(1), PathSegment::Variant]);
+    assert_eq!(err.to_string(),
+               "Expected a value of type `i`, found a uint32 at value of entry 1, variant \
+                contents.");
+
+    let err = props.get::<Vec<u8>>("Addresses").unwrap_err();
+    assert_eq!(err.path,
+               vec![PathSegment::Value(0), PathSegment::Variant, PathSegment::Element(0)]);
+
+    let value = Value::ContainerValue(ContainerValue::Dict(vec![
+        (BasicValue::String("Mtu".into()), 1500u32.to_dbus()),
+    ]));
+    let err = PropertyMap::from_dbus(value).unwrap_err();
+    assert_eq!(err.path, vec![PathSegment::Value(0)]);
+
+    let value = Value::ContainerValue(ContainerValue::Dict(vec![
+        (BasicValue::String("Mtu".into()), Variant::new(1500u32).to_dbus()),
+        (BasicValue::String("Mtu".into()), Variant::new(9000u32).to_dbus()),
+    ]));
+    let err = PropertyMap::from_dbus(value).unwrap_err();
+    assert_eq!(err.to_string(), "Duplicate property `Mtu` at key of entry 1.");
+}
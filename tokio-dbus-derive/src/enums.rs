@@ -0,0 +1,379 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use proc_macro2::{Ident, Literal, Span, TokenStream as TokenStream2};
+use syn::{DataEnum, DeriveInput, Error, Expr, Fields, Lit, Member, Meta, NestedMeta, Result,
+          UnOp};
+
+use {dbus_attrs, impl_block};
+
+pub struct Input<'a> {
+    ast: &'a DeriveInput,
+    repr: Repr,
+    // Whether any variant carries data, making the enum a tagged struct.
+    tagged: bool,
+    variants: Vec<Variant<'a>>,
+}
+
+// The basic type an enum's discriminants are marshalled as.
+struct Repr {
+    basic_type: Ident,
+    rust_type: TokenStream2,
+    string: bool,
+}
+
+struct Variant<'a> {
+    ident: &'a Ident,
+    fields: &'a Fields,
+    tag: Tag,
+}
+
+enum Tag {
+    Int(i128),
+    Str(String),
+}
+
+impl Repr {
+    fn from_code(code: &str) -> Option<Self> {
+        let (basic_type, rust_type) = match code {
+            "y" => ("Byte", quote!(u8)),
+            "n" => ("Int16", quote!(i16)),
+            "q" => ("UInt16", quote!(u16)),
+            "i" => ("Int32", quote!(i32)),
+            "u" => ("UInt32", quote!(u32)),
+            "x" => ("Int64", quote!(i64)),
+            "t" => ("UInt64", quote!(u64)),
+            "s" => ("String", quote!(::std::string::String)),
+            _ => return None,
+        };
+        Some(Repr {
+            basic_type: Ident::new(basic_type, Span::call_site()),
+            rust_type: rust_type,
+            string: code == "s",
+        })
+    }
+}
+
+fn int_discriminant(expr: &Expr) -> Option<i128> {
+    match *expr {
+        Expr::Lit(ref lit) => {
+            match lit.lit {
+                Lit::Int(ref n) => n.base10_parse().ok(),
+                _ => None,
+            }
+        }
+        Expr::Unary(ref unary) => {
+            match unary.op {
+                UnOp::Neg(_) => int_discriminant(&unary.expr).map(|n| -n),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+impl<'a> Input<'a> {
+    pub fn from_ast(ast: &'a DeriveInput, data: &'a DataEnum) -> Result<Self> {
+        let tagged = data.variants.iter().any(|variant| !is_unit(&variant.fields));
+        let mut repr = None;
+        for meta in dbus_attrs(&ast.attrs)? {
+            match meta {
+                NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("repr") => {
+                    repr = match nv.lit {
+                        Lit::Str(ref code) => Repr::from_code(&code.value()),
+                        _ => None,
+                    };
+                    if repr.is_none() {
+                        return Err(Error::new_spanned(&nv.lit,
+                                                      "expected the type code of a D-Bus \
+                                                       integer type or of a string"));
+                    }
+                }
+                meta => return Err(Error::new_spanned(meta, "unknown `dbus` attribute")),
+            }
+        }
+        let default = if tagged { "s" } else { "u" };
+        let repr = repr.unwrap_or_else(|| Repr::from_code(default).unwrap());
+
+        let mut variants = Vec::new();
+        let mut next = 0i128;
+        for variant in &data.variants {
+            if !is_unit(&variant.fields) && variant.fields.is_empty() {
+                return Err(Error::new_spanned(&variant.fields,
+                                              "D-Bus structs must have at least one field; \
+                                               use a unit variant instead"));
+            }
+            let mut tag = None;
+            for meta in dbus_attrs(&variant.attrs)? {
+                match meta {
+                    NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("value") => {
+                        tag = match nv.lit {
+                            Lit::Str(ref s) if repr.string => Some(Tag::Str(s.value())),
+                            Lit::Int(ref n) if !repr.string => {
+                                Some(Tag::Int(n.base10_parse()?))
+                            }
+                            ref lit => {
+                                return Err(Error::new_spanned(lit,
+                                                              "the value doesn't match the \
+                                                               enum's `repr`"))
+                            }
+                        };
+                    }
+                    meta => return Err(Error::new_spanned(meta, "unknown `dbus` attribute")),
+                }
+            }
+            let tag = match tag {
+                Some(tag) => tag,
+                None if repr.string => Tag::Str(variant.ident.to_string()),
+                None => {
+                    if let Some((_, ref expr)) = variant.discriminant {
+                        next = int_discriminant(expr).ok_or_else(|| {
+                                Error::new_spanned(expr,
+                                                   "use `#[dbus(value = ...)]` for \
+                                                    discriminants that aren't literals")
+                            })?;
+                    }
+                    Tag::Int(next)
+                }
+            };
+            if let Tag::Int(n) = tag {
+                next = n + 1;
+            }
+            variants.push(Variant {
+                ident: &variant.ident,
+                fields: &variant.fields,
+                tag: tag,
+            });
+        }
+        if variants.is_empty() {
+            return Err(Error::new_spanned(&ast.ident,
+                                          "D-Bus derives need an enum with at least one \
+                                           variant"));
+        }
+
+        Ok(Input {
+            ast: ast,
+            repr: repr,
+            tagged: tagged,
+            variants: variants,
+        })
+    }
+}
+
+impl Tag {
+    // The tag as a literal pattern.
+    fn pattern(&self) -> TokenStream2 {
+        match *self {
+            Tag::Int(n) if n < 0 => {
+                let abs = Literal::u128_unsuffixed(n.wrapping_neg() as u128);
+                quote!(-#abs)
+            }
+            Tag::Int(n) => {
+                let n = Literal::u128_unsuffixed(n as u128);
+                quote!(#n)
+            }
+            Tag::Str(ref s) => quote!(#s),
+        }
+    }
+
+    fn to_dbus(&self, repr: &Repr) -> TokenStream2 {
+        let pattern = self.pattern();
+        match *self {
+            Tag::Int(_) => {
+                let rust_type = &repr.rust_type;
+                quote!(<#rust_type as ::tokio_dbus::ToDBus>::to_dbus(&#pattern))
+            }
+            Tag::Str(_) => quote!(<str as ::tokio_dbus::ToDBus>::to_dbus(#pattern)),
+        }
+    }
+}
+
+// Names to bind a variant's fields to.
+fn bindings(fields: &Fields) -> Vec<Ident> {
+    fields.iter()
+        .enumerate()
+        .map(|(i, field)| match field.ident {
+            Some(ref ident) => ident.clone(),
+            None => Ident::new(&format!("__field{}", i), Span::call_site()),
+        })
+        .collect()
+}
+
+fn members(fields: &Fields) -> Vec<Member> {
+    fields.iter()
+        .enumerate()
+        .map(|(i, field)| match field.ident {
+            Some(ref ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(i.into()),
+        })
+        .collect()
+}
+
+fn is_unit(fields: &Fields) -> bool {
+    matches!(*fields, Fields::Unit)
+}
+
+// Whether a variant's contents are its single unnamed field itself, rather
+// than a struct of its fields.
+fn is_newtype(fields: &Fields) -> bool {
+    match *fields {
+        Fields::Unnamed(ref fields) => fields.unnamed.len() == 1,
+        _ => false,
+    }
+}
+
+pub fn expand_dbus_type(input: &Input) -> TokenStream2 {
+    let basic_type = &input.repr.basic_type;
+    let rust_type = &input.repr.rust_type;
+    if input.tagged {
+        return impl_block(input.ast,
+                          quote!(::tokio_dbus::DBusType),
+                          quote! {
+                              const SIGNATURE: ::tokio_dbus::ConstSignature =
+                                  ::tokio_dbus::ConstSignature::structure(&[
+                                      <#rust_type as ::tokio_dbus::DBusType>::SIGNATURE,
+                                      ::tokio_dbus::ConstSignature::new("v"),
+                                  ]);
+                          });
+    }
+
+    // C-like enums are basic, so they can be dict keys.
+    let dbus_type = impl_block(input.ast,
+                               quote!(::tokio_dbus::DBusType),
+                               quote! {
+                                   const SIGNATURE: ::tokio_dbus::ConstSignature =
+                                       <#rust_type as ::tokio_dbus::DBusType>::SIGNATURE;
+                               });
+    let basic_dbus_type = impl_block(input.ast,
+                                     quote!(::tokio_dbus::BasicDBusType),
+                                     quote! {
+                                         fn basic_type() -> ::tokio_dbus::BasicType {
+                                             ::tokio_dbus::BasicType::#basic_type
+                                         }
+                                     });
+    quote!(#dbus_type #basic_dbus_type)
+}
+
+pub fn expand_to_dbus(input: &Input) -> TokenStream2 {
+    let name = &input.ast.ident;
+    let arms = input.variants.iter().map(|variant| {
+        let ident = variant.ident;
+        let tag = variant.tag.to_dbus(&input.repr);
+        if !input.tagged {
+            return quote!(#name::#ident => #tag);
+        }
+
+        let bindings = bindings(variant.fields);
+        let members = members(variant.fields);
+        let tys = variant.fields.iter().map(|field| &field.ty).collect::<Vec<_>>();
+        let contents = if is_unit(variant.fields) {
+            quote!(::tokio_dbus::bus::derive::unit_contents())
+        } else if is_newtype(variant.fields) {
+            let ty = tys[0];
+            quote! {
+                (<#ty as ::tokio_dbus::DBusType>::dbus_type(),
+                 ::tokio_dbus::ToDBus::to_dbus(__field0))
+            }
+        } else {
+            quote! {
+                (::tokio_dbus::Type::ContainerType(::std::boxed::Box::new(
+                     ::tokio_dbus::ContainerType::Struct(vec![
+                         #(<#tys as ::tokio_dbus::DBusType>::dbus_type()),*
+                     ]))),
+                 ::tokio_dbus::Value::ContainerValue(::tokio_dbus::ContainerValue::Struct(vec![
+                     #(::tokio_dbus::ToDBus::to_dbus(#bindings)),*
+                 ])))
+            }
+        };
+        quote! {
+            #name::#ident { #(#members: ref #bindings),* } => {
+                let (contents_ty, contents) = #contents;
+                ::tokio_dbus::bus::derive::tagged_value(#tag, contents_ty, contents)
+            }
+        }
+    });
+    impl_block(input.ast,
+               quote!(::tokio_dbus::ToDBus),
+               quote! {
+                   fn to_dbus(&self) -> ::tokio_dbus::Value {
+                       match *self {
+                           #(#arms,)*
+                       }
+                   }
+               })
+}
+
+pub fn expand_from_dbus(input: &Input) -> TokenStream2 {
+    let name = &input.ast.ident;
+    let name_str = name.to_string();
+    let rust_type = &input.repr.rust_type;
+    let scrutinee = if input.repr.string {
+        quote!(&*tag)
+    } else {
+        quote!(tag)
+    };
+
+    let arms = input.variants.iter().map(|variant| {
+        let ident = variant.ident;
+        let pattern = variant.tag.pattern();
+        if is_unit(variant.fields) {
+            return quote!(#pattern => ::std::result::Result::Ok(#name::#ident));
+        }
+
+        let value = if is_newtype(variant.fields) {
+            quote!(#name::#ident(::tokio_dbus::FromDBus::from_dbus(contents)?))
+        } else {
+            let members = members(variant.fields);
+            let tys = variant.fields.iter().map(|field| &field.ty);
+            let indices = 0..members.len();
+            let len = members.len();
+            quote!({
+                let mut fields = ::tokio_dbus::bus::derive::struct_fields_of(
+                    contents,
+                    ::tokio_dbus::Type::ContainerType(::std::boxed::Box::new(
+                        ::tokio_dbus::ContainerType::Struct(vec![
+                            #(<#tys as ::tokio_dbus::DBusType>::dbus_type()),*
+                        ]))),
+                    #len)?;
+                #name::#ident {
+                    #(#members: ::tokio_dbus::bus::derive::field(&mut fields, #indices)?),*
+                }
+            })
+        };
+        quote! {
+            #pattern => {
+                (|| -> ::std::result::Result<Self, ::tokio_dbus::TypeError> {
+                    ::std::result::Result::Ok(#value)
+                })().map_err(::tokio_dbus::bus::derive::in_contents)
+            }
+        }
+    });
+
+    let body = if input.tagged {
+        quote! {
+            let (tag, contents) = ::tokio_dbus::bus::derive::tagged::<Self>(value)?;
+            let tag = <#rust_type as ::tokio_dbus::FromDBus>::from_dbus(tag)
+                .map_err(::tokio_dbus::bus::derive::in_tag)?;
+        }
+    } else {
+        quote! {
+            let tag = <#rust_type as ::tokio_dbus::FromDBus>::from_dbus(value)?;
+        }
+    };
+    impl_block(input.ast,
+               quote!(::tokio_dbus::FromDBus),
+               quote! {
+                   fn from_dbus(value: ::tokio_dbus::Value)
+                                -> ::std::result::Result<Self, ::tokio_dbus::TypeError> {
+                       #body
+                       match #scrutinee {
+                           #(#arms,)*
+                           _ => {
+                               ::std::result::Result::Err(
+                                   ::tokio_dbus::bus::derive::unknown_variant(#name_str, &tag))
+                           }
+                       }
+                   }
+               })
+}
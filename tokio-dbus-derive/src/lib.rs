@@ -0,0 +1,122 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+// Like tokio-dbus itself, this crate spells out `field: field` in struct
+// initializers.
+#![allow(clippy::redundant_field_names)]
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+mod enums;
+mod structs;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{Attribute, Data, DeriveInput, Error, Generics, Meta, NestedMeta, Result};
+
+/// Derives `DBusType`.
+///
+/// A struct is typed as a D-Bus struct of its fields, or with
+/// `#[dbus(dict)]`, as an `a{sv}` dict keyed by field name.
+///
+/// An enum whose variants carry no data is typed as an integer or a string,
+/// chosen with `#[dbus(repr = "s")]` and defaulting to `u`. Other enums are
+/// typed as a struct of a tag and a variant holding the contents, `(sv)` by
+/// default or `(uv)` with `#[dbus(repr = "u")]`. A variant with several
+/// fields holds a struct of them, and a unit variant holds an empty string.
+///
+/// Integer tags follow the Rust discriminants and string tags are the
+/// variant names, unless a variant sets its own with `#[dbus(value = ...)]`.
+#[proc_macro_derive(DBusType, attributes(dbus))]
+pub fn derive_dbus_type(input: TokenStream) -> TokenStream {
+    expand(input, |input| {
+        match *input {
+            Input::Struct(ref input) => structs::expand_dbus_type(input),
+            Input::Enum(ref input) => enums::expand_dbus_type(input),
+        }
+    })
+}
+
+/// Derives `ToDBus`, marshalling the type as `DBusType` describes.
+#[proc_macro_derive(ToDBus, attributes(dbus))]
+pub fn derive_to_dbus(input: TokenStream) -> TokenStream {
+    expand(input, |input| {
+        match *input {
+            Input::Struct(ref input) => structs::expand_to_dbus(input),
+            Input::Enum(ref input) => enums::expand_to_dbus(input),
+        }
+    })
+}
+
+/// Derives `FromDBus`, unmarshalling the type as `DBusType` describes.
+#[proc_macro_derive(FromDBus, attributes(dbus))]
+pub fn derive_from_dbus(input: TokenStream) -> TokenStream {
+    expand(input, |input| {
+        match *input {
+            Input::Struct(ref input) => structs::expand_from_dbus(input),
+            Input::Enum(ref input) => enums::expand_from_dbus(input),
+        }
+    })
+}
+
+enum Input<'a> {
+    Struct(structs::Input<'a>),
+    Enum(enums::Input<'a>),
+}
+
+fn expand(input: TokenStream, f: fn(&Input) -> TokenStream2) -> TokenStream {
+    let result = syn::parse::<DeriveInput>(input).and_then(|ast| {
+        let input = match ast.data {
+            Data::Struct(ref data) => Input::Struct(structs::Input::from_ast(&ast, data)?),
+            Data::Enum(ref data) => Input::Enum(enums::Input::from_ast(&ast, data)?),
+            Data::Union(_) => {
+                return Err(Error::new_spanned(&ast.ident, "D-Bus derives don't support unions"))
+            }
+        };
+        Ok(f(&input))
+    });
+    match result {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+// The contents of every `#[dbus(...)]` attribute in `attrs`.
+fn dbus_attrs(attrs: &[Attribute]) -> Result<Vec<NestedMeta>> {
+    let mut metas = Vec::new();
+    for attr in attrs {
+        if !attr.path.is_ident("dbus") {
+            continue;
+        }
+        match attr.parse_meta()? {
+            Meta::List(list) => metas.extend(list.nested),
+            meta => return Err(Error::new_spanned(meta, "expected `#[dbus(...)]`")),
+        }
+    }
+    Ok(metas)
+}
+
+// Emits `impl #trait_ for #name`, bounding every type parameter by `trait_`
+// too.
+fn impl_block(ast: &DeriveInput, trait_: TokenStream2, body: TokenStream2) -> TokenStream2 {
+    let mut generics: Generics = ast.generics.clone();
+    let params = generics.type_params().map(|param| param.ident.clone()).collect::<Vec<_>>();
+    {
+        let where_clause = generics.make_where_clause();
+        for param in params {
+            where_clause.predicates.push(syn::parse_quote!(#param: #trait_));
+        }
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let name = &ast.ident;
+    quote! {
+        impl #impl_generics #trait_ for #name #ty_generics #where_clause {
+            #body
+        }
+    }
+}
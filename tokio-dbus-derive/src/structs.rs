@@ -0,0 +1,202 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{DataStruct, DeriveInput, Error, Fields, Lit, Member, Meta, NestedMeta, Result};
+
+use {dbus_attrs, impl_block};
+
+pub struct Input<'a> {
+    ast: &'a DeriveInput,
+    // Whether the struct is marshalled as an `a{sv}` dict.
+    dict: bool,
+    fields: Vec<Field<'a>>,
+}
+
+struct Field<'a> {
+    member: Member,
+    ty: &'a syn::Type,
+    // The dict key.
+    name: String,
+    skip: bool,
+    skip_if_none: bool,
+}
+
+impl<'a> Input<'a> {
+    pub fn from_ast(ast: &'a DeriveInput, data: &'a DataStruct) -> Result<Self> {
+        let mut dict = false;
+        for meta in dbus_attrs(&ast.attrs)? {
+            match meta {
+                NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("dict") => dict = true,
+                meta => return Err(Error::new_spanned(meta, "unknown `dbus` attribute")),
+            }
+        }
+
+        if dict {
+            if let Fields::Named(_) = data.fields {
+            } else {
+                return Err(Error::new_spanned(&ast.ident,
+                                              "`#[dbus(dict)]` needs a struct with named fields"));
+            }
+        }
+
+        let mut fields = Vec::new();
+        for (i, field) in data.fields.iter().enumerate() {
+            let member = match field.ident {
+                Some(ref ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(i.into()),
+            };
+            let mut parsed = Field {
+                member: member,
+                ty: &field.ty,
+                name: field.ident.as_ref().map_or(String::new(), ToString::to_string),
+                skip: false,
+                skip_if_none: false,
+            };
+            for meta in dbus_attrs(&field.attrs)? {
+                match meta {
+                    NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("skip") => {
+                        parsed.skip = true;
+                    }
+                    NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("skip_if_none") &&
+                                                                dict => {
+                        parsed.skip_if_none = true;
+                    }
+                    NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("rename") &&
+                                                                 dict => {
+                        match nv.lit {
+                            Lit::Str(ref name) => parsed.name = name.value(),
+                            ref lit => return Err(Error::new_spanned(lit, "expected a string")),
+                        }
+                    }
+                    meta => {
+                        return Err(Error::new_spanned(meta,
+                                                      "unknown `dbus` attribute; `rename` and \
+                                                       `skip_if_none` apply only to dict \
+                                                       structs"))
+                    }
+                }
+            }
+            fields.push(parsed);
+        }
+
+        if !dict && fields.iter().all(|field| field.skip) {
+            return Err(Error::new_spanned(&ast.ident,
+                                          "D-Bus structs must have at least one field"));
+        }
+
+        Ok(Input {
+            ast: ast,
+            dict: dict,
+            fields: fields,
+        })
+    }
+
+    fn marshalled_fields(&self) -> Vec<&Field<'a>> {
+        self.fields.iter().filter(|field| !field.skip).collect()
+    }
+}
+
+pub fn expand_dbus_type(input: &Input) -> TokenStream2 {
+    let signature = if input.dict {
+        quote!(::tokio_dbus::ConstSignature::new("a{sv}"))
+    } else {
+        let tys = input.marshalled_fields().into_iter().map(|field| field.ty).collect::<Vec<_>>();
+        quote! {
+            ::tokio_dbus::ConstSignature::structure(&[
+                #(<#tys as ::tokio_dbus::DBusType>::SIGNATURE),*
+            ])
+        }
+    };
+    impl_block(input.ast,
+               quote!(::tokio_dbus::DBusType),
+               quote! {
+                   const SIGNATURE: ::tokio_dbus::ConstSignature = #signature;
+               })
+}
+
+pub fn expand_to_dbus(input: &Input) -> TokenStream2 {
+    let value = if input.dict {
+        let entries = input.marshalled_fields().into_iter().map(|field| {
+            let member = &field.member;
+            let name = &field.name;
+            if field.skip_if_none {
+                quote! {
+                    if let ::std::option::Option::Some(ref value) = self.#member {
+                        entries.push(::tokio_dbus::bus::derive::property(#name, value));
+                    }
+                }
+            } else {
+                quote! {
+                    entries.push(::tokio_dbus::bus::derive::property(#name, &self.#member));
+                }
+            }
+        });
+        quote! {
+            let mut entries = ::std::vec::Vec::new();
+            #(#entries)*
+            ::tokio_dbus::Value::ContainerValue(::tokio_dbus::ContainerValue::Dict(entries))
+        }
+    } else {
+        let members = input.marshalled_fields().into_iter().map(|field| &field.member);
+        quote! {
+            ::tokio_dbus::Value::ContainerValue(::tokio_dbus::ContainerValue::Struct(vec![
+                #(::tokio_dbus::ToDBus::to_dbus(&self.#members)),*
+            ]))
+        }
+    };
+    impl_block(input.ast,
+               quote!(::tokio_dbus::ToDBus),
+               quote! {
+                   fn to_dbus(&self) -> ::tokio_dbus::Value {
+                       #value
+                   }
+               })
+}
+
+pub fn expand_from_dbus(input: &Input) -> TokenStream2 {
+    let name = &input.ast.ident;
+    let body = if input.dict {
+        let inits = input.fields.iter().map(|field| {
+            let member = &field.member;
+            let key = &field.name;
+            if field.skip {
+                quote!(#member: ::std::default::Default::default())
+            } else if field.skip_if_none {
+                quote!(#member: props.take(#key)?)
+            } else {
+                quote!(#member: props.require(#key)?)
+            }
+        });
+        quote! {
+            let mut props = ::tokio_dbus::bus::derive::properties::<Self>(value)?;
+            ::std::result::Result::Ok(#name { #(#inits),* })
+        }
+    } else {
+        let len = input.marshalled_fields().len();
+        let mut index = 0usize;
+        let inits = input.fields.iter().map(|field| {
+            let member = &field.member;
+            if field.skip {
+                quote!(#member: ::std::default::Default::default())
+            } else {
+                index += 1;
+                let i = index - 1;
+                quote!(#member: ::tokio_dbus::bus::derive::field(&mut fields, #i)?)
+            }
+        }).collect::<Vec<_>>();
+        quote! {
+            let mut fields = ::tokio_dbus::bus::derive::struct_fields::<Self>(value, #len)?;
+            ::std::result::Result::Ok(#name { #(#inits),* })
+        }
+    };
+    impl_block(input.ast,
+               quote!(::tokio_dbus::FromDBus),
+               quote! {
+                   fn from_dbus(value: ::tokio_dbus::Value)
+                                -> ::std::result::Result<Self, ::tokio_dbus::TypeError> {
+                       #body
+                   }
+               })
+}